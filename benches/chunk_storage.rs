@@ -0,0 +1,221 @@
+//! Compares the palette-compressed chunk storage with the hash map it replaced,
+//! by memory use and by the time taken to serve a chunk to a client.
+//!
+//! Serving a chunk includes the negated copies of the blocks on the borders of its
+//! eight neighbors, which hold the same blocks as the chunk itself. The dense
+//! storage is served through `World::blocks_in_chunk`, as the server does, from a
+//! world in a temporary directory.
+//!
+//! Run with `cargo bench --bench chunk_storage`.
+
+extern crate craft_server;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::hint::black_box;
+use std::mem;
+use std::process;
+use std::time::{Duration, Instant};
+use craft_server::config::DatabaseConfig;
+use craft_server::world::{Block, World, CHUNK_SIZE};
+use craft_server::world::storage::Column;
+
+/// How many times each chunk is served when timing.
+const ROUNDS: u32 = 200;
+
+/// The chunk that is served. Its neighbors hold the same blocks.
+const SERVED: (i32, i32) = (0, 0);
+
+/// The storage that chunks used before: one hash map entry per block.
+type HashChunk = HashMap<(u8, u8, u8), Block>;
+
+fn main() {
+    let dir = env::temp_dir().join(format!("craft_chunk_storage_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    env::set_current_dir(&dir).unwrap();
+
+    println!("{:<10} {:>8} {:>12} {:>12} {:>12} {:>12}",
+             "chunk", "blocks", "map bytes", "dense bytes", "map serve", "dense serve");
+
+    run("sparse", &sparse());
+    run("building", &building());
+    run("terrain", &terrain());
+    run("mined", &mined());
+
+    env::set_current_dir(env::temp_dir()).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn run(name: &str, blocks: &[((u8, u8, u8), i8)]) {
+    let mut maps = HashMap::new();
+    let mut column = Column::new();
+    let mut world = World::new(&DatabaseConfig::default()).unwrap();
+
+    for &((x, y, z), w) in blocks {
+        // Mined blocks are kept, so that Craft doesn't generate terrain there again.
+        column.set(x, y as i32, z, Some(w));
+    }
+
+    world.begin_batch();
+
+    for pq in chunk_and_neighbors() {
+        let map: &mut HashChunk = maps.entry(pq).or_default();
+
+        for &((x, y, z), w) in blocks {
+            map.insert((x, y, z), Block(w));
+
+            let global = (pq.0 * CHUNK_SIZE as i32 + x as i32, y as i32, pq.1 * CHUNK_SIZE as i32 + z as i32);
+            world.set_block(global, Block(w), None).unwrap();
+        }
+    }
+
+    world.end_batch().unwrap();
+
+    let map_time = time(|out| {
+        for (&(x, y, z), w) in map_blocks_with_borders(&maps) {
+            serve(out, (x, y, z), w.0);
+        }
+    });
+
+    let dense_time = time(|out| {
+        for ((x, y, z), w) in world.blocks_in_chunk(SERVED).unwrap().into_iter().flatten() {
+            serve(out, (x, y, z), w.0);
+        }
+    });
+
+    println!("{:<10} {:>8} {:>12} {:>12} {:>10}us {:>10}us",
+             name,
+             maps[&SERVED].len(),
+             map_bytes(&maps[&SERVED]),
+             column.heap_size(),
+             map_time.as_micros(),
+             dense_time.as_micros());
+
+    world.close().unwrap();
+    fs::remove_file("world.db").unwrap();
+}
+
+/// Collects a chunk's blocks and the negated copies of its neighbors' border
+/// blocks, as the server did when chunks were hash maps.
+fn map_blocks_with_borders(maps: &HashMap<(i32, i32), HashChunk>) -> Vec<(&(u8, u8, u8), Block)> {
+    const LAST: u8 = CHUNK_SIZE - 1;
+
+    let mut blocks: Vec<_> = maps[&SERVED].iter().map(|(xyz, w)| (xyz, w.clone())).collect();
+
+    for (pq, map) in maps {
+        let (dp, dq) = (pq.0 - SERVED.0, pq.1 - SERVED.1);
+
+        if dp == 0 && dq == 0 {
+            continue;
+        }
+
+        let touches = |d: i32, n: u8| match d {
+            -1 => n == LAST,
+            0 => true,
+            _ => n == 0,
+        };
+
+        blocks.extend(map.iter().filter(|&(&(x, _, z), _)| touches(dp, x) && touches(dq, z))
+                                .map(|(xyz, w)| (xyz, Block(-w.0))));
+    }
+
+    blocks
+}
+
+fn chunk_and_neighbors() -> Vec<(i32, i32)> {
+    (-1..2).flat_map(|dp| (-1..2).map(move |dq| (SERVED.0 + dp, SERVED.1 + dq))).collect()
+}
+
+/// Formats a block as the server sends it.
+fn serve(out: &mut String, (x, y, z): (u8, u8, u8), w: i8) {
+    writeln!(out, "B,0,0,{},{},{},{}", x, y, z, w).unwrap();
+}
+
+/// Returns the average time `f` takes to write out a chunk.
+fn time<F: FnMut(&mut String)>(mut f: F) -> Duration {
+    let mut out = String::new();
+    let start = Instant::now();
+
+    for _ in 0..ROUNDS {
+        out.clear();
+        f(&mut out);
+        black_box(&out);
+    }
+
+    start.elapsed() / ROUNDS
+}
+
+/// Estimates the heap use of a hash map: one entry and one control byte per bucket.
+fn map_bytes(map: &HashChunk) -> usize {
+    map.capacity() * (mem::size_of::<((u8, u8, u8), Block)>() + 1)
+}
+
+/// A few hundred blocks scattered by players.
+fn sparse() -> Vec<((u8, u8, u8), i8)> {
+    let mut seed = 12345u32;
+    let mut next = move |n: u32| {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) % n
+    };
+
+    (0..300).map(|_| ((next(32) as u8, next(128) as u8, next(32) as u8), next(24) as i8 + 1))
+            .collect()
+}
+
+/// A hollow building with brick walls, a plank floor and glass windows.
+fn building() -> Vec<((u8, u8, u8), i8)> {
+    let mut blocks = Vec::new();
+
+    for y in 10..40u8 {
+        for x in 2..30u8 {
+            for z in 2..30u8 {
+                let wall = x == 2 || x == 29 || z == 2 || z == 29;
+                let floor = y % 10 == 0;
+
+                if floor {
+                    blocks.push(((x, y, z), 8));
+                } else if wall {
+                    blocks.push(((x, y, z), if y % 10 == 5 { 10 } else { 4 }));
+                }
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Solid ground as Craft generates it: stone, then dirt, then grass.
+fn terrain() -> Vec<((u8, u8, u8), i8)> {
+    let mut blocks = Vec::new();
+
+    for x in 0..32u8 {
+        for z in 0..32u8 {
+            let top = 30 + (x / 4 + z / 8) % 6;
+
+            for y in 0..=top {
+                let w = if y == top { 1 } else if y + 3 > top { 7 } else { 3 };
+                blocks.push(((x, y, z), w));
+            }
+        }
+    }
+
+    blocks
+}
+
+/// A quarry that players dug into generated ground. The ground itself was never
+/// set, so only the mined blocks are stored, as air.
+fn mined() -> Vec<((u8, u8, u8), i8)> {
+    let mut blocks = Vec::new();
+
+    for x in 4..28u8 {
+        for z in 4..28u8 {
+            for y in 12..30u8 {
+                blocks.push(((x, y, z), 0));
+            }
+        }
+    }
+
+    blocks
+}
@@ -0,0 +1,279 @@
+//! An append-only record of what players, moderators and consoles do, for moderation.
+//!
+//! Every entry is one line of JSON in the audit file, such as:
+//!
+//! ```text
+//! {"time":1706745599,"action":"role","actor":"alice","ip":"10.0.0.5","target":"bob","reason":"moderator"}
+//! ```
+//!
+//! The audit file is kept apart from the world, so restoring a backup of the world
+//! doesn't lose any of it. Entries are never changed or removed by the server.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{self, Display};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use http::json_string;
+use world::{timestamp, unix_time};
+
+const FILE: &str = "audit.log";
+
+/// Who did something.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Actor {
+    /// The nickname of a player, or `console` or `server`.
+    pub name: String,
+
+    /// The address of a player.
+    pub ip: Option<IpAddr>,
+}
+
+impl Actor {
+    /// A player with a nickname and address.
+    pub fn player(nick: &str, ip: IpAddr) -> Actor {
+        Actor {
+            name: nick.to_string(),
+            ip: Some(ip),
+        }
+    }
+
+    /// A console, which has the rights of an admin.
+    pub fn console() -> Actor {
+        Actor {
+            name: "console".to_string(),
+            ip: None,
+        }
+    }
+
+    /// The server itself, such as when it kicks a player automatically.
+    pub fn server() -> Actor {
+        Actor {
+            name: "server".to_string(),
+            ip: None,
+        }
+    }
+}
+
+/// One thing that was done, as stored in the audit file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The Unix time it was done at.
+    pub time: u64,
+
+    /// What was done, like `join`, `nick` or `rollback`.
+    pub action: String,
+
+    /// Who did it.
+    pub actor: Actor,
+
+    /// Who or what it was done to, if anyone.
+    pub target: String,
+
+    /// Why it was done, or more about what was done.
+    pub reason: String,
+}
+
+impl Entry {
+    fn to_json(&self) -> String {
+        format!("{{\"time\":{},\"action\":{},\"actor\":{},\"ip\":{},\"target\":{},\"reason\":{}}}",
+                self.time,
+                json_string(&self.action),
+                json_string(&self.actor.name),
+                self.actor.ip.map_or("null".to_string(), |ip| json_string(&ip.to_string())),
+                json_string(&self.target),
+                json_string(&self.reason))
+    }
+
+    fn from_json(line: &str) -> Option<Entry> {
+        let fields = parse_object(line)?;
+        let text = |key: &str| match fields.get(key) {
+            Some(Some(v)) => Some(v.clone()),
+            _ => None,
+        };
+
+        Some(Entry {
+            time: text("time")?.parse().ok()?,
+            action: text("action")?,
+            actor: Actor {
+                name: text("actor")?,
+                ip: text("ip").and_then(|ip| ip.parse().ok()),
+            },
+            target: text("target").unwrap_or_default(),
+            reason: text("reason").unwrap_or_default(),
+        })
+    }
+
+    /// Returns true if a filter names the action, the actor, the actor's address or the target.
+    fn matches(&self, filter: &str) -> bool {
+        self.action == filter ||
+        self.actor.name == filter ||
+        self.actor.ip.is_some_and(|ip| ip.to_string() == filter) ||
+        self.target == filter
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} by {}", timestamp(self.time), self.action, self.actor.name)?;
+
+        if let Some(ip) = self.actor.ip {
+            write!(f, " ({})", ip)?;
+        }
+        if !self.target.is_empty() {
+            write!(f, " on {}", self.target)?;
+        }
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The audit file, shared by the threads that record to it.
+#[derive(Clone)]
+pub struct AuditLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Opens the audit file.
+    /// # Note
+    /// If the file doesn't exist, it will be created.
+    /// # Panics
+    /// This function will panic if it can't create or open the audit file.
+    pub fn new() -> AuditLog {
+        AuditLog {
+            file: Arc::new(Mutex::new(OpenOptions::new()
+                                              .append(true)
+                                              .create(true)
+                                              .open(FILE)
+                                              .unwrap())),
+        }
+    }
+
+    /// Appends an entry for something done now.
+    pub fn record(&self, action: &str, actor: &Actor, target: &str, reason: &str) {
+        let entry = Entry {
+            time: unix_time(),
+            action: action.to_string(),
+            actor: actor.clone(),
+            target: target.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let line = entry.to_json() + "\n";
+
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            error!("Can't write to {}: {}. Lost entry: {}", FILE, e, entry);
+        }
+    }
+
+    /// Returns the newest entries, oldest first, that match a filter if one is given.
+    /// A filter matches the action, the actor's nickname or address, or the target.
+    pub fn search(&self, filter: Option<&str>, limit: usize) -> io::Result<Vec<Entry>> {
+        let _writing = self.file.lock().unwrap();
+
+        let mut found = VecDeque::with_capacity(limit);
+
+        for line in BufReader::new(File::open(FILE)?).lines() {
+            let entry = match Entry::from_json(&line?) {
+                Some(e) => e,
+                None => continue,
+            };
+
+            if filter.is_some_and(|f| !entry.matches(f)) {
+                continue;
+            }
+
+            if found.len() == limit {
+                found.pop_front();
+            }
+            found.push_back(entry);
+        }
+
+        Ok(found.into())
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> AuditLog {
+        AuditLog::new()
+    }
+}
+
+/// Parses a JSON object whose values are strings, numbers or null, as written by
+/// `Entry::to_json`. Numbers are kept as text, and null is `None`.
+fn parse_object(s: &str) -> Option<BTreeMap<String, Option<String>>> {
+    let mut fields = BTreeMap::new();
+    let mut chars = s.trim().chars().peekable();
+
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    loop {
+        match chars.next()? {
+            '"' => {},
+            '}' if fields.is_empty() => return Some(fields),
+            _ => return None,
+        }
+
+        let key = parse_string(&mut chars)?;
+
+        if chars.next()? != ':' {
+            return None;
+        }
+
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            Some(parse_string(&mut chars)?)
+        } else {
+            let mut raw = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == '}' {
+                    break;
+                }
+                raw.push(c);
+                chars.next();
+            }
+
+            match raw.trim() {
+                "null" => None,
+                n => Some(n.to_string()),
+            }
+        };
+
+        fields.insert(key, value);
+
+        match chars.next()? {
+            ',' => {},
+            '}' => return Some(fields),
+            _ => return None,
+        }
+    }
+}
+
+/// Parses the rest of a JSON string, after its opening quote.
+fn parse_string<I: Iterator<Item = char>>(chars: &mut I) -> Option<String> {
+    let mut s = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    s.push(std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                },
+                'n' => s.push('\n'),
+                't' => s.push('\t'),
+                'r' => s.push('\r'),
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
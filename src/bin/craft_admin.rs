@@ -0,0 +1,125 @@
+//! `craft_admin` runs commands on a running craft_server through its remote admin listener.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+
+const USAGE: &str = "\
+Usage:
+    craft_admin [--port <port>] [command...]
+        Logs in to the server's remote admin on localhost, and runs a command.
+        Without a command, runs the commands typed on standard input.
+        The password is taken from CRAFT_ADMIN_PASSWORD, or asked for.";
+
+/// The port that the server listens on for remote admin, unless configured otherwise.
+const DEFAULT_PORT: u16 = 4081;
+
+fn main() {
+    let mut port = DEFAULT_PORT;
+    let mut command = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" if command.is_empty() => {
+                port = match args.next().and_then(|p| p.parse().ok()) {
+                    Some(port) => port,
+                    None => usage(),
+                };
+            },
+            "--help" if command.is_empty() => usage(),
+            _ => command.push(arg),
+        }
+    }
+
+    let password = match env::var("CRAFT_ADMIN_PASSWORD") {
+        Ok(p) => p,
+        Err(_) => ask_password(),
+    };
+
+    let mut stream = match TcpStream::connect((Ipv4Addr::LOCALHOST, port)) {
+        Ok(s) => s,
+        Err(e) => fail(&format!("Can't connect to the server on port {}: {}", port, e)),
+    };
+
+    let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+
+    let logged_in = writeln!(stream, "A,{}", password).is_ok() &&
+                    replies.next().and_then(Result::ok).as_deref() == Some("A,OK");
+    if !logged_in {
+        fail("Can't log in. Check the password and rcon settings in the server's config.txt.");
+    }
+
+    // Replies are printed as they arrive. Every finished command is counted, so
+    // that the program only ends once all of them are.
+    let (done_tx, done_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in replies {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if let Some(text) = line.strip_prefix("T,") {
+                println!("{}", text);
+            } else if line == "D" && done_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut sent = 0;
+    if command.is_empty() {
+        let stdin = io::stdin();
+
+        for line in stdin.lock().lines() {
+            let line = line.unwrap_or_default();
+
+            if !line.trim().is_empty() {
+                send(&mut stream, &line);
+                sent += 1;
+            }
+        }
+    } else {
+        send(&mut stream, &command.join(" "));
+        sent += 1;
+    }
+
+    for _ in 0..sent {
+        if done_rx.recv().is_err() {
+            // The server closed the connection, for example because it was stopped.
+            break;
+        }
+    }
+}
+
+fn send(stream: &mut TcpStream, command: &str) {
+    if let Err(e) = writeln!(stream, "C,{}", command) {
+        fail(&format!("Can't send the command: {}", e));
+    }
+}
+
+fn ask_password() -> String {
+    eprint!("Password: ");
+    let _ = io::stderr().flush();
+
+    let mut password = String::new();
+    if io::stdin().read_line(&mut password).is_err() {
+        fail("Can't read the password.");
+    }
+
+    password.trim_end_matches(&['\r', '\n'][..]).to_string()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    process::exit(2);
+}
@@ -3,13 +3,19 @@
 
 use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream};
 use std::sync::mpsc::Sender;
 use std::thread;
 use event::{BlockEvent, ChunkRequestEvent, Event, IdEvent, PositionEvent,
             LightEvent, SignEvent, TalkEvent};
+use limit::{EditKind, EditLimiter, Verdict};
+use metrics::Metrics;
 use server::ServerTime;
-use world::{Block, chunked, Light, Sign};
+use world::{Author, Block, chunked, Light, Sign, Value};
+
+/// Blocks, signs and lights copied from the world, by their positions relative
+/// to where they were copied from.
+pub type Clipboard = Vec<((i32, i32, i32), Value)>;
 
 /// A type representing the ID players are given to uniquely identify them on both the client
 /// and the server side.
@@ -21,18 +27,26 @@ pub struct Client {
     addr: IpAddr,
     nick: String,
     position: (f32, f32, f32, f32, f32),
+    limiter: EditLimiter,
+    inspecting: bool,
+    selecting: bool,
+    selection: [Option<(i32, i32, i32)>; 2],
+    clipboard: Clipboard,
+    metrics: Metrics,
 }
 
 impl Client {
     /// Launches a new client with its TCP stream, a unique ID, and its nickname.
-    /// Also needed is the current server time and player transforms.
+    /// Also needed is the current server time and player transforms, and the metrics
+    /// that the messages sent to the client are counted in.
     pub fn run(mut stream: TcpStream,
                tx: Sender<IdEvent>,
                id: Id,
                nick: String,
                daytime: ServerTime,
-               other_clients: &mut HashMap<Id, Client>) -> Result<Client, ()> {
-        println!("New client id: {}", id);
+               other_clients: &mut HashMap<Id, Client>,
+               metrics: Metrics) -> Result<Client, ()> {
+        debug!(client = id; "New connection.");
 
         let send_stream = stream.try_clone().unwrap();
 
@@ -42,20 +56,35 @@ impl Client {
         let addr = stream.peer_addr().unwrap();
 
         if version_buf == [b'V', b',', b'1', b'\n'] {
-            println!("{:?} joined.", addr.to_string());
+            info!(client = id, peer:% = addr; "Client joined.");
+
+            let mut thread = ClientThread {
+                stream,
+                addr,
+                tx,
+                id,
+                metrics: metrics.clone(),
+            };
 
-            ClientThread::run(stream, addr, tx, id, &nick, daytime, other_clients);
+            thread.send_first_messages(&nick, daytime, other_clients);
+            thread.client_thread();
 
             let c = Client {
                 send_stream,
                 addr: addr.ip(),
                 nick,
                 position: (0., 0., 0., 0., 0.),
+                limiter: EditLimiter::new(),
+                inspecting: false,
+                selecting: false,
+                selection: [None, None],
+                clipboard: Vec::new(),
+                metrics,
             };
 
             return Ok(c);
         } else {
-            println!("{:?} denied.", addr.to_string());
+            warn!(client = id, peer:% = addr; "Client denied: unsupported version.");
 
             return Err(());
         }
@@ -71,6 +100,14 @@ impl Client {
         self.nick = nick.to_string();
     }
 
+    /// Returns the identity this client's changes to the world are recorded with.
+    pub fn author(&self) -> Author {
+        Author {
+            ip: self.addr,
+            nick: self.nick.clone(),
+        }
+    }
+
     /// Returns the IP address of this peer.
     pub fn addr(&self) -> &IpAddr {
         &self.addr
@@ -87,6 +124,59 @@ impl Client {
         self.position = position;
     }
 
+    /// Returns true if the blocks this client hits are inspected instead of changed.
+    pub fn is_inspecting(&self) -> bool {
+        self.inspecting
+    }
+
+    /// Sets whether the blocks this client hits are inspected instead of changed.
+    pub fn set_inspecting(&mut self, inspecting: bool) {
+        self.inspecting = inspecting;
+    }
+
+    /// Returns true if the blocks this client hits select corners instead of being changed.
+    pub fn is_selecting(&self) -> bool {
+        self.selecting
+    }
+
+    /// Sets whether the blocks this client hits select corners instead of being changed.
+    pub fn set_selecting(&mut self, selecting: bool) {
+        self.selecting = selecting;
+    }
+
+    /// Returns the two corners of this client's selection, if they are set.
+    pub fn selection(&self) -> [Option<(i32, i32, i32)>; 2] {
+        self.selection
+    }
+
+    /// Sets the first (0) or second (1) corner of this client's selection.
+    pub fn set_selection_corner(&mut self, corner: usize, xyz: (i32, i32, i32)) {
+        self.selection[corner] = Some(xyz);
+    }
+
+    /// Returns what this client last copied.
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    /// Replaces what this client last copied.
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+
+    /// Checks this client's edit rate limits for an edit of the given kind.
+    pub fn check_edit(&mut self, kind: EditKind) -> Verdict {
+        self.limiter.check(kind)
+    }
+
+    /// Tells the client why it is being removed, then closes the connection.
+    /// The client's thread will notice and report the disconnect as usual.
+    pub fn kick(&mut self, reason: &str) {
+        self.broadcast_talk(reason);
+
+        let _ = self.send_stream.shutdown(Shutdown::Both);
+    }
+
     /// Sends another client's position.
     pub fn send_position(&mut self, other_id: Id, ev: &PositionEvent) {
         //println!("should send {}'s position to: {}", another_id, self.id);
@@ -104,8 +194,7 @@ impl Client {
 
         //print!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends a chat message.
@@ -128,8 +217,7 @@ impl Client {
         let msg = format!("D,{}\n", other_id);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Notifies a client that a sign has changed in the world.
@@ -144,8 +232,7 @@ impl Client {
                           ev.text);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends a chat message without an event.
@@ -153,8 +240,7 @@ impl Client {
         let msg = format!("T,{}\n", text);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends another player's nickname to this client.
@@ -162,8 +248,7 @@ impl Client {
         let msg = format!("N,{},{}\n", other_id, nick);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends a block change without an event.
@@ -177,8 +262,7 @@ impl Client {
                           (block.1).0.to_string());
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Informs a client that a chunk needs to be redrawn.
@@ -186,8 +270,7 @@ impl Client {
         let msg = format!("R,{},{}\n", chunk.0, chunk.1);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends a sign update to the client.
@@ -199,8 +282,7 @@ impl Client {
                           sign.0);
         //println!("will send: {}", msg);
 
-        // TODO: What if the stream is now closed? Alert something that client is disconnected.
-        let _ = self.send_stream.write_all(msg.as_bytes());
+        self.send(&msg);
     }
 
     /// Sends a light update to the client.
@@ -210,6 +292,13 @@ impl Client {
                           (light.0).0, (light.0).1, (light.0).2,
                           (light.1).0);
 
+        self.send(&msg);
+    }
+
+    /// Sends a message to the client, and counts it.
+    fn send(&mut self, msg: &str) {
+        self.metrics.count_message(msg);
+
         // TODO: What if the stream is now closed? Alert something that client is disconnected.
         let _ = self.send_stream.write_all(msg.as_bytes());
     }
@@ -220,35 +309,21 @@ struct ClientThread {
     addr: SocketAddr,
     tx: Sender<IdEvent>,
     id: Id,
+    metrics: Metrics,
 }
 
 impl ClientThread {
-    fn run(stream: TcpStream,
-           addr: SocketAddr,
-           tx: Sender<IdEvent>,
-           id: Id,
-           nick: &str,
-           daytime: ServerTime,
-           other_clients: &mut HashMap<Id, Client>) {
-        let mut c = ClientThread {
-            stream,
-            addr,
-            tx,
-            id,
-        };
-
-        c.send_first_messages(nick, daytime, other_clients);
-        c.client_thread();
-    }
-
     fn client_thread(mut self) {
         thread::spawn(move || {
             const BUFFER_LEN: usize = 4096;
 
+            self.tx.send(IdEvent { id: self.id, peer: self.addr, event: Event::Join }).unwrap();
+
             loop {
                 let mut buf: [u8; BUFFER_LEN] = [0; BUFFER_LEN];
 
-                let n_read = self.stream.read(&mut buf).unwrap();
+                // An error here means the connection was reset or shut down.
+                let n_read = self.stream.read(&mut buf).unwrap_or(0);
 
                 if n_read > 0 {
                     let msg = String::from_utf8_lossy(&buf);
@@ -266,7 +341,7 @@ impl ClientThread {
 
             self.tx.send(IdEvent { id: self.id, peer: self.addr, event: Event::Disconnected }).unwrap();
 
-            println!("A client left.");
+            info!(client = self.id, peer:% = self.addr; "Client left.");
         });
     }
 
@@ -280,30 +355,30 @@ impl ClientThread {
 
         // Tell the client the ID it has and where spawn is.
         // U,id,x,y,z,rx,ry
-        let _ = self.stream.write_all(format!("U,{},0,0,0,0,0\n", id).as_bytes());
+        self.send(&format!("U,{},0,0,0,0,0\n", id));
 
         // Tell the client the current server time.
         // E,time,day_length
-        let _ = self.stream.write_all(format!("E,{},{}\n",
-                                              daytime.time(),
-                                              DAY_LENGTH).as_bytes());
+        self.send(&format!("E,{},{}\n",
+                           daytime.time(),
+                           DAY_LENGTH));
 
         for i in other_clients {
             let transform = i.1.position();
 
             // Tell the client where other players are.
             // P,id,x,y,z,rx,ry
-            let _ = self.stream.write_all(format!("P,{},{},{},{},{},{}\n",
-                                                  i.0,
-                                                  transform.0,
-                                                  transform.1,
-                                                  transform.2,
-                                                  transform.3,
-                                                  transform.4).as_bytes());
+            self.send(&format!("P,{},{},{},{},{},{}\n",
+                               i.0,
+                               transform.0,
+                               transform.1,
+                               transform.2,
+                               transform.3,
+                               transform.4));
 
             // Tell the client what the others' nickanmes are.
             // N,id,name
-            let _ = self.stream.write_all(format!("N,{},{}\n", i.0, i.1.nick()).as_bytes());
+            self.send(&format!("N,{},{}\n", i.0, i.1.nick()));
 
             // The the *other* clients what that this player exists.
             // Note that in the Craft client, a player is initialized client-side
@@ -317,7 +392,14 @@ impl ClientThread {
 
         // Tell the client its nickname.
         // N,id,name
-        let _ = self.stream.write_all(format!("N,{},{}\n", id, nick).as_bytes());
+        self.send(&format!("N,{},{}\n", id, nick));
+    }
+
+    /// Sends a message to the client, and counts it.
+    fn send(&mut self, msg: &str) {
+        self.metrics.count_message(msg);
+
+        let _ = self.stream.write_all(msg.as_bytes());
     }
 
     fn handle_message(&self, msg: &str) {
@@ -0,0 +1,61 @@
+//! The command for reading the audit log.
+
+use client;
+use role::Role;
+use super::CommandHandler;
+
+/// The number of entries shown when no number is given.
+const DEFAULT_ENTRIES: usize = 10;
+
+/// The most entries shown at once.
+const MAX_ENTRIES: usize = 50;
+
+impl CommandHandler {
+    pub(super) fn handle_audit(&mut self, id: client::Id, args: &[&str]) {
+        const USAGE: &str = "Usage: /audit [nick|ip|action] [count]";
+
+        if !self.require_role(id, Role::Moderator) {
+            return;
+        }
+
+        // A lone number is a count, and anything else is a filter.
+        let (filter, count) = match *args {
+            [] => (None, None),
+            [a] => match a.parse::<usize>() {
+                Ok(n) => (None, Some(n)),
+                Err(_) => (Some(a), None),
+            },
+            [a, n] => match n.parse::<usize>() {
+                Ok(n) => (Some(a), Some(n)),
+                Err(_) => (None, Some(0)),
+            },
+            _ => (None, Some(0)),
+        };
+
+        let count = match count {
+            None => DEFAULT_ENTRIES,
+            Some(n) if n > 0 && n <= MAX_ENTRIES => n,
+            _ => {
+                self.reply(id, &format!("{} (count 1-{})", USAGE, MAX_ENTRIES));
+                return;
+            },
+        };
+
+        let entries = match self.audit.search(filter, count) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!(client = id; "Can't read the audit log: {}", e);
+                self.reply(id, "The audit log can't be read right now.");
+                return;
+            },
+        };
+
+        if entries.is_empty() {
+            self.reply(id, "Nothing was found in the audit log.");
+        }
+
+        for e in entries {
+            self.reply(id, &e.to_string());
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! The command for backing up the world.
+
+use client;
+use role::Role;
+use super::CommandHandler;
+
+impl CommandHandler {
+    pub(super) fn handle_backup(&mut self, id: client::Id) {
+        if !self.require_role(id, Role::Admin) {
+            return;
+        }
+
+        self.reply(id, "Backing up the world...");
+
+        // The backup is taken on the backup thread, which replies when it is done.
+        let clients = self.clients.clone();
+        let consoles = self.consoles.clone();
+        let metrics = self.metrics.clone();
+        self.backups.request(Box::new(move |result| {
+            let text = match result {
+                Ok(path) => format!("Backed up the world to {}.", path.display()),
+                Err(_) => "The world can't be backed up right now.".to_string(),
+            };
+
+            if consoles.print(id, &text) {
+                return;
+            }
+
+            if let Some(c) = metrics.lock_clients(&clients).get_mut(&id) {
+                c.broadcast_talk(&text);
+            }
+        }));
+    }
+}
@@ -0,0 +1,103 @@
+//! Commands for inspecting who changed the world.
+
+use client;
+use role::Role;
+use world::{Change, HistoryQuery, unix_time, Value, World};
+use super::CommandHandler;
+
+/// The most changes shown for a position.
+const MAX_SHOWN: usize = 10;
+
+impl CommandHandler {
+    pub(super) fn handle_blame(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        if !self.require_role(id, Role::Moderator) {
+            return;
+        }
+
+        match args.len() {
+            0 => {
+                let inspecting = match self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                    Some(c) => {
+                        let inspecting = !c.is_inspecting();
+                        c.set_inspecting(inspecting);
+                        inspecting
+                    },
+                    None => return,
+                };
+
+                self.reply(id, if inspecting {
+                    "Inspection mode on. Hit a block to see its history."
+                } else {
+                    "Inspection mode off."
+                });
+            },
+            3 => {
+                let xyz: Vec<i32> = args.iter().filter_map(|a| a.parse().ok()).collect();
+
+                if xyz.len() == 3 {
+                    self.blame(id, (xyz[0], xyz[1], xyz[2]), world);
+                } else {
+                    self.reply(id, "Usage: /blame [x y z]");
+                }
+            },
+            _ => self.reply(id, "Usage: /blame [x y z]"),
+        }
+    }
+
+    /// Tells a client who changed the block, signs and light at a position, newest first.
+    pub fn blame(&self, id: client::Id, xyz: (i32, i32, i32), world: &World) {
+        let changes = world.history(&HistoryQuery {
+            at: Some(xyz),
+            include_reverted: true,
+            limit: Some(MAX_SHOWN),
+            ..Default::default()
+        });
+
+        let changes = match self.check(id, changes) {
+            Some(changes) => changes,
+            None => return,
+        };
+
+        if changes.is_empty() {
+            self.reply(id, &format!("Nobody has changed {:?}.", xyz));
+            return;
+        }
+
+        self.reply(id, &format!("History of {:?}:", xyz));
+
+        let now = unix_time();
+        for c in &changes {
+            self.reply(id, &describe(c, now));
+        }
+    }
+}
+
+fn describe(c: &Change, now: u64) -> String {
+    let what = match (&c.old, &c.new) {
+        (Value::Block(old), Value::Block(new)) => format!("block {} -> {}", old.0, new.0),
+        (Value::Sign(face, old), Value::Sign(_, new)) =>
+            format!("sign on face {} {:?} -> {:?}", face, old.0, new.0),
+        (Value::Light(old), Value::Light(new)) => format!("light {} -> {}", old.0, new.0),
+        _ => "unknown change".to_string(),
+    };
+
+    format!("{} ago, {} ({}): {}{}",
+            format_age(now.saturating_sub(c.time)),
+            c.author.nick,
+            c.author.ip,
+            what,
+            if c.reverted { " (undone)" } else { "" })
+}
+
+/// Formats a number of seconds in its largest whole unit.
+fn format_age(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h", secs / (60 * 60))
+    } else {
+        format!("{}d", secs / (24 * 60 * 60))
+    }
+}
@@ -0,0 +1,79 @@
+//! Commands that control the server process, which only consoles may use.
+
+use std::process;
+use std::thread;
+use std::time::Duration;
+use client;
+use world::World;
+use super::CommandHandler;
+
+/// How long the server waits between its last reply to `stop` and exiting.
+const STOP_DELAY: Duration = Duration::from_millis(200);
+
+impl CommandHandler {
+    pub(super) fn handle_stop(&mut self, id: client::Id, world: &mut World) {
+        if !self.require_console(id) {
+            return;
+        }
+
+        self.reply(id, "Stopping the server...");
+
+        for c in self.metrics.lock_clients(&self.clients).values_mut() {
+            c.kick("The server is stopping.");
+        }
+
+        // No events are handled after this one, so nothing changes the world
+        // while it is saved.
+        let code = match world.save() {
+            Ok(()) => {
+                self.reply(id, "Saved the world. Goodbye.");
+                0
+            },
+            Err(e) => {
+                self.reply(id, &format!("Can't save the world: {}. Stopping anyway.", e));
+                1
+            },
+        };
+
+        // Gives remote consoles a moment to receive the last replies.
+        thread::sleep(STOP_DELAY);
+        process::exit(code);
+    }
+
+    pub(super) fn handle_save(&mut self, id: client::Id, world: &mut World) {
+        if !self.require_console(id) {
+            return;
+        }
+
+        match world.save() {
+            Ok(()) => self.reply(id, "Saved the world."),
+            Err(e) => self.reply(id, &format!("Can't save the world: {}", e)),
+        }
+    }
+
+    pub(super) fn handle_reload(&mut self, id: client::Id) {
+        if !self.require_console(id) {
+            return;
+        }
+
+        let roles = self.roles.lock().unwrap().reload();
+        let nicks = self.nicks.lock().unwrap().reload();
+
+        self.reply(id, match (roles, nicks) {
+            (true, true) => "Reloaded roles.txt and nicks.txt.",
+            (false, true) => "Reloaded nicks.txt, but roles.txt can't be read. The old roles are kept.",
+            (true, false) => "Reloaded roles.txt, but nicks.txt can't be read. The old nicknames are kept.",
+            (false, false) => "Neither roles.txt nor nicks.txt can be read. Nothing was reloaded.",
+        });
+    }
+
+    /// Checks that a command came from a console, and tells the client if it didn't.
+    fn require_console(&self, id: client::Id) -> bool {
+        if self.is_console(id) {
+            true
+        } else {
+            self.reply(id, "That can only be done from a console.");
+            false
+        }
+    }
+}
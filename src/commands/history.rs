@@ -0,0 +1,185 @@
+//! Commands that undo recorded changes to the world.
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use client;
+use edit;
+use role::Role;
+use world::{Change, HistoryQuery, unix_time, Value, World, WorldError};
+use super::CommandHandler;
+
+/// The most changes a player may undo at once.
+const MAX_UNDO: usize = 100;
+
+/// The farthest a rollback may reach from the moderator, on every axis. Rollbacks
+/// without a radius reach the whole world.
+const MAX_ROLLBACK_RADIUS: i32 = 1024;
+
+impl CommandHandler {
+    pub(super) fn handle_rollback(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        const USAGE: &str = "Usage: /rollback <nick> <duration> [radius]";
+
+        if !self.require_role(id, Role::Moderator) {
+            return;
+        }
+
+        if args.len() < 2 || args.len() > 3 {
+            self.reply(id, USAGE);
+            return;
+        }
+
+        let ip = match self.resolve_player(args[0]) {
+            Some(ip) => ip,
+            None => {
+                self.reply(id, &format!("Nobody is known as {}.", args[0]));
+                return;
+            },
+        };
+
+        let duration = match parse_duration(args[1]) {
+            Some(d) => d,
+            None => {
+                self.reply(id, "Durations look like 90s, 30m, 12h or 7d.");
+                return;
+            },
+        };
+
+        let near = match args.get(2) {
+            Some(r) => match (r.parse::<i32>(), self.block_position_of(id)) {
+                (Ok(r), Some(xyz)) if (0..=MAX_ROLLBACK_RADIUS).contains(&r) => Some((xyz, r)),
+                (Ok(r), Some(_)) if r > MAX_ROLLBACK_RADIUS => {
+                    self.reply(id, &format!("The radius may be at most {}. Leave it out to roll back \
+                                             everywhere.", MAX_ROLLBACK_RADIUS));
+                    return;
+                },
+                _ => {
+                    self.reply(id, USAGE);
+                    return;
+                },
+            },
+            None => None,
+        };
+
+        let changes = world.history(&HistoryQuery {
+            ip: Some(ip),
+            since: Some(unix_time().saturating_sub(duration)),
+            near,
+            ..Default::default()
+        });
+
+        let changes = match self.check(id, changes) {
+            Some(changes) => changes,
+            None => return,
+        };
+
+        if self.revert_changes(id, world, &changes) {
+            self.audit(id, "rollback", args[0], &format!("{} changes in the last {}", changes.len(), args[1]));
+            self.reply(id, &format!("Rolled back {} changes by {}.", changes.len(), args[0]));
+        }
+    }
+
+    pub(super) fn handle_undo(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        let n = match args.first().map(|n| n.parse::<usize>()) {
+            None => 1,
+            Some(Ok(n)) if n > 0 && n <= MAX_UNDO => n,
+            _ => {
+                self.reply(id, &format!("Usage: /undo [1-{}]", MAX_UNDO));
+                return;
+            },
+        };
+
+        let ip = match self.addr_of(id) {
+            Some(ip) => ip,
+            None => return,
+        };
+
+        let changes = world.history(&HistoryQuery {
+            ip: Some(ip),
+            limit: Some(n),
+            ..Default::default()
+        });
+
+        let changes = match self.check(id, changes) {
+            Some(changes) => changes,
+            None => return,
+        };
+
+        if changes.is_empty() {
+            self.reply(id, "You have nothing to undo.");
+        } else if self.revert_changes(id, world, &changes) {
+            self.reply(id, &format!("Undid {} changes.", changes.len()));
+        }
+    }
+
+    /// Finds the address of a player by nickname, whether or not the player is online.
+    fn resolve_player(&self, nick: &str) -> Option<IpAddr> {
+        self.find_player(nick).or_else(|| self.nicks.lock().unwrap().find(nick))
+    }
+
+    /// Restores the values the changes replaced, sends them to all clients and
+    /// marks the changes as reverted.
+    ///
+    /// The changes must be sorted from newest to oldest. Where several changes
+    /// touch the same position, the value from before the oldest one is restored.
+    /// # Return value
+    /// Returns false if the world could not be changed. The client that issued
+    /// the command is told.
+    fn revert_changes(&self, id: client::Id, world: &mut World, changes: &[Change]) -> bool {
+        let mut restore = BTreeMap::new();
+
+        for c in changes {
+            let slot = match c.old {
+                Value::Block(_) => (0, c.xyz, 0),
+                Value::Sign(face, _) => (1, c.xyz, face),
+                Value::Light(_) => (2, c.xyz, 0),
+            };
+
+            restore.insert(slot, c.old.clone());
+        }
+
+        // Blocks are restored before signs, so that signs have blocks to go on.
+        let (signs, edits): (Vec<_>, Vec<_>) = restore.into_iter()
+                                                      .map(|(slot, value)| (slot.1, value))
+                                                      .partition(|(_, v)| matches!(*v, Value::Sign(..)));
+
+        let mut clients = self.metrics.lock_clients(&self.clients);
+        let mut result = edit::apply(world, &mut clients, None, &edits);
+
+        for sign in signs {
+            if result.is_err() {
+                break;
+            }
+
+            result = match edit::apply(world, &mut clients, None, &[sign]) {
+                // The block under the sign is still mined, so the sign stays removed.
+                Err(WorldError::SignOnAir) => Ok(()),
+                r => r,
+            };
+        }
+
+        drop(clients);
+
+        let result = result.and_then(|_| world.set_reverted(changes.iter().map(|c| c.id).collect()));
+
+        self.check(id, result).is_some()
+    }
+}
+
+/// Parses a duration such as `90s`, `30m`, `12h` or `7d` into seconds.
+/// A number without a unit is in seconds.
+fn parse_duration(s: &str) -> Option<u64> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n.parse().ok()?;
+
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    n.checked_mul(scale)
+}
@@ -0,0 +1,310 @@
+//! The `commands` module contains the majority of the mechanism for handling chat commands.
+
+mod audit;
+mod backup;
+mod blame;
+mod console;
+mod history;
+mod region;
+mod schematic;
+mod worldedit;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use audit::{Actor, AuditLog};
+use client;
+use console::Consoles;
+use metrics::Metrics;
+use nick::NickManager;
+use role::{Role, RoleManager};
+use world::{Backups, World, WorldError};
+
+/// The commands that act on the player that issues them, which consoles can't use.
+const PLAYER_COMMANDS: [&str; 14] = ["nick", "claim", "claims", "undo", "pos1", "pos2", "wand", "set",
+                                     "replace", "walls", "copy", "paste", "move", "schem"];
+
+/// Allows processing of chat commands.
+pub struct CommandHandler {
+    clients: Arc<Mutex<HashMap<client::Id, client::Client>>>,
+    nicks: Arc<Mutex<NickManager>>,
+    roles: Arc<Mutex<RoleManager>>,
+    backups: Backups,
+    consoles: Consoles,
+    metrics: Metrics,
+    audit: AuditLog,
+}
+
+impl CommandHandler {
+    /// Creates a new CommandHandler, requiring access to the server's client list,
+    /// nicknames, roles, backups, consoles, metrics and audit log.
+    pub fn new(clients: Arc<Mutex<HashMap<client::Id, client::Client>>>,
+               nicks: Arc<Mutex<NickManager>>,
+               roles: Arc<Mutex<RoleManager>>,
+               backups: Backups,
+               consoles: Consoles,
+               metrics: Metrics,
+               audit: AuditLog) -> CommandHandler {
+        CommandHandler {
+            clients,
+            nicks,
+            roles,
+            backups,
+            consoles,
+            metrics,
+            audit,
+        }
+    }
+
+    /// Handle an incoming command.
+    ///
+    /// Commands from consoles are sent with the IDs of the consoles, and have the rights of an admin.
+    ///
+    /// # Arguments
+    /// * **command**: The slice of the command contains the name and arguments, but no `/`.
+    /// * **world**: The world, for commands that inspect or change it.
+    pub fn handle_command(&mut self, id: client::Id, command: &str, world: &mut World) {
+        let args: Vec<&str> = command.split_whitespace().collect();
+        let name = args.first().cloned().unwrap_or("");
+
+        if self.is_console(id) && PLAYER_COMMANDS.contains(&name) {
+            self.reply(id, "Only players can do that.");
+            return;
+        }
+
+        match name {
+            "say" => self.handle_say(command),
+            "nick" => self.handle_nick(id, command),
+            "role" => self.handle_role(id, &args[1..]),
+            "audit" => self.handle_audit(id, &args[1..]),
+            "claim" => self.handle_claim(id, &args[1..], world),
+            "unclaim" => self.handle_unclaim(id, &args[1..], world),
+            "claims" => self.handle_claims(id, world),
+            "trust" => self.handle_trust(id, &args[1..], world, true),
+            "untrust" => self.handle_trust(id, &args[1..], world, false),
+            "region" => self.handle_region(id, &args[1..], world),
+            "blame" => self.handle_blame(id, &args[1..], world),
+            "rollback" => self.handle_rollback(id, &args[1..], world),
+            "undo" => self.handle_undo(id, &args[1..], world),
+            "backup" => self.handle_backup(id),
+            "pos1" => self.handle_pos(id, 0, &args[1..]),
+            "pos2" => self.handle_pos(id, 1, &args[1..]),
+            "wand" => self.handle_wand(id),
+            "set" => self.handle_set(id, &args[1..], world),
+            "replace" => self.handle_replace(id, &args[1..], world),
+            "walls" => self.handle_walls(id, &args[1..], world),
+            "copy" => self.handle_copy(id, world),
+            "paste" => self.handle_paste(id, world),
+            "move" => self.handle_move(id, &args[1..], world),
+            "schem" => self.handle_schem(id, &args[1..], world),
+            "stop" => self.handle_stop(id, world),
+            "save" => self.handle_save(id, world),
+            "reload" => self.handle_reload(id),
+            _ => {
+                debug!(client = id; "Unknown command or invalid usage: {}", command);
+                self.reply(id, "Unknown command.");
+            },
+        }
+    }
+
+    /// Sends a chat message to the client or console that issued a command.
+    fn reply(&self, id: client::Id, text: &str) {
+        if self.consoles.print(id, text) {
+            return;
+        }
+
+        if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            c.broadcast_talk(text);
+        }
+    }
+
+    /// Returns the address of a connected client.
+    fn addr_of(&self, id: client::Id) -> Option<IpAddr> {
+        self.metrics.lock_clients(&self.clients).get(&id).map(|c| *c.addr())
+    }
+
+    /// Returns the position of the block a connected client is in. Consoles are
+    /// told that only players can do that.
+    fn block_position_of(&self, id: client::Id) -> Option<(i32, i32, i32)> {
+        if self.is_console(id) {
+            self.reply(id, "Only players can do that.");
+        }
+
+        self.metrics.lock_clients(&self.clients).get(&id).map(|c| {
+            let p = c.position();
+            (p.0.floor() as i32, p.1.floor() as i32, p.2.floor() as i32)
+        })
+    }
+
+    /// Returns the address of the connected client with the given nickname.
+    fn find_player(&self, nick: &str) -> Option<IpAddr> {
+        self.metrics.lock_clients(&self.clients).values().find(|c| c.nick() == nick).map(|c| *c.addr())
+    }
+
+    /// Returns who issued a command, for the audit log.
+    fn actor_of(&self, id: client::Id) -> Actor {
+        if self.is_console(id) {
+            return Actor::console();
+        }
+
+        match self.metrics.lock_clients(&self.clients).get(&id) {
+            Some(c) => Actor::player(c.nick(), *c.addr()),
+            None => Actor::server(),
+        }
+    }
+
+    /// Records what a client or console did in the audit log.
+    fn audit(&self, id: client::Id, action: &str, target: &str, reason: &str) {
+        self.audit.record(action, &self.actor_of(id), target, reason);
+    }
+
+    /// Returns true if a command came from a console.
+    fn is_console(&self, id: client::Id) -> bool {
+        self.consoles.contains(id)
+    }
+
+    /// Returns the role of a connected client. Consoles are admins.
+    fn role_of(&self, id: client::Id) -> Role {
+        if self.is_console(id) {
+            return Role::Admin;
+        }
+
+        match self.addr_of(id) {
+            Some(ip) => self.roles.lock().unwrap().get(&ip),
+            None => Role::Player,
+        }
+    }
+
+    /// Unwraps the result of reading or changing the world. If it failed, the client
+    /// that issued the command is told.
+    fn check<T>(&self, id: client::Id, result: Result<T, WorldError>) -> Option<T> {
+        match result {
+            Ok(v) => Some(v),
+            Err(e) => {
+                error!(client = id; "Command failed: {}", e);
+                self.reply(id, "That can't be done, because the world can't be read right now.");
+
+                None
+            },
+        }
+    }
+
+    /// Checks that a client has at least the given role, and tells it if it doesn't.
+    fn require_role(&self, id: client::Id, role: Role) -> bool {
+        if self.role_of(id) >= role {
+            true
+        } else {
+            self.reply(id, &format!("You must be a {} to do that.", role));
+            false
+        }
+    }
+
+    fn handle_role(&mut self, id: client::Id, args: &[&str]) {
+        if !self.require_role(id, Role::Admin) {
+            return;
+        }
+
+        if args.len() != 2 {
+            self.reply(id, "Usage: /role <nick> <player|moderator|admin>");
+            return;
+        }
+
+        let role: Role = match args[1].parse() {
+            Ok(r) => r,
+            Err(_) => {
+                self.reply(id, "Usage: /role <nick> <player|moderator|admin>");
+                return;
+            },
+        };
+
+        match self.find_player(args[0]) {
+            Some(ip) => {
+                self.roles.lock().unwrap().set(&ip, role);
+                self.audit(id, "role", args[0], &role.to_string());
+                self.reply(id, &format!("{} is now a {}.", args[0], role));
+            },
+            None => self.reply(id, &format!("No player named {} is online.", args[0])),
+        }
+    }
+
+    fn handle_say(&self, command: &str) {
+        let mut it = command.split(' ').peekable();
+
+        if it.next().unwrap_or("") == "say" {
+            let preserve_whitespace = if *it.peek().unwrap_or(&"") == "-w" {
+                let _ = it.next();
+
+                true
+            } else {
+                false
+            };
+
+            // TODO: Efficiency?
+            let announcement: String = it
+                    .filter(|s| preserve_whitespace || !s.is_empty())
+                    .map(|s| (s.to_string() + " ").lines().next().unwrap_or("").to_string())
+                    .collect();
+
+            if announcement.is_empty() || announcement == "-w" {
+                debug!("Invalid usage of say.");
+                return;
+            }
+
+            debug!("Announcement: {:?}", announcement);
+
+            for i in self.metrics.lock_clients(&self.clients).iter_mut() {
+                i.1.broadcast_talk(&announcement);
+            }
+        } else {
+            debug!("Invalid usage of say.");
+        }
+    }
+
+    fn handle_nick(&mut self, id: client::Id, command: &str) {
+        let mut it = command.split(' ');
+
+        if it.next().unwrap_or("") == "nick" {
+            let nick;
+
+            if let Some(n) = it.next() {
+                let mut n = n.trim();
+                if n.find(|c: char| !c.is_whitespace() && !c.is_control()).is_none() {
+                    debug!(client = id; "Invalid usage.");
+                    return;
+                }
+
+                n = n.trim_matches(|c: char| c.is_control());
+                nick = n;
+
+                if it.next().is_some() {
+                    debug!(client = id; "Invalid usage.");
+                    return;
+                }
+
+                debug!(client = id; "New nick: {}", nick);
+
+                let mut clients = self.metrics.lock_clients(&self.clients);
+                let msg;
+                {
+                    let c = clients.get_mut(&id).unwrap();
+
+                    if c.nick() == nick {
+                        debug!(client = id, peer:% = c.addr(); "Client tried to set its current nick again.");
+                        return;
+                    }
+
+                    self.audit.record("nick", &Actor::player(c.nick(), *c.addr()), nick, "");
+                    self.nicks.lock().unwrap().set(c.addr(), nick);
+                    c.set_nick(nick);
+
+                    msg = format!("{} is now known as: {}", c.nick(), nick);
+                }
+
+                for i in clients.values_mut() {
+                    i.broadcast_talk(&msg);
+                    i.broadcast_nick(id, nick);
+                }
+            }
+        }
+    }
+}
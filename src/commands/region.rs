@@ -0,0 +1,334 @@
+//! Commands for land claims and server regions.
+
+use client;
+use role::Role;
+use world::{Flag, Region, World, WORLD_HEIGHT};
+use super::CommandHandler;
+
+/// The most claims a player may own.
+const MAX_CLAIMS: usize = 5;
+
+/// The longest side, on the X and Z axes, that a claim may have.
+const MAX_CLAIM_SIDE: i32 = 128;
+
+/// The vertical extent of claims made with a radius.
+const CLAIM_HEIGHT: (i32, i32) = (0, WORLD_HEIGHT - 1);
+
+/// Two opposite corners of a cuboid.
+type Corners = ((i32, i32, i32), (i32, i32, i32));
+
+impl CommandHandler {
+    pub(super) fn handle_claim(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        const USAGE: &str = "Usage: /claim <name> <radius> or /claim <name> <x1> <y1> <z1> <x2> <y2> <z2>";
+
+        let ip = match self.addr_of(id) {
+            Some(ip) => ip,
+            None => return,
+        };
+
+        let admin = self.role_of(id) >= Role::Admin;
+
+        let region = match args.len() {
+            2 => {
+                let radius: i32 = match args[1].parse() {
+                    Ok(r) if r >= 0 => r,
+                    _ => {
+                        self.reply(id, USAGE);
+                        return;
+                    },
+                };
+
+                if !admin && radius > MAX_CLAIM_SIDE / 2 {
+                    self.reply(id, &format!("Claims can't be wider than {} blocks.", MAX_CLAIM_SIDE));
+                    return;
+                }
+
+                let (x, _, z) = match self.block_position_of(id) {
+                    Some(xyz) => xyz,
+                    None => return,
+                };
+
+                let corners = (x.checked_sub(radius), z.checked_sub(radius),
+                               x.checked_add(radius), z.checked_add(radius));
+
+                match corners {
+                    (Some(x1), Some(z1), Some(x2), Some(z2)) => {
+                        Region::new(args[0], Some(ip), (x1, CLAIM_HEIGHT.0, z1), (x2, CLAIM_HEIGHT.1, z2))
+                    },
+                    _ => {
+                        self.reply(id, "That claim would reach past the edge of the world.");
+                        return;
+                    },
+                }
+            },
+            7 => match parse_corners(&args[1..]) {
+                Some((a, b)) => Region::new(args[0], Some(ip), a, b),
+                None => {
+                    self.reply(id, USAGE);
+                    return;
+                },
+            },
+            _ => {
+                self.reply(id, USAGE);
+                return;
+            },
+        };
+
+        if world.regions().get(&region.name).is_some() {
+            self.reply(id, &format!("A region named {} already exists.", region.name));
+            return;
+        }
+
+        if !admin {
+            if world.regions().owned_by(&ip).len() >= MAX_CLAIMS {
+                self.reply(id, &format!("You can't own more than {} claims.", MAX_CLAIMS));
+                return;
+            }
+
+            // Widths are computed in i64, since corners can be as far apart as i32 allows.
+            if region.max.0 as i64 - region.min.0 as i64 >= MAX_CLAIM_SIDE as i64 ||
+               region.max.2 as i64 - region.min.2 as i64 >= MAX_CLAIM_SIDE as i64 {
+                self.reply(id, &format!("Claims can't be wider than {} blocks.", MAX_CLAIM_SIDE));
+                return;
+            }
+
+            if let Some(other) = world.regions().iter().find(|r| r.overlaps(&region)) {
+                self.reply(id, &format!("That area overlaps the region {}.", other.name));
+                return;
+            }
+        }
+
+        let name = region.name.clone();
+        let extent = format!("{:?} to {:?}", region.min, region.max);
+
+        if self.check(id, world.set_region(region)).is_some() {
+            self.audit(id, "claim", &name, &extent);
+            self.reply(id, &format!("Claimed {} from {}.", name, extent));
+        }
+    }
+
+    pub(super) fn handle_unclaim(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        if args.len() != 1 {
+            self.reply(id, "Usage: /unclaim <name>");
+            return;
+        }
+
+        if self.may_manage(id, args[0], world) && self.check(id, world.remove_region(args[0])).is_some() {
+            self.audit(id, "unclaim", args[0], "");
+            self.reply(id, &format!("Removed the claim {}.", args[0]));
+        }
+    }
+
+    pub(super) fn handle_claims(&mut self, id: client::Id, world: &mut World) {
+        let ip = match self.addr_of(id) {
+            Some(ip) => ip,
+            None => return,
+        };
+
+        let claims = world.regions().owned_by(&ip);
+
+        if claims.is_empty() {
+            self.reply(id, "You have no claims.");
+        }
+
+        for r in claims {
+            self.reply(id, &format!("{}: {:?} to {:?}, {} trusted",
+                                    r.name, r.min, r.max, r.trusted.len()));
+        }
+    }
+
+    pub(super) fn handle_trust(&mut self,
+                               id: client::Id,
+                               args: &[&str],
+                               world: &mut World,
+                               trust: bool) {
+        if args.len() != 2 {
+            self.reply(id, if trust {
+                "Usage: /trust <claim> <nick>"
+            } else {
+                "Usage: /untrust <claim> <nick>"
+            });
+            return;
+        }
+
+        if !self.may_manage(id, args[0], world) {
+            return;
+        }
+
+        let ip = match self.find_player(args[1]) {
+            Some(ip) => ip,
+            None => {
+                self.reply(id, &format!("No player named {} is online.", args[1]));
+                return;
+            },
+        };
+
+        let mut region = world.regions().get(args[0]).unwrap().clone();
+
+        let text = if trust {
+            region.trusted.insert(ip);
+            format!("{} may now edit {}.", args[1], args[0])
+        } else {
+            region.trusted.remove(&ip);
+            format!("{} may no longer edit {}.", args[1], args[0])
+        };
+
+        if self.check(id, world.set_region(region)).is_some() {
+            self.audit(id, if trust { "trust" } else { "untrust" }, args[1], &format!("claim {}", args[0]));
+            self.reply(id, &text);
+        }
+    }
+
+    pub(super) fn handle_region(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        const USAGE: &str = "Usage: /region <define|remove|flag|list|info> ...";
+
+        match args.first().cloned().unwrap_or("") {
+            "info" => self.handle_region_info(id, &args[1..], world),
+            "list" => {
+                if !self.require_role(id, Role::Admin) {
+                    return;
+                }
+
+                for r in world.regions().iter() {
+                    self.reply(id, &describe(r));
+                }
+            },
+            "define" => {
+                if !self.require_role(id, Role::Admin) {
+                    return;
+                }
+
+                let corners = if args.len() == 8 { parse_corners(&args[2..]) } else { None };
+
+                match corners {
+                    Some((a, b)) => {
+                        if world.regions().get(args[1]).is_some() {
+                            self.reply(id, &format!("A region named {} already exists.", args[1]));
+                            return;
+                        }
+
+                        if self.check(id, world.set_region(Region::new(args[1], None, a, b))).is_some() {
+                            self.audit(id, "region-define", args[1], &format!("{:?} to {:?}", a, b));
+                            self.reply(id, &format!("Defined the region {}.", args[1]));
+                        }
+                    },
+                    None => self.reply(id, "Usage: /region define <name> <x1> <y1> <z1> <x2> <y2> <z2>"),
+                }
+            },
+            "remove" => {
+                if !self.require_role(id, Role::Admin) {
+                    return;
+                }
+
+                if args.len() != 2 {
+                    self.reply(id, "Usage: /region remove <name>");
+                    return;
+                }
+
+                match self.check(id, world.remove_region(args[1])) {
+                    Some(Some(_)) => {
+                        self.audit(id, "region-remove", args[1], "");
+                        self.reply(id, &format!("Removed the region {}.", args[1]));
+                    },
+                    Some(None) => self.reply(id, &format!("There is no region named {}.", args[1])),
+                    None => {},
+                }
+            },
+            "flag" => {
+                if !self.require_role(id, Role::Admin) {
+                    return;
+                }
+
+                let flag = args.get(2).and_then(|f| Flag::from_name(f));
+                let value = match args.get(3).cloned() {
+                    Some("allow") => Some(true),
+                    Some("deny") => Some(false),
+                    _ => None,
+                };
+
+                match (args.len(), world.regions().get(args.get(1).cloned().unwrap_or("")), flag, value) {
+                    (4, Some(r), Some(flag), Some(value)) => {
+                        let mut r = r.clone();
+                        r.set_flag(flag, value);
+
+                        let text = describe(&r);
+
+                        if self.check(id, world.set_region(r)).is_some() {
+                            self.audit(id, "region-flag", args[1], &format!("{} {}", args[2], args[3]));
+                            self.reply(id, &text);
+                        }
+                    },
+                    _ => self.reply(id, "Usage: /region flag <name> <build|sign|light> <allow|deny>"),
+                }
+            },
+            _ => self.reply(id, USAGE),
+        }
+    }
+
+    fn handle_region_info(&mut self, id: client::Id, args: &[&str], world: &World) {
+        if let Some(name) = args.first() {
+            match world.regions().get(name) {
+                Some(r) => self.reply(id, &describe(r)),
+                None => self.reply(id, &format!("There is no region named {}.", name)),
+            }
+
+            return;
+        }
+
+        let xyz = match self.block_position_of(id) {
+            Some(xyz) => xyz,
+            None => return,
+        };
+
+        let regions = world.regions().at(xyz);
+
+        if regions.is_empty() {
+            self.reply(id, "You are not in any region.");
+        }
+
+        for r in regions {
+            self.reply(id, &describe(r));
+        }
+    }
+
+    /// Checks that a region exists and that a client may change it.
+    /// Only the owner and admins may change a claim, and only admins may change server regions.
+    fn may_manage(&self, id: client::Id, name: &str, world: &World) -> bool {
+        let owner = match world.regions().get(name) {
+            Some(r) => r.owner,
+            None => {
+                self.reply(id, &format!("There is no region named {}.", name));
+                return false;
+            },
+        };
+
+        if owner.is_some() && owner == self.addr_of(id) {
+            true
+        } else {
+            self.require_role(id, Role::Admin)
+        }
+    }
+}
+
+fn parse_corners(args: &[&str]) -> Option<Corners> {
+    let n: Vec<i32> = args.iter().filter_map(|a| a.parse().ok()).collect();
+
+    if n.len() == 6 {
+        Some(((n[0], n[1], n[2]), (n[3], n[4], n[5])))
+    } else {
+        None
+    }
+}
+
+fn describe(r: &Region) -> String {
+    let allow = |b: bool| if b { "allow" } else { "deny" };
+
+    format!("{} ({}): {:?} to {:?}, build {}, sign {}, light {}",
+            r.name,
+            if r.owner.is_some() { "claim" } else { "server" },
+            r.min,
+            r.max,
+            allow(r.build),
+            allow(r.sign),
+            allow(r.light))
+}
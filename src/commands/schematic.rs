@@ -0,0 +1,133 @@
+//! Commands that save the selection as a schematic file and place schematic files.
+//!
+//! Schematics are kept in the `schematics` directory, named after the name given
+//! to `/schem save` with the `.schem` extension.
+
+use std::fs;
+use std::path::PathBuf;
+use client;
+use role::Role;
+use schematic::{BlockMapping, MAX_SIDE, Schematic};
+use world::{editable_height, World};
+use super::CommandHandler;
+use super::worldedit::{volume, without_signs_on_air};
+
+const DIRECTORY: &str = "schematics";
+
+impl CommandHandler {
+    pub(super) fn handle_schem(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        if !self.require_role(id, Role::Moderator) {
+            return;
+        }
+
+        let path = match args {
+            [_, name] => path_of(name),
+            _ => None,
+        };
+
+        match (args.first().cloned(), path) {
+            (Some("save"), Some(path)) => self.handle_schem_save(id, args[1], path, world),
+            (Some("load"), Some(path)) => self.handle_schem_load(id, args[1], path, world),
+            _ => self.reply(id, "Usage: /schem <save|load> <name>. Names may hold letters, digits, - and _."),
+        }
+    }
+
+    fn handle_schem_save(&mut self, id: client::Id, name: &str, path: PathBuf, world: &mut World) {
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        if !self.check_volume(id, volume((min, max))) {
+            return;
+        }
+
+        if max.0 - min.0 >= MAX_SIDE || max.1 - min.1 >= MAX_SIDE || max.2 - min.2 >= MAX_SIDE {
+            self.reply(id, &format!("Schematics can't be longer than {} blocks.", MAX_SIDE));
+            return;
+        }
+
+        let origin = match self.block_position_of(id) {
+            Some(xyz) => xyz,
+            None => return,
+        };
+
+        let schematic = match self.check(id, Schematic::from_world(world, min, max, origin)) {
+            Some(schematic) => schematic,
+            None => return,
+        };
+
+        let result = BlockMapping::load().and_then(|mapping| {
+            fs::create_dir_all(DIRECTORY)?;
+            schematic.write(&path, &mapping)
+        });
+
+        match result {
+            Ok(()) => self.reply(id, &format!("Saved {} blocks and {} signs as {}. Use /schem load {} \
+                                               to place them where you stand.",
+                                              schematic.blocks.len(), schematic.signs.len(), name, name)),
+            Err(e) => {
+                error!(client = id; "Can't save schematic {}: {}", path.display(), e);
+                self.reply(id, "The schematic can't be saved right now.");
+            },
+        }
+    }
+
+    fn handle_schem_load(&mut self, id: client::Id, name: &str, path: PathBuf, world: &mut World) {
+        if !path.exists() {
+            self.reply(id, &format!("There is no schematic named {}.", name));
+            return;
+        }
+
+        let origin = match self.block_position_of(id) {
+            Some(xyz) => xyz,
+            None => return,
+        };
+
+        let schematic = match BlockMapping::load().and_then(|mapping| Schematic::read(&path, &mapping)) {
+            Ok(schematic) => schematic,
+            Err(e) => {
+                error!(client = id; "Can't load schematic {}: {}", path.display(), e);
+                self.reply(id, &format!("The schematic {} can't be read.", name));
+                return;
+            },
+        };
+
+        let edits: Vec<_> = match schematic.edits(origin) {
+            Some(edits) => edits.into_iter().filter(|&(xyz, _)| editable_height(xyz.1)).collect(),
+            None => {
+                self.reply(id, &format!("The schematic {} would reach past the edge of the world.", name));
+                return;
+            },
+        };
+
+        if !self.check_volume(id, edits.len() as i64) {
+            return;
+        }
+
+        let edits = match self.check(id, without_signs_on_air(world, edits)) {
+            Some(edits) => edits,
+            None => return,
+        };
+
+        if self.apply_bulk(id, world, &edits) {
+            let mut text = format!("Placed {} blocks and signs from {}.", edits.len(), name);
+            if !schematic.unmapped.is_empty() {
+                text += &format!(" Blocks without a mapping were left out: {}", schematic.unmapped.join(", "));
+            }
+
+            self.reply(id, &text);
+        }
+    }
+}
+
+/// Returns the path of the schematic with a name, if the name is allowed.
+fn path_of(name: &str) -> Option<PathBuf> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+
+    if name.is_empty() || name.len() > 64 || !name.chars().all(allowed) {
+        return None;
+    }
+
+    Some(PathBuf::from(DIRECTORY).join(format!("{}.schem", name)))
+}
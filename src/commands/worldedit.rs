@@ -0,0 +1,509 @@
+//! Commands that select cuboids of the world and edit them in bulk.
+//!
+//! The server only knows the blocks that players placed or mined. Craft generates
+//! the terrain at every other position itself, so commands that read the world,
+//! like `/replace` and `/copy`, only see placed and mined blocks.
+
+use std::collections::HashMap;
+use client::{self, Clipboard};
+use edit;
+use role::Role;
+use world::{Block, block_type_by_name, cuboid, editable_height, Flag, Light, Value, World,
+            WorldError, WORLD_HEIGHT};
+use super::CommandHandler;
+
+/// Two opposite corners of a cuboid.
+type Corners = ((i32, i32, i32), (i32, i32, i32));
+
+/// Values to set, by their global positions.
+type Edits = Vec<((i32, i32, i32), Value)>;
+
+/// Returns the most positions that a bulk edit by a player with this role may change.
+fn max_volume(role: Role) -> i64 {
+    match role {
+        Role::Player => 4096,
+        Role::Moderator => 65536,
+        Role::Admin => 1048576,
+    }
+}
+
+impl CommandHandler {
+    pub(super) fn handle_pos(&mut self, id: client::Id, corner: usize, args: &[&str]) {
+        let xyz = match args.len() {
+            0 => match self.block_position_of(id) {
+                Some(xyz) => xyz,
+                None => return,
+            },
+            3 => match parse_xyz(args) {
+                Some(xyz) => xyz,
+                None => {
+                    self.reply(id, &format!("Usage: /pos{} [x y z]", corner + 1));
+                    return;
+                },
+            },
+            _ => {
+                self.reply(id, &format!("Usage: /pos{} [x y z]", corner + 1));
+                return;
+            },
+        };
+
+        let selection = match self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            Some(c) => {
+                c.set_selection_corner(corner, xyz);
+                c.selection()
+            },
+            None => return,
+        };
+
+        let mut text = format!("Position {} set to {:?}.", corner + 1, xyz);
+        if let [Some(a), Some(b)] = selection {
+            text += &format!(" The selection holds {} blocks.", volume(cuboid(a, b)));
+        }
+
+        self.reply(id, &text);
+    }
+
+    pub(super) fn handle_wand(&mut self, id: client::Id) {
+        let selecting = match self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            Some(c) => {
+                let selecting = !c.is_selecting();
+                c.set_selecting(selecting);
+                c.set_inspecting(false);
+                selecting
+            },
+            None => return,
+        };
+
+        self.reply(id, if selecting {
+            "Mine a block to set position 1, and place one to set position 2. Use /wand again to stop."
+        } else {
+            "Stopped selecting."
+        });
+    }
+
+    pub(super) fn handle_set(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        let block = match args {
+            [name] => parse_block(name),
+            _ => None,
+        };
+
+        let block = match block {
+            Some(b) => b,
+            None => {
+                self.reply(id, "Usage: /set <block>");
+                return;
+            },
+        };
+
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        if !self.check_volume(id, volume((min, max))) {
+            return;
+        }
+
+        let edits: Vec<_> = positions((min, max)).into_iter()
+                                                 .map(|xyz| (xyz, Value::Block(block.clone())))
+                                                 .collect();
+
+        if self.apply_bulk(id, world, &edits) {
+            self.reply(id, &format!("Set {} blocks.", edits.len()));
+        }
+    }
+
+    pub(super) fn handle_replace(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        let blocks = match args {
+            [from, to] => block_type_by_name(from).map(|t| Block(t.id)).zip(parse_block(to)),
+            _ => None,
+        };
+
+        let (from, to) = match blocks {
+            Some(blocks) => blocks,
+            None => {
+                self.reply(id, "Usage: /replace <from> <to>");
+                return;
+            },
+        };
+
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        if !self.check_volume(id, volume((min, max))) {
+            return;
+        }
+
+        let found = match self.check(id, world.blocks_in(min, max)) {
+            Some(found) => found,
+            None => return,
+        };
+
+        let edits: Vec<_> = found.filter(|&(xyz, ref b)| b.0 == from.0 && editable_height(xyz.1))
+                                 .map(|(xyz, _)| (xyz, Value::Block(to.clone())))
+                                 .collect();
+
+        if edits.is_empty() {
+            self.reply(id, "No blocks were replaced. Only blocks that were placed or mined can be replaced.");
+        } else if self.apply_bulk(id, world, &edits) {
+            self.reply(id, &format!("Replaced {} blocks.", edits.len()));
+        }
+    }
+
+    pub(super) fn handle_walls(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        let block = match args {
+            [name] => parse_block(name),
+            _ => None,
+        };
+
+        let block = match block {
+            Some(b) => b,
+            None => {
+                self.reply(id, "Usage: /walls <block>");
+                return;
+            },
+        };
+
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        // The walls go around the selection, on every layer of it.
+        let (dx, dz) = (side(min.0, max.0), side(min.2, max.2));
+        let around = if dx <= 2 || dz <= 2 { dx * dz } else { 2 * (dx + dz) - 4 };
+
+        if !self.check_volume(id, around.saturating_mul(side(min.1, max.1))) {
+            return;
+        }
+
+        let edits: Vec<_> = walls((min, max)).into_iter()
+                                             .map(|xyz| (xyz, Value::Block(block.clone())))
+                                             .collect();
+
+        if self.apply_bulk(id, world, &edits) {
+            self.reply(id, &format!("Set {} blocks.", edits.len()));
+        }
+    }
+
+    pub(super) fn handle_copy(&mut self, id: client::Id, world: &mut World) {
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        if !self.check_volume(id, volume((min, max))) {
+            return;
+        }
+
+        let origin = match self.block_position_of(id) {
+            Some(xyz) => xyz,
+            None => return,
+        };
+
+        let contents = match self.check(id, contents(world, (min, max))) {
+            Some(contents) => contents,
+            None => return,
+        };
+
+        let clipboard: Option<Clipboard> = contents.into_iter()
+                                                   .map(|(xyz, v)| Some((offset(xyz, origin, -1)?, v)))
+                                                   .collect();
+        let clipboard = match clipboard {
+            Some(clipboard) => clipboard,
+            None => {
+                self.reply(id, "You are too far from the selection to copy it.");
+                return;
+            },
+        };
+        let count = clipboard.len();
+
+        if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            c.set_clipboard(clipboard);
+        }
+
+        self.reply(id, &format!("Copied {} blocks, signs and lights. Use /paste to place them \
+                                 where you stand.", count));
+    }
+
+    pub(super) fn handle_paste(&mut self, id: client::Id, world: &mut World) {
+        let origin = match self.block_position_of(id) {
+            Some(xyz) => xyz,
+            None => return,
+        };
+
+        let edits: Option<Vec<_>> = match self.metrics.lock_clients(&self.clients).get(&id) {
+            Some(c) => c.clipboard().iter()
+                                    .map(|&(xyz, ref v)| Some((offset(xyz, origin, 1)?, v.clone())))
+                                    .filter(|e| e.as_ref().is_none_or(|&(xyz, _)| editable_height(xyz.1)))
+                                    .collect(),
+            None => return,
+        };
+        let edits = match edits {
+            Some(edits) => edits,
+            None => {
+                self.reply(id, "That paste would reach past the edge of the world.");
+                return;
+            },
+        };
+
+        if edits.is_empty() {
+            self.reply(id, "There is nothing to paste. Use /copy first.");
+            return;
+        }
+
+        if !self.check_volume(id, edits.len() as i64) {
+            return;
+        }
+
+        let edits = match self.check(id, without_signs_on_air(world, edits)) {
+            Some(edits) => edits,
+            None => return,
+        };
+
+        if self.apply_bulk(id, world, &edits) {
+            self.reply(id, &format!("Pasted {} blocks, signs and lights.", edits.len()));
+        }
+    }
+
+    pub(super) fn handle_move(&mut self, id: client::Id, args: &[&str], world: &mut World) {
+        let d = match parse_xyz(args) {
+            Some(d) => d,
+            None => {
+                self.reply(id, "Usage: /move <dx> <dy> <dz>");
+                return;
+            },
+        };
+
+        let (min, max) = match self.selection_of(id) {
+            Some(corners) => corners,
+            None => return,
+        };
+
+        if !self.check_volume(id, volume((min, max))) {
+            return;
+        }
+
+        // Every position in the selection lies between its corners, so it can
+        // be moved if they can.
+        let (new_min, new_max) = match (offset(min, d, 1), offset(max, d, 1)) {
+            (Some(new_min), Some(new_max)) => (new_min, new_max),
+            _ => {
+                self.reply(id, "That move would reach past the edge of the world.");
+                return;
+            },
+        };
+
+        let contents = match self.check(id, contents(world, (min, max))) {
+            Some(contents) => contents,
+            None => return,
+        };
+
+        // The old positions are cleared first, so that the moved contents
+        // may overlap them.
+        let mut edits = Vec::new();
+        for &(xyz, ref v) in &contents {
+            match *v {
+                Value::Block(_) => edits.push((xyz, Value::Block(Block(0)))),
+                Value::Light(_) => edits.push((xyz, Value::Light(Light(0)))),
+                // Signs are removed with their blocks.
+                Value::Sign(..) => {},
+            }
+        }
+
+        edits.extend(contents.into_iter()
+                             .filter_map(|(xyz, v)| Some((offset(xyz, d, 1)?, v)))
+                             .filter(|&(xyz, _)| editable_height(xyz.1)));
+
+        let edits = match self.check(id, without_signs_on_air(world, edits)) {
+            Some(edits) => edits,
+            None => return,
+        };
+
+        if self.apply_bulk(id, world, &edits) {
+            if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                c.set_selection_corner(0, new_min);
+                c.set_selection_corner(1, new_max);
+            }
+
+            self.reply(id, &format!("Moved the selection by {:?}.", d));
+        }
+    }
+
+    /// Returns the lowest and highest corners of a client's selection. If a corner
+    /// is missing, the client is told.
+    pub(super) fn selection_of(&self, id: client::Id) -> Option<Corners> {
+        let selection = self.metrics.lock_clients(&self.clients).get(&id).map(|c| c.selection());
+
+        match selection {
+            Some([Some(a), Some(b)]) => Some(cuboid(a, b)),
+            Some(_) => {
+                self.reply(id, "Select two corners first, with /pos1 and /pos2 or /wand.");
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// Checks that a client's role allows a bulk edit of this size, and tells it if it doesn't.
+    pub(super) fn check_volume(&self, id: client::Id, volume: i64) -> bool {
+        let max = max_volume(self.role_of(id));
+
+        if volume <= max {
+            true
+        } else {
+            self.reply(id, &format!("That is {} blocks, but you may only edit {} at once.", volume, max));
+            false
+        }
+    }
+
+    /// Applies a bulk edit for a client, if it may edit every position. Admins may edit anywhere.
+    /// # Return value
+    /// Returns true if the edit was applied.
+    pub(super) fn apply_bulk(&self, id: client::Id, world: &mut World, edits: &[((i32, i32, i32), Value)]) -> bool {
+        let ip = match self.addr_of(id) {
+            Some(ip) => ip,
+            None => return false,
+        };
+
+        if self.role_of(id) < Role::Admin {
+            let denied = edits.iter().any(|&(xyz, ref v)| {
+                let flag = match *v {
+                    Value::Block(_) => Flag::Build,
+                    Value::Sign(..) => Flag::Sign,
+                    Value::Light(_) => Flag::Light,
+                };
+
+                !world.regions().allows(&ip, xyz, flag)
+            });
+
+            if denied {
+                self.reply(id, "You may not edit this area.");
+                return false;
+            }
+        }
+
+        let mut clients = self.metrics.lock_clients(&self.clients);
+        let author = match clients.get(&id) {
+            Some(c) => c.author(),
+            None => return false,
+        };
+
+        let result = edit::apply(world, &mut clients, Some(&author), edits);
+        drop(clients);
+
+        self.check(id, result).is_some()
+    }
+}
+
+/// Returns the blocks, then the signs, then the lights in a cuboid.
+fn contents(world: &World, (min, max): Corners) -> Result<Edits, WorldError> {
+    let mut contents: Vec<_> = world.blocks_in(min, max)?.map(|(xyz, b)| (xyz, Value::Block(b))).collect();
+    contents.extend(world.signs_in(min, max)?.map(|(xyz, (face, s))| (xyz, Value::Sign(face, s))));
+    contents.extend(world.lights_in(min, max)?.map(|(xyz, l)| (xyz, Value::Light(l))));
+
+    Ok(contents)
+}
+
+/// Leaves out the signs that would end up on air, either because the edits
+/// mine their block or because it is already mined.
+pub(super) fn without_signs_on_air(world: &World, edits: Edits) -> Result<Edits, WorldError> {
+    let mut air = HashMap::new();
+    for &(xyz, ref v) in &edits {
+        if let Value::Block(ref b) = *v {
+            air.insert(xyz, b.is_air());
+        }
+    }
+
+    let mut kept = Vec::with_capacity(edits.len());
+    for (xyz, v) in edits {
+        if let Value::Sign(..) = v {
+            let on_air = match air.get(&xyz) {
+                Some(&air) => air,
+                None => world.block_at(xyz)?.is_some_and(|b| b.is_air()),
+            };
+
+            if on_air {
+                continue;
+            }
+        }
+
+        kept.push((xyz, v));
+    }
+
+    Ok(kept)
+}
+
+/// Returns every editable position in a cuboid.
+fn positions((min, max): Corners) -> Vec<(i32, i32, i32)> {
+    let mut positions = Vec::new();
+
+    for y in min.1.max(1)..=max.1.min(WORLD_HEIGHT - 1) {
+        for z in min.2..=max.2 {
+            for x in min.0..=max.0 {
+                positions.push((x, y, z));
+            }
+        }
+    }
+
+    positions
+}
+
+/// Returns every editable position on the four vertical sides of a cuboid.
+fn walls((min, max): Corners) -> Vec<(i32, i32, i32)> {
+    let mut positions = Vec::new();
+
+    for y in min.1.max(1)..=max.1.min(WORLD_HEIGHT - 1) {
+        for x in min.0..=max.0 {
+            positions.push((x, y, min.2));
+            if max.2 != min.2 {
+                positions.push((x, y, max.2));
+            }
+        }
+
+        for z in min.2 + 1..max.2 {
+            positions.push((min.0, y, z));
+            if max.0 != min.0 {
+                positions.push((max.0, y, z));
+            }
+        }
+    }
+
+    positions
+}
+
+/// Returns the number of blocks along one side of a cuboid. It is measured in
+/// i64, since the corners may be further apart than an i32 can count.
+fn side(min: i32, max: i32) -> i64 {
+    max as i64 - min as i64 + 1
+}
+
+/// Returns the number of blocks in a cuboid. The product saturates, so that
+/// huge selections can't overflow.
+pub(super) fn volume((min, max): Corners) -> i64 {
+    side(min.0, max.0).saturating_mul(side(min.1, max.1))
+                      .saturating_mul(side(min.2, max.2))
+}
+
+/// Adds `d` times `sign` to a position.
+/// # Return value
+/// Returns None if the result is past the edge of the world.
+fn offset(xyz: (i32, i32, i32), d: (i32, i32, i32), sign: i32) -> Option<(i32, i32, i32)> {
+    let add = |a: i32, b: i32| b.checked_mul(sign).and_then(|b| a.checked_add(b));
+
+    Some((add(xyz.0, d.0)?, add(xyz.1, d.1)?, add(xyz.2, d.2)?))
+}
+
+/// Parses a block that players may set, by name or ID.
+fn parse_block(name: &str) -> Option<Block> {
+    block_type_by_name(name).map(|t| Block(t.id)).filter(|b| b.is_placeable())
+}
+
+fn parse_xyz(args: &[&str]) -> Option<(i32, i32, i32)> {
+    match *args {
+        [x, y, z] => Some((x.parse().ok()?, y.parse().ok()?, z.parse().ok()?)),
+        _ => None,
+    }
+}
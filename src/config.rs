@@ -0,0 +1,338 @@
+//! This module handles loading of server settings from the configuration file.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use log::LevelFilter;
+
+const FILE: &str = "config.txt";
+
+/// The configuration file written when there is none, holding the default settings.
+const DEFAULT: &str = "\
+# Settings for craft_server. Each line has the form `key = value`.
+
+# Seconds between writes of queued world changes to the database, at least 1.
+database.flush_interval = 5
+
+# Number of queued world changes that causes a write before the interval is over.
+database.flush_threshold = 1000
+
+# How carefully SQLite waits for writes to reach the disk: off, normal, full or extra.
+database.synchronous = normal
+
+# Directory that backups of the world are written to.
+backup.directory = backups
+
+# Minutes between scheduled backups, or 0 to only back up with /backup.
+backup.interval = 60
+
+# Number of backups to keep. Older ones are deleted, and 0 keeps every backup.
+backup.keep = 24
+
+# Port on localhost that craft_admin connects to, to run commands as an admin.
+rcon.port = 4081
+
+# Password that craft_admin must send. Remote admin is disabled while it is empty.
+rcon.password =
+
+# Address to answer HTTP requests for /health, /status and /metrics on, like 127.0.0.1:8080.
+# The HTTP listener is disabled while it is empty.
+http.address =
+
+# Least severe messages to log: off, error, warn, info, debug or trace.
+log.level = info
+
+# Levels for single modules, overriding log.level, like `server = debug, world = warn`.
+log.modules =
+
+# File to also write the log to. Nothing is written to a file while it is empty.
+log.file =
+
+# Size in megabytes at which the log file is renamed to <file>.1, and a new one is started.
+log.max_size = 10
+
+# Number of old log files to keep.
+log.keep = 5
+";
+
+/// How carefully SQLite waits for writes to reach the disk.
+/// See https://www.sqlite.org/pragma.html#pragma_synchronous
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synchronous {
+    /// Never wait. A power loss can corrupt the database.
+    Off,
+
+    /// Wait at the most critical moments. In WAL mode, a power loss can only
+    /// lose the most recent writes.
+    Normal,
+
+    /// Wait for every write.
+    Full,
+
+    /// Like `Full`, and also wait for the journal to be deleted.
+    Extra,
+}
+
+impl Display for Synchronous {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Synchronous::Off => "off",
+            Synchronous::Normal => "normal",
+            Synchronous::Full => "full",
+            Synchronous::Extra => "extra",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Synchronous {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Synchronous, ()> {
+        match s {
+            "off" => Ok(Synchronous::Off),
+            "normal" => Ok(Synchronous::Normal),
+            "full" => Ok(Synchronous::Full),
+            "extra" => Ok(Synchronous::Extra),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Settings for writing the world to its database.
+#[derive(Clone, Debug)]
+pub struct DatabaseConfig {
+    /// The longest time that world changes wait before they are written.
+    pub flush_interval: Duration,
+
+    /// The number of waiting world changes that causes them to be written at once.
+    pub flush_threshold: usize,
+
+    /// How carefully SQLite waits for writes to reach the disk.
+    pub synchronous: Synchronous,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> DatabaseConfig {
+        DatabaseConfig {
+            flush_interval: Duration::from_secs(5),
+            flush_threshold: 1000,
+            synchronous: Synchronous::Normal,
+        }
+    }
+}
+
+/// Settings for backing up the world.
+#[derive(Clone, Debug)]
+pub struct BackupConfig {
+    /// The directory that backups are written to.
+    pub directory: PathBuf,
+
+    /// The time between scheduled backups, if backups are scheduled.
+    pub interval: Option<Duration>,
+
+    /// The number of backups to keep, if older ones are deleted.
+    pub keep: Option<usize>,
+}
+
+impl Default for BackupConfig {
+    fn default() -> BackupConfig {
+        BackupConfig {
+            directory: PathBuf::from("backups"),
+            interval: Some(Duration::from_secs(60 * 60)),
+            keep: Some(24),
+        }
+    }
+}
+
+/// Settings for running commands remotely with craft_admin.
+#[derive(Clone, Debug)]
+pub struct RconConfig {
+    /// The port on localhost to listen on.
+    pub port: u16,
+
+    /// The password that connections must send, if remote admin is enabled.
+    pub password: Option<String>,
+}
+
+impl Default for RconConfig {
+    fn default() -> RconConfig {
+        RconConfig {
+            port: 4081,
+            password: None,
+        }
+    }
+}
+
+/// Settings for the HTTP listener that reports the server's health and status.
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    /// The address to listen on, if the listener is enabled.
+    pub address: Option<SocketAddr>,
+}
+
+/// Settings for logging what the server does.
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// The least severe messages that are logged.
+    pub level: LevelFilter,
+
+    /// The levels of single modules, which override `level` within them.
+    pub modules: Vec<(String, LevelFilter)>,
+
+    /// The file that the log is also written to, if any.
+    pub file: Option<PathBuf>,
+
+    /// The size in bytes at which the log file is rotated.
+    pub max_size: u64,
+
+    /// The number of rotated log files to keep.
+    pub keep: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            level: LevelFilter::Info,
+            modules: Vec::new(),
+            file: None,
+            max_size: 10 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+/// The server's settings.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Settings for writing the world to its database.
+    pub database: DatabaseConfig,
+
+    /// Settings for backing up the world.
+    pub backup: BackupConfig,
+
+    /// Settings for running commands remotely.
+    pub rcon: RconConfig,
+
+    /// Settings for reporting the server's health and status.
+    pub http: HttpConfig,
+
+    /// Settings for logging.
+    pub log: LogConfig,
+}
+
+impl Config {
+    /// Loads the settings from the configuration file.
+    /// # Note
+    /// If the file doesn't exist, it will be created with the default settings.
+    /// Settings missing from the file keep their default values.
+    /// # Panics
+    /// This function will panic if it can't create or read the configuration file,
+    /// or if a setting has an invalid value.
+    pub fn load() -> Config {
+        if fs::metadata(FILE).is_err() {
+            fs::write(FILE, DEFAULT).unwrap();
+        }
+
+        let values = read_values(File::open(FILE).unwrap());
+        let mut config = Config::default();
+
+        let db = &mut config.database;
+        if let Some(secs) = parse::<u64>(&values, "database.flush_interval") {
+            // The database thread would wake up without pause if it didn't wait at all.
+            assert!(secs > 0, "Can't read {}: invalid value for database.flush_interval", FILE);
+            db.flush_interval = Duration::from_secs(secs);
+        }
+        if let Some(n) = parse(&values, "database.flush_threshold") {
+            db.flush_threshold = n;
+        }
+        if let Some(s) = parse(&values, "database.synchronous") {
+            db.synchronous = s;
+        }
+
+        let backup = &mut config.backup;
+        if let Some(dir) = parse(&values, "backup.directory") {
+            backup.directory = dir;
+        }
+        if let Some(mins) = parse::<u64>(&values, "backup.interval") {
+            backup.interval = if mins == 0 { None } else { Some(Duration::from_secs(mins * 60)) };
+        }
+        if let Some(n) = parse::<usize>(&values, "backup.keep") {
+            backup.keep = if n == 0 { None } else { Some(n) };
+        }
+
+        let rcon = &mut config.rcon;
+        if let Some(port) = parse(&values, "rcon.port") {
+            rcon.port = port;
+        }
+        if let Some(password) = parse::<String>(&values, "rcon.password") {
+            rcon.password = if password.is_empty() { None } else { Some(password) };
+        }
+
+        if values.get("http.address").is_some_and(|a| !a.is_empty()) {
+            config.http.address = parse(&values, "http.address");
+        }
+
+        let log = &mut config.log;
+        if let Some(level) = parse(&values, "log.level") {
+            log.level = level;
+        }
+        if let Some(modules) = values.get("log.modules") {
+            log.modules = modules.split(',').filter(|m| !m.trim().is_empty()).map(|m| {
+                let mut pieces = m.splitn(2, '=');
+                let module = pieces.next().unwrap().trim().to_string();
+
+                match pieces.next().map(|l| l.trim().parse()) {
+                    Some(Ok(level)) => (module, level),
+                    _ => panic!("Can't read {}: invalid value for log.modules", FILE),
+                }
+            }).collect();
+        }
+        if values.get("log.file").is_some_and(|f| !f.is_empty()) {
+            log.file = parse(&values, "log.file");
+        }
+        if let Some(mb) = parse::<u64>(&values, "log.max_size") {
+            log.max_size = mb * 1024 * 1024;
+        }
+        if let Some(n) = parse(&values, "log.keep") {
+            log.keep = n;
+        }
+
+        config
+    }
+}
+
+fn read_values(file: File) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+
+    for i in BufReader::new(file).lines() {
+        let i = i.unwrap();
+        let i = i.trim();
+
+        if i.is_empty() || i.starts_with('#') {
+            continue;
+        }
+
+        let mut pieces = i.splitn(2, '=');
+        let key = pieces.next().unwrap().trim();
+        let value = pieces.next();
+        assert!(value.is_some(), "Can't read {}: {}", FILE, i);
+
+        values.insert(key.to_string(), value.unwrap().trim().to_string());
+    }
+
+    values
+}
+
+fn parse<T: FromStr>(values: &HashMap<String, String>, key: &str) -> Option<T> {
+    values.get(key).map(|v| match v.parse() {
+        Ok(v) => v,
+        Err(_) => panic!("Can't read {}: invalid value for {}", FILE, key),
+    })
+}
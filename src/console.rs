@@ -0,0 +1,144 @@
+//! Consoles, which send commands to the server outside of the game.
+//!
+//! Consoles accept the same commands as chat, with or without the leading `/`,
+//! and have the rights of an admin. Each console has an ID that its commands are
+//! sent with, and receives the replies to them. The server's own console reads
+//! commands from standard input and prints the replies to standard output.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use client;
+use event::{Event, IdEvent};
+
+/// What a console receives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// A reply to a command.
+    Line(String),
+
+    /// The command has been handled. Some commands still reply later.
+    Done,
+}
+
+/// The consoles connected to the server.
+#[derive(Clone, Default)]
+pub struct Consoles {
+    inner: Arc<Mutex<ConsoleMap>>,
+}
+
+#[derive(Default)]
+struct ConsoleMap {
+    outputs: HashMap<client::Id, mpsc::Sender<Output>>,
+    opened: client::Id,
+}
+
+impl Consoles {
+    /// Creates an empty set of consoles.
+    pub fn new() -> Consoles {
+        Consoles::default()
+    }
+
+    /// Adds a console.
+    /// # Return value
+    /// Returns the ID for the console's commands, and the receiver of its output.
+    /// Console IDs count down from the largest ID, and clients count up from 1.
+    pub fn open(&self) -> (client::Id, mpsc::Receiver<Output>) {
+        let (tx, rx) = mpsc::channel();
+        let mut map = self.inner.lock().unwrap();
+
+        map.opened += 1;
+        let id = client::Id::MAX - map.opened;
+        map.outputs.insert(id, tx);
+
+        (id, rx)
+    }
+
+    /// Removes a console. Its output ends.
+    pub fn close(&self, id: client::Id) {
+        self.inner.lock().unwrap().outputs.remove(&id);
+    }
+
+    /// Returns true if commands with this ID come from a console.
+    pub fn contains(&self, id: client::Id) -> bool {
+        self.inner.lock().unwrap().outputs.contains_key(&id)
+    }
+
+    /// Sends a reply to a console.
+    /// # Return value
+    /// Returns false if there is no console with this ID.
+    pub fn print(&self, id: client::Id, text: &str) -> bool {
+        self.send(id, Output::Line(text.to_string()))
+    }
+
+    /// Tells a console that its command has been handled.
+    pub fn done(&self, id: client::Id) {
+        self.send(id, Output::Done);
+    }
+
+    fn send(&self, id: client::Id, output: Output) -> bool {
+        match self.inner.lock().unwrap().outputs.get(&id) {
+            Some(tx) => {
+                let _ = tx.send(output);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Starts the server's own console, which reads commands from standard input and
+/// sends them to the event thread. The console stops reading when standard input
+/// is closed, but still prints the replies to the commands it sent.
+pub fn run(tx: mpsc::Sender<IdEvent>, consoles: &Consoles) {
+    let (id, output) = consoles.open();
+
+    thread::spawn(move || {
+        for o in output {
+            if let Output::Line(text) = o {
+                println!("{}", text);
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if let Some(command) = command_of(&line) {
+                if !send_command(&tx, id, command) {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Returns the command in a line typed on a console, without the leading `/`,
+/// or None if the line is empty.
+pub fn command_of(line: &str) -> Option<&str> {
+    let command = line.trim();
+    let command = command.strip_prefix('/').unwrap_or(command);
+
+    if command.is_empty() {
+        None
+    } else {
+        Some(command)
+    }
+}
+
+/// Sends a command from a console to the event thread.
+/// # Return value
+/// Returns false if the event thread has stopped.
+pub fn send_command(tx: &mpsc::Sender<IdEvent>, id: client::Id, command: &str) -> bool {
+    let peer = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+
+    tx.send(IdEvent { id, peer, event: Event::Console(command.to_string()) }).is_ok()
+}
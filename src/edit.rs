@@ -0,0 +1,95 @@
+//! This module applies edits to the world and tells every client about them.
+
+use std::collections::{HashMap, HashSet};
+use client::{self, Client};
+use world::{Author, Block, border_chunks, chunked, Sign, Value, World, WorldError};
+
+/// Applies a batch of edits to the world and sends them to all clients.
+/// Every affected chunk is redrawn once, after all edits have been sent.
+///
+/// The edits are written to the database in one transaction. If an author is
+/// given, they are recorded in the world's history.
+/// # Return value
+/// If an edit fails, the edits after it are not applied and the error is returned.
+/// The edits before it stay applied.
+pub fn apply(world: &mut World,
+             clients: &mut HashMap<client::Id, Client>,
+             author: Option<&Author>,
+             edits: &[((i32, i32, i32), Value)]) -> Result<(), WorldError> {
+    let mut redraw = HashSet::new();
+    let mut result = Ok(());
+
+    world.begin_batch();
+
+    for &(xyz, ref value) in edits {
+        result = apply_one(world, clients, author, xyz, value, &mut redraw);
+
+        if result.is_err() {
+            break;
+        }
+    }
+
+    let sent = world.end_batch();
+
+    for pq in redraw {
+        for c in clients.values_mut() {
+            c.broadcast_redraw(pq);
+        }
+    }
+
+    result.and(sent)
+}
+
+fn apply_one(world: &mut World,
+             clients: &mut HashMap<client::Id, Client>,
+             author: Option<&Author>,
+             xyz: (i32, i32, i32),
+             value: &Value,
+             redraw: &mut HashSet<(i32, i32)>) -> Result<(), WorldError> {
+    let pq = (chunked(xyz.0), chunked(xyz.2));
+
+    match *value {
+        Value::Block(ref block) => {
+            // Signs are removed with their block.
+            let removed = if block.is_air() { world.signs_at(xyz)? } else { Vec::new() };
+
+            world.set_block(xyz, block.clone(), author)?;
+            for c in clients.values_mut() {
+                c.broadcast_block((xyz, block), pq);
+            }
+
+            let cleared = Sign(String::new());
+            for (face, _) in removed {
+                for c in clients.values_mut() {
+                    c.broadcast_sign(xyz, face, &cleared);
+                }
+            }
+
+            // Clients keep a negated copy of the block in chunks that overlap it.
+            let copy = Block(-block.0);
+            for pq in border_chunks(xyz.0, xyz.2) {
+                for c in clients.values_mut() {
+                    c.broadcast_block((xyz, &copy), pq);
+                }
+
+                redraw.insert(pq);
+            }
+        },
+        Value::Sign(face, ref sign) => {
+            world.set_sign(xyz, face, sign.clone(), author)?;
+            for c in clients.values_mut() {
+                c.broadcast_sign(xyz, face, sign);
+            }
+        },
+        Value::Light(ref light) => {
+            world.set_light(xyz, light.clone(), author)?;
+            for c in clients.values_mut() {
+                c.broadcast_light((xyz, light), pq);
+            }
+        },
+    }
+
+    redraw.insert(pq);
+
+    Ok(())
+}
@@ -6,6 +6,7 @@ use std::net::SocketAddr;
 use std::num::{ParseFloatError, ParseIntError};
 use std::fmt::{self, Display};
 use client;
+use world::{within_world, CHUNK_SIZE, WORLD_RADIUS};
 
 /// A struct that can store both events and their senders.
 #[derive(Debug)]
@@ -27,6 +28,9 @@ pub enum Event {
     Empty,
     */
 
+    /// Informs the server event thread that the client has joined.
+    Join,
+
     /// Informs the server event thread that the client has left.
     Disconnected,
 
@@ -47,6 +51,9 @@ pub enum Event {
 
     /// Represents a light toggled on a client.
     Light(LightEvent),
+
+    /// Represents a command typed on the server console.
+    Console(String),
 }
 
 /// Describes errors that occur parsing messages.
@@ -56,6 +63,7 @@ pub enum MessageParseError {
      IntError(ParseIntError),
      FloatError(ParseFloatError),
      EmptyMessageError,
+     OutsideWorld,
 }
 
 impl Display for MessageParseError {
@@ -71,6 +79,7 @@ impl Error for MessageParseError {
             MessageParseError::IntError(ref e) => e.description(),
             MessageParseError::FloatError(ref e) => e.description(),
             MessageParseError::EmptyMessageError => "The message had no content",
+            MessageParseError::OutsideWorld => "The message referred to a position outside the world",
         }
     }
 
@@ -80,6 +89,7 @@ impl Error for MessageParseError {
             MessageParseError::IntError(ref e) => Some(e),
             MessageParseError::FloatError(ref e) => Some(e),
             MessageParseError::EmptyMessageError => None,
+            MessageParseError::OutsideWorld => None,
         }
     }
 }
@@ -132,7 +142,7 @@ impl PositionEvent {
     }
 
     fn warn_invalid() {
-        println!("Warning: invalid position packet.");
+        warn!("Invalid position packet.");
     }
 }
 
@@ -171,6 +181,10 @@ impl BlockEvent {
         }
 
         match Self::parse_all(&pieces) {
+            Ok(ref v) if !within_world(v.x, v.z) => {
+                Self::warn_invalid();
+                Err(MessageParseError::OutsideWorld)
+            },
             Ok(v) => Ok(v),
             Err(e) => {
                 Self::warn_invalid();
@@ -194,7 +208,7 @@ impl BlockEvent {
     }
 
     fn warn_invalid() {
-        println!("Warning: invalid block packet.");
+        warn!("Invalid block packet.");
     }
 }
 
@@ -217,6 +231,10 @@ impl ChunkRequestEvent {
         }
 
         match Self::parse_all(&pieces) {
+            Ok(ref v) if !chunk_within_world(v.p) || !chunk_within_world(v.q) => {
+                Self::warn_invalid();
+                Err(MessageParseError::OutsideWorld)
+            },
             Ok(v) => Ok(v),
             Err(e) => {
                 Self::warn_invalid();
@@ -238,10 +256,17 @@ impl ChunkRequestEvent {
     }
 
     fn warn_invalid() {
-        println!("Warning: invalid chunk data request packet.");
+        warn!("Invalid chunk data request packet.");
     }
 }
 
+/// Returns true if a chunk on one axis holds or borders blocks within the world.
+fn chunk_within_world(p: i32) -> bool {
+    let limit = WORLD_RADIUS / CHUNK_SIZE as i32 + 1;
+
+    (-limit..=limit).contains(&p)
+}
+
 /// Corresponds to `S` sign place messages.
 #[derive(Debug)]
 pub struct SignEvent {
@@ -263,6 +288,10 @@ impl SignEvent {
         }
 
         match Self::parse_all(&pieces) {
+            Ok(ref v) if !within_world(v.x, v.z) => {
+                Self::warn_invalid();
+                Err(MessageParseError::OutsideWorld)
+            },
             Ok(v) => Ok(v),
             Err(e) => {
                 Self::warn_invalid();
@@ -288,7 +317,7 @@ impl SignEvent {
     }
 
     fn warn_invalid() {
-        println!("Warning: invalid sign packet.");
+        warn!("Invalid sign packet.");
     }
 }
 
@@ -312,6 +341,10 @@ impl LightEvent {
         }
 
         match Self::parse_all(&pieces) {
+            Ok(ref v) if !within_world(v.x, v.z) => {
+                Self::warn_invalid();
+                Err(MessageParseError::OutsideWorld)
+            },
             Ok(v) => Ok(v),
             Err(e) => {
                 Self::warn_invalid();
@@ -335,6 +368,6 @@ impl LightEvent {
     }
 
     fn warn_invalid() {
-        println!("Warning: invalid light packet.");
+        warn!("Invalid light packet.");
     }
 }
@@ -0,0 +1,98 @@
+//! Tracks whether the server's threads are still running.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use world::WorldMonitor;
+
+/// How long the event thread may go without handling an event or timeout before
+/// it counts as stuck. Large bulk edits can take several seconds.
+const EVENT_THREAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shows that a thread is running. The thread beats it regularly, and it stops
+/// when the thread ends, even by panicking.
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    last: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Heartbeat {
+    /// Creates a heartbeat that has just beaten.
+    pub fn new() -> Heartbeat {
+        Heartbeat {
+            last: Arc::new(Mutex::new(Some(Instant::now()))),
+        }
+    }
+
+    /// Records that the thread is still running.
+    pub fn beat(&self) {
+        *self.last.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Returns a guard that stops the heartbeat when it is dropped. The thread
+    /// should hold it for as long as it runs.
+    pub fn guard(&self) -> HeartbeatGuard {
+        HeartbeatGuard(self.clone())
+    }
+
+    /// Returns true if the heartbeat has not stopped, and has beaten within `timeout` if one is given.
+    pub fn is_alive(&self, timeout: Option<Duration>) -> bool {
+        // A thread that panicked while beating has stopped.
+        match self.last.lock() {
+            Ok(last) => match (*last, timeout) {
+                (Some(at), Some(timeout)) => at.elapsed() <= timeout,
+                (Some(_), None) => true,
+                (None, _) => false,
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Heartbeat {
+        Heartbeat::new()
+    }
+}
+
+/// Stops a heartbeat when dropped.
+pub struct HeartbeatGuard(Heartbeat);
+
+impl Drop for HeartbeatGuard {
+    fn drop(&mut self) {
+        if let Ok(mut last) = self.0.last.lock() {
+            *last = None;
+        }
+    }
+}
+
+/// The threads that must run for the server to be healthy.
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// The thread that accepts connections from players.
+    pub listener: Heartbeat,
+
+    /// The thread that handles the events from players.
+    pub event_thread: Heartbeat,
+
+    /// The world, whose database thread writes changes.
+    pub world: WorldMonitor,
+}
+
+impl Health {
+    /// Returns the names of the threads that are not running, or that are stuck.
+    pub fn failing(&self) -> Vec<&'static str> {
+        let mut failing = Vec::new();
+
+        if !self.listener.is_alive(None) {
+            failing.push("listener");
+        }
+        if !self.event_thread.is_alive(Some(EVENT_THREAD_TIMEOUT)) {
+            failing.push("event thread");
+        }
+        if !self.world.is_database_running() {
+            failing.push("database thread");
+        }
+
+        failing
+    }
+}
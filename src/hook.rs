@@ -0,0 +1,171 @@
+//! Hooks that extend what the server does with the events from clients and consoles.
+//!
+//! A hook implements `ServerHook` and is registered with `ServerBuilder::hook`. Its
+//! callbacks run on the event thread before the server handles each event, in the
+//! order the hooks were registered. A callback may change the event it is given,
+//! and the server then handles the changed event. A callback may also cancel the
+//! event, in which case later hooks don't see it and the server doesn't handle it.
+//!
+//! Edits that are cancelled are undone on the client that made them. Hooks are
+//! called before edits are checked, so the server still rejects edits that a hook
+//! makes invalid, and edits in regions the player may not change.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use client::{self, Client};
+use console::Consoles;
+use edit;
+use event::{BlockEvent, ChunkRequestEvent, LightEvent, SignEvent, TalkEvent};
+use metrics::Metrics;
+use role::{Role, RoleManager};
+use world::{Value, World, WorldError};
+
+/// Whether an event goes on to later hooks and the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// The event is handled as usual.
+    Continue,
+
+    /// The event is dropped.
+    Cancel,
+}
+
+/// Callbacks for the events that the server handles. Every callback does nothing
+/// and continues by default, so hooks only implement those they need.
+pub trait ServerHook: Send {
+    /// Called when a client has joined. Cancelling disconnects it.
+    fn on_join(&mut self, _ctx: &mut HookContext, _id: client::Id) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called when a client leaves, while it is still connected as far as other
+    /// hooks can tell. Leaving can't be cancelled.
+    fn on_leave(&mut self, _ctx: &mut HookContext, _id: client::Id) {}
+
+    /// Called for a chat message that isn't a command.
+    fn on_chat(&mut self, _ctx: &mut HookContext, _id: client::Id, _ev: &mut TalkEvent) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called when a client places or mines a block.
+    fn on_block(&mut self, _ctx: &mut HookContext, _id: client::Id, _ev: &mut BlockEvent) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called when a client changes a sign.
+    fn on_sign(&mut self, _ctx: &mut HookContext, _id: client::Id, _ev: &mut SignEvent) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called when a client changes a light.
+    fn on_light(&mut self, _ctx: &mut HookContext, _id: client::Id, _ev: &mut LightEvent) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called when a client asks for a chunk. Cancelling leaves it unanswered.
+    fn on_chunk_request(&mut self,
+                        _ctx: &mut HookContext,
+                        _id: client::Id,
+                        _ev: &mut ChunkRequestEvent) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for a command from a client or a console, without the leading `/`.
+    /// Hooks can add commands by handling them and cancelling.
+    fn on_command(&mut self, _ctx: &mut HookContext, _id: client::Id, _command: &mut String) -> Flow {
+        Flow::Continue
+    }
+}
+
+/// What a hook may use while it handles an event.
+pub struct HookContext<'a> {
+    world: &'a mut World,
+    clients: &'a Mutex<HashMap<client::Id, Client>>,
+    roles: &'a Mutex<RoleManager>,
+    consoles: &'a Consoles,
+    metrics: &'a Metrics,
+}
+
+impl<'a> HookContext<'a> {
+    /// Creates a context for the hooks called by the event thread.
+    pub fn new(world: &'a mut World,
+               clients: &'a Mutex<HashMap<client::Id, Client>>,
+               roles: &'a Mutex<RoleManager>,
+               consoles: &'a Consoles,
+               metrics: &'a Metrics) -> HookContext<'a> {
+        HookContext {
+            world,
+            clients,
+            roles,
+            consoles,
+            metrics,
+        }
+    }
+
+    /// Returns the world, to read it or change it without telling clients.
+    pub fn world(&mut self) -> &mut World {
+        self.world
+    }
+
+    /// Changes the world and sends the changes to every client. The changes are
+    /// not recorded in the world's history.
+    pub fn edit(&mut self, edits: &[((i32, i32, i32), Value)]) -> Result<(), WorldError> {
+        let mut clients = self.metrics.lock_clients(self.clients);
+
+        edit::apply(self.world, &mut clients, None, edits)
+    }
+
+    /// Returns the IDs of the connected clients.
+    pub fn players(&self) -> Vec<client::Id> {
+        self.metrics.lock_clients(self.clients).keys().cloned().collect()
+    }
+
+    /// Returns the nickname of a connected client.
+    pub fn nick(&self, id: client::Id) -> Option<String> {
+        self.metrics.lock_clients(self.clients).get(&id).map(|c| c.nick().to_string())
+    }
+
+    /// Returns the position of a connected client, as (x, y, z, rx, ry).
+    pub fn position(&self, id: client::Id) -> Option<(f32, f32, f32, f32, f32)> {
+        self.metrics.lock_clients(self.clients).get(&id).map(|c| c.position())
+    }
+
+    /// Returns the role of a connected client. Consoles are admins.
+    pub fn role(&self, id: client::Id) -> Role {
+        if self.consoles.contains(id) {
+            return Role::Admin;
+        }
+
+        let addr = self.metrics.lock_clients(self.clients).get(&id).map(|c| *c.addr());
+
+        match addr {
+            Some(ip) => self.roles.lock().unwrap().get(&ip),
+            None => Role::Player,
+        }
+    }
+
+    /// Sends a chat message to one client, or prints it on a console.
+    pub fn tell(&self, id: client::Id, text: &str) {
+        if self.consoles.print(id, text) {
+            return;
+        }
+
+        if let Some(c) = self.metrics.lock_clients(self.clients).get_mut(&id) {
+            c.broadcast_talk(text);
+        }
+    }
+
+    /// Sends a chat message to every client.
+    pub fn broadcast(&self, text: &str) {
+        for c in self.metrics.lock_clients(self.clients).values_mut() {
+            c.broadcast_talk(text);
+        }
+    }
+
+    /// Tells a client why it is being removed, then disconnects it.
+    pub fn kick(&self, id: client::Id, reason: &str) {
+        if let Some(c) = self.metrics.lock_clients(self.clients).get_mut(&id) {
+            c.kick(reason);
+        }
+    }
+}
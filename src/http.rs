@@ -0,0 +1,152 @@
+//! An HTTP listener that reports the server's health and status, for monitoring.
+//!
+//! * `GET /health` answers `200 OK` while the listener, event and database threads
+//!   run, and `503 Service Unavailable` with the failing threads otherwise.
+//! * `GET /status` answers JSON with the online players and their positions, the
+//!   uptime in seconds, the time of day, the number of chunks in memory and the
+//!   number of database commands waiting to be written.
+//! * `GET /metrics` answers the server's metrics in the Prometheus text format.
+
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use client;
+use health::Health;
+use metrics::Metrics;
+use server::ServerTime;
+
+/// How long a connection may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Everything the listener reports on.
+#[derive(Clone)]
+pub struct StatusSource {
+    pub clients: Arc<Mutex<HashMap<client::Id, client::Client>>>,
+    pub daytime: ServerTime,
+    pub started: Instant,
+    pub health: Health,
+    pub metrics: Metrics,
+}
+
+/// Starts listening for HTTP requests on an address.
+pub fn run(address: SocketAddr, source: StatusSource) {
+    let listener = match TcpListener::bind(address) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Can't listen for HTTP on {}: {}", address, e);
+            return;
+        },
+    };
+
+    info!("Listening for HTTP on {}.", address);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            let source = source.clone();
+            thread::spawn(move || {
+                let _ = respond(stream, &source);
+            });
+        }
+    });
+}
+
+fn respond(mut stream: TcpStream, source: &StatusSource) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+
+    // The headers are read, but not used.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut pieces = request.split_whitespace();
+    let method = pieces.next().unwrap_or("");
+    let path = pieces.next().unwrap_or("");
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/health") => health(source),
+        ("GET", "/status") => ("200 OK", "application/json", status(source)),
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", metrics(source)),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string()),
+    };
+
+    write!(stream,
+           "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, content_type, body.len(), body)?;
+    stream.flush()
+}
+
+fn health(source: &StatusSource) -> (&'static str, &'static str, String) {
+    let failing = source.health.failing();
+
+    if failing.is_empty() {
+        ("200 OK", "text/plain", "OK\n".to_string())
+    } else {
+        ("503 Service Unavailable", "text/plain", format!("Not running: {}\n", failing.join(", ")))
+    }
+}
+
+fn status(source: &StatusSource) -> String {
+    let mut players: Vec<_> = source.clients.lock().unwrap().iter().map(|(id, c)| {
+        let (x, y, z, _, _) = c.position();
+        (*id, format!("{{\"id\":{},\"nick\":{},\"x\":{},\"y\":{},\"z\":{}}}",
+                      id, json_string(c.nick()), json_number(x), json_number(y), json_number(z)))
+    }).collect();
+    players.sort_by_key(|&(id, _)| id);
+
+    let players: Vec<_> = players.into_iter().map(|(_, json)| json).collect();
+
+    let world = &source.health.world;
+
+    format!("{{\"players\":[{}],\"uptime\":{},\"time\":{},\"loaded_chunks\":{},\"database_queue\":{},\
+             \"degraded\":{}}}\n",
+            players.join(","),
+            source.started.elapsed().as_secs(),
+            source.daytime.time(),
+            world.loaded_chunks(),
+            world.queued_commands(),
+            world.is_degraded())
+}
+
+fn metrics(source: &StatusSource) -> String {
+    let connections = source.clients.lock().unwrap().len();
+
+    source.metrics.render(connections, &source.health.world)
+}
+
+/// Writes a number for JSON, which has no infinities or NaN. Clients may send
+/// those as their position, so they are written as null.
+fn json_number(n: f32) -> String {
+    if n.is_finite() { n.to_string() } else { "null".to_string() }
+}
+
+/// Quotes a string for JSON.
+pub fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
@@ -1,12 +1,31 @@
 //! `craft_server` is an alternate server for Craft.
 
+extern crate flate2;
+#[macro_use]
+extern crate log;
+extern crate rhai;
 extern crate sqlite;
+extern crate sqlite3_sys;
 
-pub use server::Server;
+pub use server::{Server, ServerBuilder};
 
+pub mod audit;
 pub mod client;
 pub mod commands;
+pub mod config;
+pub mod console;
+pub mod edit;
 pub mod event;
+pub mod health;
+pub mod hook;
+pub mod http;
+pub mod limit;
+pub mod logging;
+pub mod metrics;
 pub mod nick;
+pub mod rcon;
+pub mod role;
+pub mod script;
+pub mod schematic;
 pub mod server;
 pub mod world;
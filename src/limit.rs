@@ -0,0 +1,119 @@
+//! This module implements the rate limiting applied to world edits made by clients.
+
+use std::time::Instant;
+
+/// The kinds of world edits that are rate limited separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditKind {
+    Block,
+    Sign,
+    Light,
+}
+
+/// The outcome of checking an edit against a client's limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The edit may be applied.
+    Allow,
+
+    /// The edit must be rejected.
+    Deny,
+
+    /// The edit must be rejected, and the client has exceeded its limits
+    /// so often that it should be kicked.
+    Kick,
+}
+
+/// A classic token bucket. Tokens are refilled continuously at `rate`
+/// per second, up to `capacity`.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f32,
+    rate: f32,
+    tokens: f32,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a new, full token bucket.
+    pub fn new(capacity: f32, rate: f32) -> TokenBucket {
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Attempts to take one token from the bucket.
+    /// # Return value
+    /// Returns true if a token was available.
+    pub fn take(&mut self) -> bool {
+        self.refill();
+
+        if self.tokens >= 1. {
+            self.tokens -= 1.;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        let elapsed = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+}
+
+/// Keeps track of the edit rate limits of a single client.
+#[derive(Debug)]
+pub struct EditLimiter {
+    blocks: TokenBucket,
+    signs: TokenBucket,
+    lights: TokenBucket,
+    violations: TokenBucket,
+}
+
+impl EditLimiter {
+    /// Creates a new limiter with the default limits.
+    ///
+    /// A client may place short bursts of blocks, signs and lights, after which
+    /// the edits are accepted at a steady rate. Every rejected edit counts as
+    /// a violation. Violations are forgiven slowly, and a client that runs out of
+    /// forgiveness should be kicked.
+    pub fn new() -> EditLimiter {
+        EditLimiter {
+            blocks: TokenBucket::new(40., 15.),
+            signs: TokenBucket::new(8., 1.),
+            lights: TokenBucket::new(20., 5.),
+            violations: TokenBucket::new(200., 2.),
+        }
+    }
+
+    /// Checks if an edit of the given kind is allowed right now.
+    pub fn check(&mut self, kind: EditKind) -> Verdict {
+        let allowed = match kind {
+            EditKind::Block => self.blocks.take(),
+            EditKind::Sign => self.signs.take(),
+            EditKind::Light => self.lights.take(),
+        };
+
+        if allowed {
+            Verdict::Allow
+        } else if self.violations.take() {
+            Verdict::Deny
+        } else {
+            Verdict::Kick
+        }
+    }
+}
+
+impl Default for EditLimiter {
+    fn default() -> EditLimiter {
+        EditLimiter::new()
+    }
+}
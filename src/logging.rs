@@ -0,0 +1,177 @@
+//! Logs what the server does, to the console and optionally to a file.
+//!
+//! Messages are logged with the macros of the `log` crate. Their level decides
+//! whether they are shown, by the `log.level` setting or the level of the module
+//! they come from. Fields given with a message, such as `client = id`, are written
+//! after it as `client=3`:
+//!
+//! ```text
+//! 20240131-235959 INFO  server: Kicking a client for editing too quickly. client=3 peer=10.0.0.5
+//! ```
+//!
+//! The log file is renamed to `<file>.1` when it grows past its maximum size, and
+//! older files move up to `<file>.2` and so on, until only the configured number is kept.
+
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::{self, kv, Level, LevelFilter, Log, Metadata, Record};
+use config::LogConfig;
+use world::{timestamp, unix_time};
+
+/// The prefix of the targets of messages from this crate, which is left out
+/// of module names.
+const CRATE_PREFIX: &str = "craft_server::";
+
+/// Starts logging with the given settings. Messages logged before this are dropped.
+/// # Note
+/// Only the first call has an effect.
+pub fn init(config: &LogConfig) {
+    let file = config.file.as_ref().and_then(|path| match LogFile::open(path, config) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            println!("Can't open the log file {}: {}", path.display(), e);
+            None
+        },
+    });
+
+    let logger = Logger {
+        level: config.level,
+        modules: config.modules.iter()
+                               .map(|(m, l)| (m.trim_start_matches(CRATE_PREFIX).to_string(), *l))
+                               .collect(),
+        file: Mutex::new(file),
+    };
+
+    let max = config.modules.iter().map(|(_, l)| *l).fold(config.level, |a, b| a.max(b));
+
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max);
+    }
+}
+
+struct Logger {
+    level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Logger {
+    /// Returns the level of the most specific module setting that covers a module,
+    /// or the default level.
+    fn level_of(&self, module: &str) -> LevelFilter {
+        self.modules.iter()
+                    .filter(|(m, _)| module == m || module.strip_prefix(m.as_str()).is_some_and(|r| r.starts_with("::")))
+                    .max_by_key(|(m, _)| m.len())
+                    .map_or(self.level, |(_, l)| *l)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_of(short_target(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut line = format!("{} {:<5} {}: {}",
+                               timestamp(unix_time()),
+                               record.level(),
+                               short_target(record.target()),
+                               record.args());
+
+        let _ = record.key_values().visit(&mut Fields(&mut line));
+
+        if record.level() <= Level::Warn {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            line.push('\n');
+            let _ = file.write(&line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+fn short_target(target: &str) -> &str {
+    target.strip_prefix(CRATE_PREFIX).unwrap_or(target)
+}
+
+/// Appends the fields of a message to its line.
+struct Fields<'l>(&'l mut String);
+
+impl<'l, 'kvs> kv::VisitSource<'kvs> for Fields<'l> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        write!(self.0, " {}={}", key, value).map_err(|_| kv::Error::msg("can't format a field"))
+    }
+}
+
+/// A log file that is rotated when it grows too large.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl LogFile {
+    fn open(path: &Path, config: &LogConfig) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size: config.max_size,
+            keep: config.keep,
+        })
+    }
+
+    fn write(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Moves every old log file up by one, drops the oldest, and starts a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.keep));
+
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+
+            fs::rename(&self.path, rotated(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
@@ -1,7 +1,217 @@
 extern crate craft_server;
 
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
 use craft_server::Server;
+use craft_server::config::Config;
+use craft_server::logging;
+use craft_server::schematic::{BlockMapping, Schematic};
+use craft_server::world::{editable_height, import_craft_db, ImportPolicy, restore, Value, World,
+                          WorldError};
+
+const USAGE: &str = "\
+Usage:
+    craft_server
+        Runs the server.
+    craft_server import-craft-db <file> [--offset x,z] [--policy overwrite|keep-existing|skip-conflicts]
+        Merges a Craft single-player world into world.db. Stop the server first.
+        Existing blocks are kept unless another policy is given.
+    craft_server restore <backup>
+        Replaces world.db with a backup. Stop the server first.
+        The replaced world is backed up to the backup directory.
+    craft_server schem export <file> <x1> <y1> <z1> <x2> <y2> <z2>
+        Saves the cuboid between two corners as a Sponge schematic.
+    craft_server schem import <file> <x> <y> <z>
+        Places a Sponge schematic with its lowest corner at a position. Stop the server first.
+        Block names are mapped to Craft's blocks as schematic_blocks.txt says.";
 
 fn main() {
-    Server::run();
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None => Server::run(),
+        Some("import-craft-db") => import(&args[1..]),
+        Some("restore") if args.len() == 2 => restore_backup(&args[1]),
+        Some("schem") if args.len() == 9 && args[1] == "export" => export_schematic(&args[2], &args[3..]),
+        Some("schem") if args.len() == 6 && args[1] == "import" => import_schematic(&args[2], &args[3..]),
+        Some(_) => usage(),
+    }
+}
+
+fn import(args: &[String]) {
+    let mut file = None;
+    let mut offset = (0, 0);
+    let mut policy = ImportPolicy::KeepExisting;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--offset" => {
+                offset = match args.next().and_then(|v| parse_offset(v)) {
+                    Some(offset) => offset,
+                    None => usage(),
+                };
+            },
+            "--policy" => {
+                policy = match args.next().and_then(|v| v.parse().ok()) {
+                    Some(policy) => policy,
+                    None => usage(),
+                };
+            },
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let file = match file {
+        Some(file) => file,
+        None => usage(),
+    };
+
+    if fs::metadata(file).is_err() {
+        println!("Can't import {}: the file doesn't exist", file);
+        process::exit(1);
+    }
+
+    logging::init(&Config::load().log);
+
+    println!("Importing {} with offset {},{} ({})...", file, offset.0, offset.1, policy);
+
+    match import_craft_db(file, offset, policy) {
+        Ok(counts) => println!("Done: {}.", counts),
+        Err(e) => {
+            println!("Can't import {}: {}", file, e);
+            process::exit(1);
+        },
+    }
+}
+
+fn restore_backup(file: &str) {
+    if fs::metadata(file).is_err() {
+        println!("Can't restore {}: the file doesn't exist", file);
+        process::exit(1);
+    }
+
+    let config = Config::load();
+
+    match restore(Path::new(file), &config.backup) {
+        Ok(previous) => println!("Restored {}. The replaced world was backed up to {}.",
+                                 file, previous.display()),
+        Err(e) => {
+            println!("Can't restore {}: {}", file, e);
+            process::exit(1);
+        },
+    }
+}
+
+fn export_schematic(file: &str, args: &[String]) {
+    let (a, b) = match (parse_xyz(&args[..3]), parse_xyz(&args[3..])) {
+        (Some(a), Some(b)) => (a, b),
+        _ => usage(),
+    };
+
+    let mapping = load_mapping();
+    let world = open_world();
+
+    // The schematic is placed by its lowest corner.
+    let schematic = match Schematic::from_world(&world, a, b, (a.0.min(b.0), a.1.min(b.1), a.2.min(b.2))) {
+        Ok(schematic) => schematic,
+        Err(e) => fail(&format!("Can't read the world: {}", e)),
+    };
+
+    match schematic.write(file, &mapping) {
+        Ok(()) => println!("Saved {} blocks and {} signs to {}.", schematic.blocks.len(), schematic.signs.len(), file),
+        Err(e) => fail(&format!("Can't save {}: {}", file, e)),
+    }
+}
+
+fn import_schematic(file: &str, args: &[String]) {
+    let origin = match parse_xyz(args) {
+        Some(origin) => origin,
+        None => usage(),
+    };
+
+    let mapping = load_mapping();
+    let mut schematic = match Schematic::read(file, &mapping) {
+        Ok(schematic) => schematic,
+        Err(e) => fail(&format!("Can't read {}: {}", file, e)),
+    };
+
+    if !schematic.unmapped.is_empty() {
+        println!("Leaving out blocks without a mapping: {}", schematic.unmapped.join(", "));
+    }
+
+    schematic.offset = (0, 0, 0);
+
+    let edits = match schematic.edits(origin) {
+        Some(edits) => edits,
+        None => fail(&format!("{} would reach past the edge of the world.", file)),
+    };
+
+    let mut world = open_world();
+    world.begin_batch();
+
+    let mut placed = 0;
+    for (xyz, v) in edits.into_iter().filter(|&(xyz, _)| editable_height(xyz.1)) {
+        let result = match v {
+            Value::Block(b) => world.set_block(xyz, b, None),
+            Value::Sign(face, s) => world.set_sign(xyz, face, s, None),
+            Value::Light(l) => world.set_light(xyz, l, None),
+        };
+
+        match result {
+            Ok(()) => placed += 1,
+            Err(WorldError::SignOnAir) => {},
+            Err(e) => fail(&format!("Can't change the world: {}", e)),
+        }
+    }
+
+    match world.close() {
+        Ok(()) => println!("Placed {} blocks and signs from {}.", placed, file),
+        Err(e) => fail(&format!("Can't save the world: {}", e)),
+    }
+}
+
+fn load_mapping() -> BlockMapping {
+    match BlockMapping::load() {
+        Ok(mapping) => mapping,
+        Err(e) => fail(&format!("Can't load the block mapping: {}", e)),
+    }
+}
+
+fn open_world() -> World {
+    let config = Config::load();
+    logging::init(&config.log);
+
+    match World::new(&config.database) {
+        Ok(world) => world,
+        Err(e) => fail(&format!("Can't load the world: {}", e)),
+    }
+}
+
+fn parse_xyz(args: &[String]) -> Option<(i32, i32, i32)> {
+    match *args {
+        [ref x, ref y, ref z] => Some((x.parse().ok()?, y.parse().ok()?, z.parse().ok()?)),
+        _ => None,
+    }
+}
+
+fn parse_offset(s: &str) -> Option<(i32, i32)> {
+    let mut pieces = s.splitn(2, ',');
+    let x = pieces.next()?.trim().parse().ok()?;
+    let z = pieces.next()?.trim().parse().ok()?;
+
+    Some((x, z))
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    process::exit(2);
 }
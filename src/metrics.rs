@@ -0,0 +1,187 @@
+//! Counts what the server does, to be read in the Prometheus text format.
+//!
+//! Counters only ever grow while the server runs. Durations are kept as histograms
+//! with fixed buckets, in seconds.
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use event::Event;
+use world::WorldMonitor;
+
+/// The names of the kinds of events, in the order they are counted in.
+const EVENT_KINDS: [&str; 9] = ["join", "disconnected", "position", "talk", "block", "chunk_request",
+                                "sign", "light", "console"];
+
+/// The types of messages sent to clients, by their first letter.
+const MESSAGE_TYPES: [u8; 10] = *b"BDELNPRSTU";
+
+/// The upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [0.00001, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1., 5., 10.];
+
+/// The counters shared by the threads of the server.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    inner: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    events: [AtomicU64; 9],
+    messages: [AtomicU64; 10],
+    bytes: [AtomicU64; 10],
+    chunk_requests: AtomicU64,
+    clients_lock_wait: Histogram,
+}
+
+impl Metrics {
+    /// Creates counters that start at zero.
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Counts an event received by the event thread.
+    pub fn count_event(&self, event: &Event) {
+        let kind = match *event {
+            Event::Join => 0,
+            Event::Disconnected => 1,
+            Event::Position(_) => 2,
+            Event::Talk(_) => 3,
+            Event::Block(_) => 4,
+            Event::ChunkRequest(_) => 5,
+            Event::Sign(_) => 6,
+            Event::Light(_) => 7,
+            Event::Console(_) => 8,
+        };
+
+        self.inner.events[kind].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a message sent to a client. Its type is its first letter.
+    pub fn count_message(&self, msg: &str) {
+        let kind = msg.bytes().next().and_then(|t| MESSAGE_TYPES.iter().position(|&m| m == t));
+
+        if let Some(kind) = kind {
+            self.inner.messages[kind].fetch_add(1, Ordering::Relaxed);
+            self.inner.bytes[kind].fetch_add(msg.len() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a chunk sent to a client that requested it.
+    pub fn count_chunk_request(&self) {
+        self.inner.chunk_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Locks the clients, recording how long that had to wait.
+    pub fn lock_clients<'m, T>(&self, clients: &'m Mutex<T>) -> MutexGuard<'m, T> {
+        let start = Instant::now();
+        let guard = clients.lock().unwrap();
+        self.inner.clients_lock_wait.observe(start.elapsed());
+
+        guard
+    }
+
+    /// Writes every metric in the Prometheus text format, along with the number of
+    /// connected clients and the metrics of the world.
+    pub fn render(&self, connections: usize, world: &WorldMonitor) -> String {
+        let mut out = String::new();
+        let counts = &self.inner;
+
+        header(&mut out, "craft_events_received_total", "counter", "Events received by the event thread.");
+        for (kind, n) in EVENT_KINDS.iter().zip(&counts.events) {
+            writeln!(out, "craft_events_received_total{{event=\"{}\"}} {}", kind, n.load(Ordering::Relaxed)).unwrap();
+        }
+
+        header(&mut out, "craft_messages_sent_total", "counter", "Messages sent to clients, by type.");
+        for (kind, n) in MESSAGE_TYPES.iter().zip(&counts.messages) {
+            writeln!(out, "craft_messages_sent_total{{type=\"{}\"}} {}", *kind as char, n.load(Ordering::Relaxed))
+                .unwrap();
+        }
+
+        header(&mut out, "craft_bytes_sent_total", "counter", "Bytes sent to clients, by message type.");
+        for (kind, n) in MESSAGE_TYPES.iter().zip(&counts.bytes) {
+            writeln!(out, "craft_bytes_sent_total{{type=\"{}\"}} {}", *kind as char, n.load(Ordering::Relaxed))
+                .unwrap();
+        }
+
+        header(&mut out, "craft_chunk_requests_served_total", "counter", "Chunks sent to clients that requested them.");
+        writeln!(out, "craft_chunk_requests_served_total {}", counts.chunk_requests.load(Ordering::Relaxed)).unwrap();
+
+        header(&mut out, "craft_connections", "gauge", "Connected clients.");
+        writeln!(out, "craft_connections {}", connections).unwrap();
+
+        header(&mut out, "craft_loaded_chunks", "gauge", "Chunks kept in memory.");
+        writeln!(out, "craft_loaded_chunks {}", world.loaded_chunks()).unwrap();
+
+        header(&mut out, "craft_database_commands_queued_total", "counter",
+               "Commands sent to the database thread.");
+        writeln!(out, "craft_database_commands_queued_total {}", world.queued_total()).unwrap();
+
+        header(&mut out, "craft_database_commands_committed_total", "counter",
+               "Commands the database thread has written.");
+        writeln!(out, "craft_database_commands_committed_total {}", world.committed()).unwrap();
+
+        header(&mut out, "craft_database_queue", "gauge", "Commands waiting to be written.");
+        writeln!(out, "craft_database_queue {}", world.queued_commands()).unwrap();
+
+        header(&mut out, "craft_database_degraded", "gauge", "1 while changes to the world can't be saved.");
+        writeln!(out, "craft_database_degraded {}", world.is_degraded() as u8).unwrap();
+
+        header(&mut out, "craft_database_flush_seconds", "histogram", "Time taken to write queued commands.");
+        world.flush_latency().render(&mut out, "craft_database_flush_seconds");
+
+        header(&mut out, "craft_clients_lock_wait_seconds", "histogram", "Time spent waiting to lock the clients.");
+        counts.clients_lock_wait.render(&mut out, "craft_clients_lock_wait_seconds");
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Counts durations in buckets, and keeps their sum.
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    inner: Arc<HistogramCounts>,
+}
+
+#[derive(Debug, Default)]
+struct HistogramCounts {
+    buckets: [AtomicU64; 12],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    /// Records a duration.
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+
+        for (bound, n) in BUCKETS.iter().zip(&self.inner.buckets) {
+            if secs <= *bound {
+                n.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.inner.count.fetch_add(1, Ordering::Relaxed);
+        self.inner.sum_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Writes the buckets, sum and count of the histogram as the metric `name`.
+    fn render(&self, out: &mut String, name: &str) {
+        let counts = &self.inner;
+        let count = counts.count.load(Ordering::Relaxed);
+
+        for (bound, n) in BUCKETS.iter().zip(&counts.buckets) {
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, n.load(Ordering::Relaxed)).unwrap();
+        }
+
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count).unwrap();
+        writeln!(out, "{}_sum {}", name, counts.sum_nanos.load(Ordering::Relaxed) as f64 * 1e-9).unwrap();
+        writeln!(out, "{}_count {}", name, count).unwrap();
+    }
+}
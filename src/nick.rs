@@ -1,7 +1,7 @@
 //! This module handles loading of nicknames from the nickname file.
 
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
 use std::fs::{File, OpenOptions};
 use std::net::IpAddr;
 
@@ -47,6 +47,14 @@ impl NickManager {
         }
     }
 
+    /// Find the IP address that uses a nickname.
+    /// # Return value
+    /// If an address with this nickname was found, it is returned.
+    /// Otherwise, None is returned.
+    pub fn find(&self, nick: &str) -> Option<IpAddr> {
+        self.map.iter().find(|i| i.1 == nick).map(|i| *i.0)
+    }
+
     /// Set the nickname for an IP address.
     pub fn set(&mut self, ip: &IpAddr, nick: &str) {
         self.map.insert(ip.clone(), nick.to_string());
@@ -54,30 +62,55 @@ impl NickManager {
         self.save();
     }
 
+    /// Reads the nickname file again, for changes made to it while the server runs.
+    /// # Return value
+    /// Returns false if the file can't be read, in which case the nicknames are kept.
+    pub fn reload(&mut self) -> bool {
+        match self.read() {
+            Some(map) => {
+                self.map = map;
+                debug!("Nickname map: {:?}", self.map);
+
+                true
+            },
+            None => false,
+        }
+    }
+
     fn load(&mut self) {
-        for i in BufReader::new(self.file.try_clone().unwrap()).lines() {
-            let i = i.unwrap();
+        match self.read() {
+            Some(map) => self.map = map,
+            None => panic!("Can't read {}", FILE),
+        }
+
+        debug!("Nickname map: {:?}", self.map);
+    }
+
+    fn read(&mut self) -> Option<HashMap<IpAddr, String>> {
+        let mut map = HashMap::new();
+
+        self.file.seek(SeekFrom::Start(0)).ok()?;
+
+        for i in BufReader::new(self.file.try_clone().ok()?).lines() {
+            let i = i.ok()?;
 
             if i.split('=').count() != 2 {
-                panic!("Can't read {}", FILE);
+                return None;
             }
 
             let mut pieces: Vec<&str> = i.split(|c: char| c == '=' || c.is_whitespace()).collect();
             pieces.retain(|p| *p != "");
 
             if pieces.len() != 2 {
-                panic!("Can't read {}", FILE);
+                return None;
             }
 
             //println!("pieces: {:?}", pieces);
 
-            let ip = pieces[0].parse();
-            assert!(ip.is_ok(), "Can't read {}", FILE);
-
-            self.map.insert(ip.unwrap(), pieces[1].to_string());
+            map.insert(pieces[0].parse().ok()?, pieces[1].to_string());
         }
 
-        println!("nickname map: {:?}", self.map);
+        Some(map)
     }
 
     fn save(&mut self) {
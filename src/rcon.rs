@@ -0,0 +1,146 @@
+//! Remote admin, which runs commands sent by `craft_admin` over TCP.
+//!
+//! The listener only accepts connections on localhost, and only while a password
+//! is configured. Like Craft's own protocol, messages are lines of comma-separated
+//! fields:
+//!
+//! * `A,<password>` logs in. The server answers `A,OK`, or `A,FAIL` and closes the connection.
+//! * `C,<command>` runs a command with the rights of an admin. The server sends
+//!   `T,<text>` for every reply, and `D` once the command has been handled.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use client;
+use config::RconConfig;
+use console::{self, Consoles, Output};
+use event::IdEvent;
+
+/// How long a connection may take to log in.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a failed login waits before it is answered, to slow down guessing.
+const FAILED_LOGIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Starts listening for remote admin connections, if a password is configured.
+pub fn run(config: &RconConfig, tx: mpsc::Sender<IdEvent>, consoles: &Consoles) {
+    let password = match config.password {
+        Some(ref p) => p.clone(),
+        None => return,
+    };
+
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, config.port)) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("Can't listen for remote admin on port {}: {}", config.port, e);
+            return;
+        },
+    };
+
+    info!("Listening for remote admin on port {}.", config.port);
+
+    let consoles = consoles.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+
+            let password = password.clone();
+            let tx = tx.clone();
+            let consoles = consoles.clone();
+
+            thread::spawn(move || {
+                if let Err(e) = connection(stream, &password, &tx, &consoles) {
+                    warn!("Remote admin connection failed: {}", e);
+                }
+            });
+        }
+    });
+}
+
+fn connection(mut stream: TcpStream,
+              password: &str,
+              tx: &mpsc::Sender<IdEvent>,
+              consoles: &Consoles) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    stream.set_read_timeout(Some(LOGIN_TIMEOUT))?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let accepted = match line.trim_end_matches(&['\r', '\n'][..]).strip_prefix("A,") {
+        Some(p) => same(p.as_bytes(), password.as_bytes()),
+        None => false,
+    };
+
+    if !accepted {
+        warn!(peer:% = peer; "Rejected a remote admin login.");
+
+        thread::sleep(FAILED_LOGIN_DELAY);
+        return stream.write_all(b"A,FAIL\n");
+    }
+
+    stream.set_read_timeout(None)?;
+    stream.write_all(b"A,OK\n")?;
+
+    let (id, output) = consoles.open();
+    info!(console = id, peer:% = peer; "Remote admin logged in.");
+
+    let writer = thread::spawn(move || {
+        for o in output {
+            let result = match o {
+                Output::Line(text) => writeln!(stream, "T,{}", text.replace('\n', " ")),
+                Output::Done => stream.write_all(b"D\n"),
+            };
+
+            if result.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = read_commands(reader, tx, id, consoles);
+
+    // The writer stops once it has sent the replies that are already waiting.
+    consoles.close(id);
+    let _ = writer.join();
+
+    info!(console = id; "Remote admin logged out.");
+
+    result
+}
+
+/// Sends the commands from a logged in connection to the event thread, until it closes.
+fn read_commands(reader: BufReader<TcpStream>,
+                 tx: &mpsc::Sender<IdEvent>,
+                 id: client::Id,
+                 consoles: &Consoles) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+
+        let command = match line.strip_prefix("C,") {
+            Some(c) => c,
+            None => break,
+        };
+
+        match console::command_of(command) {
+            Some(command) => if !console::send_command(tx, id, command) {
+                break;
+            },
+            None => consoles.done(id),
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares two byte strings in a time that doesn't depend on where they differ.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |d, (x, y)| d | (x ^ y)) == 0
+}
@@ -0,0 +1,156 @@
+//! This module handles loading of player roles from the roles file.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::fs::{File, OpenOptions};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const FILE: &str = "roles.txt";
+
+/// The roles a player can have. Every role has all the rights of the roles before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// A regular player. This is the role of everyone not listed in the roles file.
+    Player,
+
+    /// A player that may inspect and undo the edits of others.
+    Moderator,
+
+    /// A player that may manage the server.
+    Admin,
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Role::Player => "player",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Role, ()> {
+        match s {
+            "player" => Ok(Role::Player),
+            "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Manages the roles file.
+pub struct RoleManager {
+    map: HashMap<IpAddr, Role>,
+    file: File,
+}
+
+impl RoleManager {
+    /// Creates a new RoleManager.
+    /// # Note
+    /// The roles will be loaded from the role storage file.
+    /// If the file doesn't exist, it will be created.
+    /// # Panics
+    /// This function will panic if it can't create or open the roles file.
+    pub fn new() -> RoleManager {
+        let mut m = RoleManager {
+            map: HashMap::new(),
+            file: OpenOptions::new()
+                    .read(true)
+                    .append(true)
+                    .create(true)
+                    .open(FILE)
+                    .unwrap(),
+        };
+
+        m.load();
+
+        m
+    }
+
+    /// Get the role of an IP address.
+    /// Addresses that are not in the roles file are players.
+    pub fn get(&self, ip: &IpAddr) -> Role {
+        self.map.get(ip).cloned().unwrap_or(Role::Player)
+    }
+
+    /// Set the role of an IP address.
+    pub fn set(&mut self, ip: &IpAddr, role: Role) {
+        if role == Role::Player {
+            self.map.remove(ip);
+        } else {
+            self.map.insert(*ip, role);
+        }
+
+        self.save();
+    }
+
+    /// Reads the roles file again, for changes made to it while the server runs.
+    /// # Return value
+    /// Returns false if the file can't be read, in which case the roles are kept.
+    pub fn reload(&mut self) -> bool {
+        match self.read() {
+            Some(map) => {
+                self.map = map;
+                debug!("Role map: {:?}", self.map);
+
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn load(&mut self) {
+        match self.read() {
+            Some(map) => self.map = map,
+            None => panic!("Can't read {}", FILE),
+        }
+
+        debug!("Role map: {:?}", self.map);
+    }
+
+    fn read(&mut self) -> Option<HashMap<IpAddr, Role>> {
+        let mut map = HashMap::new();
+
+        self.file.seek(SeekFrom::Start(0)).ok()?;
+
+        for i in BufReader::new(self.file.try_clone().ok()?).lines() {
+            let i = i.ok()?;
+
+            let mut pieces: Vec<&str> = i.split(|c: char| c == '=' || c.is_whitespace()).collect();
+            pieces.retain(|p| !p.is_empty());
+
+            if pieces.len() != 2 {
+                return None;
+            }
+
+            map.insert(pieces[0].parse().ok()?, pieces[1].parse().ok()?);
+        }
+
+        Some(map)
+    }
+
+    fn save(&mut self) {
+        self.file.set_len(0).unwrap();
+
+        for i in &self.map {
+            self.file.write_fmt(format_args!("{} = {}\n", i.0, i.1)).unwrap();
+        }
+
+        self.file.flush().unwrap();
+    }
+}
+
+impl Default for RoleManager {
+    fn default() -> RoleManager {
+        RoleManager::new()
+    }
+}
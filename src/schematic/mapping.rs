@@ -0,0 +1,164 @@
+//! Maps Craft's blocks to the names that schematics use for them.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use world::{Block, block_type_by_name};
+use super::SchematicError;
+
+const FILE: &str = "schematic_blocks.txt";
+
+/// The prefix of the names of blocks that have no mapping. Blocks named like
+/// `craft:brick` are read back as the Craft block of that name.
+const CRAFT_PREFIX: &str = "craft:";
+
+/// The mapping file written when there is none.
+const DEFAULT_HEADER: &str = "\
+# How Craft's blocks are named in schematics. Each line has the form `craft block = schematic block`.
+# Craft blocks may be given by name or ID. Schematic blocks may carry properties, like
+# minecraft:oak_log[axis=y], which are ignored when a schematic's blocks are looked up.
+# Blocks without a line here are saved as craft:<name>.
+";
+
+/// The schematic names of Craft's blocks in the mapping file written when there is none.
+static DEFAULT_NAMES: [(&str, &str); 55] = [
+    ("air", "minecraft:air"),
+    ("grass", "minecraft:grass_block"),
+    ("sand", "minecraft:sand"),
+    ("stone", "minecraft:stone"),
+    ("brick", "minecraft:bricks"),
+    ("wood", "minecraft:oak_log"),
+    ("cement", "minecraft:smooth_stone"),
+    ("dirt", "minecraft:dirt"),
+    ("plank", "minecraft:oak_planks"),
+    ("snow", "minecraft:snow_block"),
+    ("glass", "minecraft:glass"),
+    ("cobble", "minecraft:cobblestone"),
+    ("light_stone", "minecraft:stone_bricks"),
+    ("dark_stone", "minecraft:polished_blackstone_bricks"),
+    ("chest", "minecraft:chest"),
+    ("leaves", "minecraft:oak_leaves"),
+    ("tall_grass", "minecraft:short_grass"),
+    ("yellow_flower", "minecraft:dandelion"),
+    ("red_flower", "minecraft:poppy"),
+    ("purple_flower", "minecraft:allium"),
+    ("sun_flower", "minecraft:sunflower"),
+    ("white_flower", "minecraft:oxeye_daisy"),
+    ("blue_flower", "minecraft:cornflower"),
+    ("color_00", "minecraft:white_wool"),
+    ("color_01", "minecraft:orange_wool"),
+    ("color_02", "minecraft:magenta_wool"),
+    ("color_03", "minecraft:light_blue_wool"),
+    ("color_04", "minecraft:yellow_wool"),
+    ("color_05", "minecraft:lime_wool"),
+    ("color_06", "minecraft:pink_wool"),
+    ("color_07", "minecraft:gray_wool"),
+    ("color_08", "minecraft:light_gray_wool"),
+    ("color_09", "minecraft:cyan_wool"),
+    ("color_10", "minecraft:purple_wool"),
+    ("color_11", "minecraft:blue_wool"),
+    ("color_12", "minecraft:brown_wool"),
+    ("color_13", "minecraft:green_wool"),
+    ("color_14", "minecraft:red_wool"),
+    ("color_15", "minecraft:black_wool"),
+    ("color_16", "minecraft:white_concrete"),
+    ("color_17", "minecraft:orange_concrete"),
+    ("color_18", "minecraft:magenta_concrete"),
+    ("color_19", "minecraft:light_blue_concrete"),
+    ("color_20", "minecraft:yellow_concrete"),
+    ("color_21", "minecraft:lime_concrete"),
+    ("color_22", "minecraft:pink_concrete"),
+    ("color_23", "minecraft:gray_concrete"),
+    ("color_24", "minecraft:light_gray_concrete"),
+    ("color_25", "minecraft:cyan_concrete"),
+    ("color_26", "minecraft:purple_concrete"),
+    ("color_27", "minecraft:blue_concrete"),
+    ("color_28", "minecraft:brown_concrete"),
+    ("color_29", "minecraft:green_concrete"),
+    ("color_30", "minecraft:red_concrete"),
+    ("color_31", "minecraft:black_concrete"),
+];
+
+/// A mapping between Craft's block IDs and the names of blocks in schematics.
+#[derive(Clone, Debug, Default)]
+pub struct BlockMapping {
+    names: HashMap<i8, String>,
+    blocks: HashMap<String, Block>,
+}
+
+impl BlockMapping {
+    /// Loads the mapping from the mapping file.
+    /// # Note
+    /// If the file doesn't exist, it will be created with the default mapping.
+    /// # Return value
+    /// Returns an error if the file can't be created or read, or names a block
+    /// that Craft does not know.
+    pub fn load() -> Result<BlockMapping, SchematicError> {
+        if !Path::new(FILE).exists() {
+            fs::write(FILE, default_file())?;
+        }
+
+        BlockMapping::parse(&fs::read_to_string(FILE)?)
+    }
+
+    fn parse(text: &str) -> Result<BlockMapping, SchematicError> {
+        let mut mapping = BlockMapping::default();
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || SchematicError::Format(format!("can't read {}: {}", FILE, line));
+
+            let mut pieces = line.splitn(2, '=');
+            let craft = pieces.next().unwrap().trim();
+            let name = pieces.next().map(str::trim).filter(|n| !n.is_empty()).ok_or_else(invalid)?;
+            let block = block_type_by_name(craft).map(|t| Block(t.id)).ok_or_else(invalid)?;
+
+            // The first line for a block decides its name, and the first line
+            // for a name decides its block.
+            mapping.names.entry(block.0).or_insert_with(|| name.to_string());
+            mapping.blocks.entry(name.to_string()).or_insert(block);
+        }
+
+        Ok(mapping)
+    }
+
+    /// Returns the name of a block in schematics.
+    pub fn name_of(&self, block: &Block) -> String {
+        match self.names.get(&block.0) {
+            Some(name) => name.clone(),
+            None => match block.block_type() {
+                Some(t) => format!("{}{}", CRAFT_PREFIX, t.name),
+                None => format!("{}{}", CRAFT_PREFIX, block.0),
+            },
+        }
+    }
+
+    /// Looks up the block with a name from a schematic. Names may carry properties,
+    /// which are only used if the name with them is mapped.
+    pub fn block_named(&self, name: &str) -> Option<Block> {
+        let base = name.split('[').next().unwrap_or(name);
+
+        if let Some(b) = self.blocks.get(name).or_else(|| self.blocks.get(base)) {
+            return Some(b.clone());
+        }
+
+        base.strip_prefix(CRAFT_PREFIX).and_then(block_type_by_name).map(|t| Block(t.id))
+    }
+}
+
+fn default_file() -> String {
+    let mut text = DEFAULT_HEADER.to_string();
+    text.push('\n');
+
+    for &(craft, name) in DEFAULT_NAMES.iter() {
+        writeln!(text, "{} = {}", craft, name).unwrap();
+    }
+
+    text
+}
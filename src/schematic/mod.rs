@@ -0,0 +1,389 @@
+//! Reads and writes cuboids of the world as Sponge schematics, the format that
+//! WorldEdit and other tools exchange builds in.
+//! See https://github.com/SpongePowered/Schematic-Specification
+//!
+//! Schematics are written in version 2 of the format, and versions 1 to 3 can be
+//! read. Blocks are named through a `BlockMapping`. Signs are written as sign block
+//! entities, which also carry the face and full text of the Craft sign, so that
+//! they read back unchanged. Block entities without those are not read.
+
+mod mapping;
+pub mod nbt;
+
+pub use self::mapping::BlockMapping;
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use world::{Block, cuboid, MAX_SIGN_FACE, MAX_SIGN_LENGTH, Sign, Value, within_world, World, WorldError};
+use self::nbt::Tag;
+
+/// The version of the schematic format that is written.
+const VERSION: i32 = 2;
+
+/// The Minecraft data version that written schematics claim, which is that of 1.20.1.
+const DATA_VERSION: i32 = 3465;
+
+/// The longest that a schematic can be along any axis.
+pub const MAX_SIDE: i32 = u16::MAX as i32;
+
+/// The length of the lines that sign texts are split into for other tools.
+const SIGN_LINE_LENGTH: usize = 15;
+
+/// Values to set, by their global positions.
+type Edits = Vec<((i32, i32, i32), Value)>;
+
+/// Describes why a schematic could not be read or written.
+#[derive(Debug)]
+pub enum SchematicError {
+    /// The file could not be read or written.
+    Io(io::Error),
+
+    /// The file is not a schematic this server can read.
+    Format(String),
+}
+
+impl Display for SchematicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchematicError::Io(ref e) => write!(f, "file error: {}", e),
+            SchematicError::Format(ref s) => write!(f, "invalid schematic: {}", s),
+        }
+    }
+}
+
+impl Error for SchematicError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SchematicError::Io(ref e) => Some(e),
+            SchematicError::Format(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SchematicError {
+    fn from(e: io::Error) -> SchematicError {
+        SchematicError::Io(e)
+    }
+}
+
+/// A cuboid of blocks and signs. Positions are relative to its lowest corner.
+#[derive(Clone, Debug, Default)]
+pub struct Schematic {
+    /// The size of the cuboid along X, Y and Z.
+    pub size: (i32, i32, i32),
+
+    /// Where the lowest corner goes, relative to the position the schematic is placed at.
+    pub offset: (i32, i32, i32),
+
+    /// The blocks that are not air.
+    pub blocks: Vec<((i32, i32, i32), Block)>,
+
+    /// The signs, with the faces of the blocks they are on.
+    pub signs: Vec<((i32, i32, i32), u8, Sign)>,
+
+    /// The names of the blocks that were read but have no mapping, and were left out.
+    pub unmapped: Vec<String>,
+}
+
+impl Schematic {
+    /// Takes the blocks and signs in a cuboid of the world. `origin` is the position
+    /// the schematic is later placed relative to.
+    ///
+    /// Only blocks that were placed are included. Mined blocks and the terrain Craft
+    /// generates are left out, and become air.
+    pub fn from_world(world: &World,
+                      a: (i32, i32, i32),
+                      b: (i32, i32, i32),
+                      origin: (i32, i32, i32)) -> Result<Schematic, WorldError> {
+        let (min, max) = cuboid(a, b);
+        let relative = |xyz: (i32, i32, i32)| (xyz.0 - min.0, xyz.1 - min.1, xyz.2 - min.2);
+
+        Ok(Schematic {
+            size: (max.0 - min.0 + 1, max.1 - min.1 + 1, max.2 - min.2 + 1),
+            offset: (min.0 - origin.0, min.1 - origin.1, min.2 - origin.2),
+            blocks: world.blocks_in(min, max)?
+                         .filter(|(_, b)| !b.is_air())
+                         .map(|(xyz, b)| (relative(xyz), b))
+                         .collect(),
+            signs: world.signs_in(min, max)?
+                        .map(|(xyz, (face, s))| (relative(xyz), face, s))
+                        .collect(),
+            unmapped: Vec::new(),
+        })
+    }
+
+    /// Returns the edits that place the schematic at a position, blocks first.
+    /// # Return value
+    /// Returns None if the schematic would reach past the edge of the world.
+    pub fn edits(&self, origin: (i32, i32, i32)) -> Option<Edits> {
+        // The offset and the positions come from the file, so they may be anything.
+        let add = |a: i32, b: i32, c: i32| a.checked_add(b)?.checked_add(c);
+        let global = |xyz: (i32, i32, i32)| {
+            let global = (add(origin.0, self.offset.0, xyz.0)?,
+                          add(origin.1, self.offset.1, xyz.1)?,
+                          add(origin.2, self.offset.2, xyz.2)?);
+
+            Some(global).filter(|&(x, _, z)| within_world(x, z))
+        };
+
+        let mut edits = Vec::with_capacity(self.blocks.len() + self.signs.len());
+        for (xyz, b) in &self.blocks {
+            edits.push((global(*xyz)?, Value::Block(b.clone())));
+        }
+        for (xyz, face, s) in &self.signs {
+            edits.push((global(*xyz)?, Value::Sign(*face, s.clone())));
+        }
+
+        Some(edits)
+    }
+
+    /// Reads a schematic file.
+    pub fn read<P: AsRef<Path>>(path: P, mapping: &BlockMapping) -> Result<Schematic, SchematicError> {
+        let mut r = BufReader::new(GzDecoder::new(File::open(path)?));
+        let (_, root) = nbt::read(&mut r)?;
+
+        // Version 3 wraps the schematic in an unnamed compound.
+        let root = root.get("Schematic").unwrap_or(&root);
+        let version = int(root, "Version")?;
+
+        let (blocks, entities) = match version {
+            1 | 2 => (root, root.get("BlockEntities").or_else(|| root.get("TileEntities"))),
+            3 => match root.get("Blocks") {
+                Some(blocks) => (blocks, blocks.get("BlockEntities")),
+                None => (root, None),
+            },
+            _ => return Err(format_error(format!("version {} is not supported", version))),
+        };
+
+        // Sizes are unsigned, though NBT has no unsigned integers.
+        let size = (side(root, "Width")?, side(root, "Height")?, side(root, "Length")?);
+        let offset = match root.get("Offset") {
+            Some(Tag::IntArray(xyz)) if xyz.len() == 3 => (xyz[0], xyz[1], xyz[2]),
+            _ => (0, 0, 0),
+        };
+
+        let mut schematic = Schematic {
+            size,
+            offset,
+            ..Schematic::default()
+        };
+
+        if blocks.get("Palette").is_some() {
+            schematic.read_blocks(blocks, mapping)?;
+        }
+
+        if let Some(Tag::List(entities)) = entities {
+            for e in entities {
+                schematic.read_sign(e, version);
+            }
+        }
+
+        Ok(schematic)
+    }
+
+    fn read_blocks(&mut self, tag: &Tag, mapping: &BlockMapping) -> Result<(), SchematicError> {
+        let mut palette = BTreeMap::new();
+        match tag.get("Palette") {
+            Some(Tag::Compound(entries)) => for (name, index) in entries {
+                let index = index.as_int().ok_or_else(|| format_error("invalid palette"))?;
+
+                match mapping.block_named(name) {
+                    Some(b) => {
+                        palette.insert(index, b);
+                    },
+                    None => self.unmapped.push(name.clone()),
+                }
+            },
+            _ => return Err(format_error("invalid palette")),
+        }
+
+        let data = match tag.get("BlockData").or_else(|| tag.get("Data")) {
+            Some(Tag::ByteArray(data)) => data,
+            _ => return Err(format_error("missing block data")),
+        };
+
+        let (width, length) = (self.size.0 as usize, self.size.2 as usize);
+        let volume = width * length * self.size.1 as usize;
+        let mut bytes = data.iter();
+        let mut i = 0;
+
+        while let Some(index) = read_varint(&mut bytes)? {
+            if i >= volume {
+                return Err(format_error("too much block data"));
+            }
+
+            if let Some(b) = palette.get(&index).filter(|b| !b.is_air()) {
+                let xyz = ((i % width) as i32, (i / (width * length)) as i32, (i / width % length) as i32);
+                self.blocks.push((xyz, b.clone()));
+            }
+
+            i += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a block entity, if it is a sign this server wrote.
+    fn read_sign(&mut self, tag: &Tag, version: i32) {
+        let data = if version >= 3 { tag.get("Data").unwrap_or(tag) } else { tag };
+
+        let pos = match tag.get("Pos") {
+            Some(Tag::IntArray(xyz)) if xyz.len() == 3 => (xyz[0], xyz[1], xyz[2]),
+            _ => return,
+        };
+
+        let face = data.get("CraftFace").and_then(Tag::as_int);
+        let text = data.get("CraftText").and_then(Tag::as_str);
+
+        // Signs are held to the same limits as those that players place.
+        if let (Some(face), Some(text)) = (face, text) {
+            if (0..=MAX_SIGN_FACE as i32).contains(&face) && !text.is_empty() && text.len() <= MAX_SIGN_LENGTH {
+                self.signs.push((pos, face as u8, Sign(text.to_string())));
+            }
+        }
+    }
+
+    /// Writes the schematic to a file.
+    pub fn write<P: AsRef<Path>>(&self, path: P, mapping: &BlockMapping) -> Result<(), SchematicError> {
+        let (width, height, length) = (self.size.0 as usize, self.size.1 as usize, self.size.2 as usize);
+
+        if [self.size.0, self.size.1, self.size.2].iter().any(|&n| n > MAX_SIDE) {
+            return Err(format_error(format!("schematics can't be longer than {} blocks", MAX_SIDE)));
+        }
+
+        // Index 0 is air, which fills every position without a block.
+        let mut palette = BTreeMap::new();
+        palette.insert(mapping.name_of(&Block(0)), 0);
+
+        let mut indices = vec![0; width * height * length];
+        for ((x, y, z), b) in &self.blocks {
+            let next = palette.len() as i32;
+            let index = *palette.entry(mapping.name_of(b)).or_insert(next);
+
+            indices[*x as usize + *z as usize * width + *y as usize * width * length] = index;
+        }
+
+        let mut data = Vec::with_capacity(indices.len());
+        for index in indices {
+            write_varint(&mut data, index);
+        }
+
+        let entities = self.signs.iter().map(|&((x, y, z), face, ref sign)| {
+            let mut entity = BTreeMap::new();
+            entity.insert("Id".to_string(), Tag::String("minecraft:sign".to_string()));
+            entity.insert("Pos".to_string(), Tag::IntArray(vec![x, y, z]));
+
+            let chars: Vec<char> = sign.0.chars().collect();
+            let mut lines = chars.chunks(SIGN_LINE_LENGTH);
+            for i in 1..=4 {
+                let line: String = lines.next().map(|l| l.iter().collect()).unwrap_or_default();
+                entity.insert(format!("Text{}", i), Tag::String(json_text(&line)));
+            }
+
+            entity.insert("CraftFace".to_string(), Tag::Byte(face as i8));
+            entity.insert("CraftText".to_string(), Tag::String(sign.0.clone()));
+
+            Tag::Compound(entity)
+        }).collect();
+
+        let mut root = BTreeMap::new();
+        root.insert("Version".to_string(), Tag::Int(VERSION));
+        root.insert("DataVersion".to_string(), Tag::Int(DATA_VERSION));
+        root.insert("Width".to_string(), Tag::Short(self.size.0 as i16));
+        root.insert("Height".to_string(), Tag::Short(self.size.1 as i16));
+        root.insert("Length".to_string(), Tag::Short(self.size.2 as i16));
+        root.insert("Offset".to_string(), Tag::IntArray(vec![self.offset.0, self.offset.1, self.offset.2]));
+        root.insert("PaletteMax".to_string(), Tag::Int(palette.len() as i32));
+        root.insert("Palette".to_string(),
+                    Tag::Compound(palette.into_iter().map(|(name, i)| (name, Tag::Int(i))).collect()));
+        root.insert("BlockData".to_string(), Tag::ByteArray(data));
+        root.insert("BlockEntities".to_string(), Tag::List(entities));
+
+        // The file is written under a temporary name first, so that a failed
+        // write never replaces a schematic with a broken one.
+        let path = path.as_ref();
+        let partial = path.with_extension("schem.partial");
+
+        let result = (|| {
+            let mut w = GzEncoder::new(BufWriter::new(File::create(&partial)?), Compression::default());
+            nbt::write(&mut w, "Schematic", &Tag::Compound(root))?;
+            w.finish()?.flush()
+        })();
+
+        match result {
+            Ok(()) => Ok(fs::rename(&partial, path)?),
+            Err(e) => {
+                let _ = fs::remove_file(&partial);
+                Err(e.into())
+            },
+        }
+    }
+}
+
+fn int(tag: &Tag, name: &str) -> Result<i32, SchematicError> {
+    tag.get(name).and_then(Tag::as_int).ok_or_else(|| format_error(format!("missing {}", name)))
+}
+
+fn side(tag: &Tag, name: &str) -> Result<i32, SchematicError> {
+    Ok(int(tag, name)? as u16 as i32)
+}
+
+fn format_error<S: Into<String>>(message: S) -> SchematicError {
+    SchematicError::Format(message.into())
+}
+
+/// Reads an unsigned variable-length integer, as block data is stored in.
+/// # Return value
+/// Returns None at the end of the data.
+fn read_varint<'a, I: Iterator<Item = &'a u8>>(bytes: &mut I) -> Result<Option<i32>, SchematicError> {
+    let mut value = 0;
+
+    for shift in (0..35).step_by(7) {
+        let byte = match bytes.next() {
+            Some(&b) => b,
+            None if shift == 0 => return Ok(None),
+            None => return Err(format_error("truncated block data")),
+        };
+
+        value |= ((byte & 0x7f) as i32) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err(format_error("invalid block data"))
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: i32) {
+    while value & !0x7f != 0 {
+        data.push((value & 0x7f) as u8 | 0x80);
+        value = ((value as u32) >> 7) as i32;
+    }
+
+    data.push(value as u8);
+}
+
+/// Formats a line of sign text as the JSON text component that Minecraft expects.
+fn json_text(line: &str) -> String {
+    let mut json = String::from("{\"text\":\"");
+
+    for c in line.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push_str("\"}");
+    json
+}
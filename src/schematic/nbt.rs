@@ -0,0 +1,274 @@
+//! Reads and writes Named Binary Tags, the format schematics are stored in.
+//! See https://minecraft.wiki/w/NBT_format
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// The deepest that lists and compounds may be nested, so that malicious
+/// files can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+/// A tag, holding a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(BTreeMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Returns the tag with the given name, if this is a compound that holds one.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match *self {
+            Tag::Compound(ref tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a byte, short or int tag.
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            Tag::Byte(n) => Some(n as i32),
+            Tag::Short(n) => Some(n as i32),
+            Tag::Int(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a string tag.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Tag::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match *self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+}
+
+/// Reads a named tag, such as the root compound of a file.
+pub fn read<R: Read>(r: &mut R) -> io::Result<(String, Tag)> {
+    let id = read_u8(r)?;
+    let name = read_string(r)?;
+    let tag = read_payload(r, id, 0)?;
+
+    Ok((name, tag))
+}
+
+/// Writes a named tag, such as the root compound of a file.
+pub fn write<W: Write>(w: &mut W, name: &str, tag: &Tag) -> io::Result<()> {
+    w.write_all(&[tag.id()])?;
+    write_string(w, name)?;
+    write_payload(w, tag)
+}
+
+fn read_payload<R: Read>(r: &mut R, id: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("tags are nested too deeply"));
+    }
+
+    Ok(match id {
+        BYTE => Tag::Byte(read_u8(r)? as i8),
+        SHORT => Tag::Short(i16::from_be_bytes(read_array(r)?)),
+        INT => Tag::Int(read_i32(r)?),
+        LONG => Tag::Long(i64::from_be_bytes(read_array(r)?)),
+        FLOAT => Tag::Float(f32::from_be_bytes(read_array(r)?)),
+        DOUBLE => Tag::Double(f64::from_be_bytes(read_array(r)?)),
+        BYTE_ARRAY => {
+            let len = read_len(r)?;
+            let mut bytes = Vec::new();
+            r.take(len as u64).read_to_end(&mut bytes)?;
+
+            if bytes.len() != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            Tag::ByteArray(bytes)
+        },
+        STRING => Tag::String(read_string(r)?),
+        LIST => {
+            let id = read_u8(r)?;
+            let len = read_len(r)?;
+            let mut tags = Vec::new();
+
+            for _ in 0..len {
+                tags.push(read_payload(r, id, depth + 1)?);
+            }
+
+            Tag::List(tags)
+        },
+        COMPOUND => {
+            let mut tags = BTreeMap::new();
+
+            loop {
+                let id = read_u8(r)?;
+                if id == END {
+                    break;
+                }
+
+                let name = read_string(r)?;
+                tags.insert(name, read_payload(r, id, depth + 1)?);
+            }
+
+            Tag::Compound(tags)
+        },
+        INT_ARRAY => {
+            let len = read_len(r)?;
+            let mut ints = Vec::new();
+
+            for _ in 0..len {
+                ints.push(read_i32(r)?);
+            }
+
+            Tag::IntArray(ints)
+        },
+        LONG_ARRAY => {
+            let len = read_len(r)?;
+            let mut longs = Vec::new();
+
+            for _ in 0..len {
+                longs.push(i64::from_be_bytes(read_array(r)?));
+            }
+
+            Tag::LongArray(longs)
+        },
+        _ => return Err(invalid("unknown tag type")),
+    })
+}
+
+fn write_payload<W: Write>(w: &mut W, tag: &Tag) -> io::Result<()> {
+    match *tag {
+        Tag::Byte(n) => w.write_all(&[n as u8]),
+        Tag::Short(n) => w.write_all(&n.to_be_bytes()),
+        Tag::Int(n) => w.write_all(&n.to_be_bytes()),
+        Tag::Long(n) => w.write_all(&n.to_be_bytes()),
+        Tag::Float(n) => w.write_all(&n.to_be_bytes()),
+        Tag::Double(n) => w.write_all(&n.to_be_bytes()),
+        Tag::ByteArray(ref bytes) => {
+            w.write_all(&(bytes.len() as i32).to_be_bytes())?;
+            w.write_all(bytes)
+        },
+        Tag::String(ref s) => write_string(w, s),
+        Tag::List(ref tags) => {
+            w.write_all(&[tags.first().map(Tag::id).unwrap_or(END)])?;
+            w.write_all(&(tags.len() as i32).to_be_bytes())?;
+
+            for t in tags {
+                write_payload(w, t)?;
+            }
+
+            Ok(())
+        },
+        Tag::Compound(ref tags) => {
+            for (name, t) in tags {
+                write(w, name, t)?;
+            }
+
+            w.write_all(&[END])
+        },
+        Tag::IntArray(ref ints) => {
+            w.write_all(&(ints.len() as i32).to_be_bytes())?;
+
+            for n in ints {
+                w.write_all(&n.to_be_bytes())?;
+            }
+
+            Ok(())
+        },
+        Tag::LongArray(ref longs) => {
+            w.write_all(&(longs.len() as i32).to_be_bytes())?;
+
+            for n in longs {
+                w.write_all(&n.to_be_bytes())?;
+            }
+
+            Ok(())
+        },
+    }
+}
+
+fn read_array<R: Read, A: AsMut<[u8]> + Default>(r: &mut R) -> io::Result<A> {
+    let mut bytes = A::default();
+    r.read_exact(bytes.as_mut())?;
+
+    Ok(bytes)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    Ok(read_array::<R, [u8; 1]>(r)?[0])
+}
+
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    Ok(i32::from_be_bytes(read_array(r)?))
+}
+
+fn read_len<R: Read>(r: &mut R) -> io::Result<usize> {
+    let len = read_i32(r)?;
+
+    if len < 0 {
+        return Err(invalid("negative length"));
+    }
+
+    Ok(len as usize)
+}
+
+fn read_string<R: Read>(r: &mut R) -> io::Result<String> {
+    let len = u16::from_be_bytes(read_array(r)?) as usize;
+    let mut bytes = vec![0; len];
+    r.read_exact(&mut bytes)?;
+
+    // Strings are in Java's modified UTF-8, which only differs from UTF-8 for
+    // characters that block names and sign texts rarely hold.
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write_string<W: Write>(w: &mut W, s: &str) -> io::Result<()> {
+    if s.len() > u16::MAX as usize {
+        return Err(invalid("string is too long"));
+    }
+
+    w.write_all(&(s.len() as u16).to_be_bytes())?;
+    w.write_all(s.as_bytes())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
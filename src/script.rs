@@ -0,0 +1,507 @@
+//! Gameplay written in scripts, which are loaded from the scripts directory.
+//!
+//! Scripts are written in Rhai (https://rhai.rs) and end in `.rhai`. Every script
+//! is run once when it is loaded, and registers the commands and events it handles:
+//!
+//! ```text
+//! command("ground", |id, args| {
+//!     let y = highest_block(0, 0);
+//!     tell(id, `The ground at the spawn is at ${y}.`);
+//! });
+//!
+//! on("block", |id, ev| {
+//!     if ev.w == 10 && role(id) == "player" {
+//!         tell(id, "Only moderators may place cement.");
+//!         return false;
+//!     }
+//! });
+//! ```
+//!
+//! A command is called with the ID of the client or console that issued it and its
+//! arguments, before the server's own commands. Event handlers are called for
+//! `join` and `leave` with the ID of the client, and for `chat`, `block`, `sign` and
+//! `light` with the ID and the event as a map. A handler returns `false` to cancel
+//! the event, or a changed map to change it.
+//!
+//! Commands and handlers can use these functions:
+//!
+//! ```text
+//! players()                      The IDs of the connected clients.
+//! nick(id), role(id)             The nickname and role of a client.
+//! position(id)                   The position of a client, as #{x, y, z, rx, ry}.
+//! tell(id, text)                 Sends a chat message to a client or console.
+//! broadcast(text)                Sends a chat message to every client.
+//! kick(id, reason)               Disconnects a client.
+//! block(x, y, z)                 The ID of a block.
+//! set_block(x, y, z, block)      Places a block, given by its ID or name.
+//! sign(x, y, z, face)            The text of a sign.
+//! set_sign(x, y, z, face, text)  Changes a sign. Empty text removes it.
+//! light(x, y, z)                 The light level at a block.
+//! set_light(x, y, z, w)          Changes the light level at a block.
+//! highest_block(x, z)            The height of the highest block in a column.
+//! ```
+//!
+//! Changes that scripts make are sent to every client, but aren't recorded in the
+//! world's history. `print` writes to the server's log.
+//!
+//! `/scripts` lists the loaded scripts, and `/scripts reload` loads them again.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, Map, Scope, AST, INT};
+use client;
+use event::{BlockEvent, LightEvent, SignEvent, TalkEvent};
+use hook::{Flow, HookContext, ServerHook};
+use role::Role;
+use world::{block_type_by_name, editable_height, within_world, Block, Light, MAX_LIGHT, MAX_SIGN_FACE, Sign,
+            Value};
+
+const DIRECTORY: &str = "scripts";
+
+/// The events that scripts can handle.
+const EVENTS: [&str; 6] = ["join", "leave", "chat", "block", "sign", "light"];
+
+/// The most operations a script may run for one command or event, so that a script
+/// stuck in a loop can't stop the server from handling events.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+thread_local! {
+    /// The context of the hook that is running a script on this thread, if any.
+    static CONTEXT: Cell<*mut ()> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// A loaded script.
+struct Script {
+    name: String,
+    ast: AST,
+    commands: HashMap<String, FnPtr>,
+    handlers: Vec<(String, FnPtr)>,
+}
+
+/// What a script registers while it is loaded.
+#[derive(Default)]
+struct Registry {
+    loading: bool,
+    commands: HashMap<String, FnPtr>,
+    handlers: Vec<(String, FnPtr)>,
+}
+
+/// The hook that runs the scripts.
+pub struct Scripts {
+    engine: Engine,
+    registry: Arc<Mutex<Registry>>,
+    scripts: Vec<Script>,
+}
+
+impl Scripts {
+    /// Loads every script in the scripts directory. Scripts that can't be loaded
+    /// are left out, and the reason is logged.
+    pub fn load() -> Scripts {
+        let registry = Arc::new(Mutex::new(Registry::default()));
+
+        let mut s = Scripts {
+            engine: engine(&registry),
+            registry,
+            scripts: Vec::new(),
+        };
+
+        s.reload();
+
+        s
+    }
+
+    /// Loads every script in the scripts directory again. If a script can't be
+    /// loaded, its old version is kept.
+    /// # Return value
+    /// Returns why each script that couldn't be loaded failed.
+    fn reload(&mut self) -> Vec<String> {
+        let mut files: Vec<_> = match fs::read_dir(DIRECTORY) {
+            Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path()))
+                                  .filter(|p| p.extension().is_some_and(|e| e == "rhai"))
+                                  .collect(),
+            Err(e) => {
+                debug!("Not loading scripts from {}: {}", DIRECTORY, e);
+                Vec::new()
+            },
+        };
+        files.sort();
+
+        let mut old: HashMap<_, _> = self.scripts.drain(..).map(|s| (s.name.clone(), s)).collect();
+        let mut errors = Vec::new();
+
+        for path in files {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+            match self.load_script(&path) {
+                Ok(script) => self.scripts.push(script),
+                Err(e) => {
+                    let kept = old.remove(&name);
+                    let message = format!("Can't load {}: {}.{}", name, e,
+                                          if kept.is_some() { " The old version is kept." } else { "" });
+
+                    error!("{}", message);
+                    errors.push(message);
+                    self.scripts.extend(kept);
+                },
+            }
+        }
+
+        info!("Loaded {} scripts.", self.scripts.len());
+
+        errors
+    }
+
+    /// Runs a script, and keeps the commands and handlers it registers.
+    fn load_script(&self, path: &Path) -> Result<Script, Box<EvalAltResult>> {
+        let ast = self.engine.compile_file(path.to_path_buf())?;
+
+        self.registry.lock().unwrap().loading = true;
+        let result = self.engine.run_ast_with_scope(&mut Scope::new(), &ast);
+
+        let registered = mem::take(&mut *self.registry.lock().unwrap());
+
+        result.map(|()| Script {
+            name: path.file_name().unwrap().to_string_lossy().into_owned(),
+            ast,
+            commands: registered.commands,
+            handlers: registered.handlers,
+        })
+    }
+
+    /// Calls a function of a script, with the context available to the functions
+    /// that scripts use.
+    /// # Return value
+    /// Returns what the function returned, or None if it failed.
+    fn call(&self, ctx: &mut HookContext, script: &Script, f: &FnPtr, args: impl FuncArgs) -> Option<Dynamic> {
+        struct Restore(*mut ());
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CONTEXT.with(|c| c.set(self.0));
+            }
+        }
+
+        let _restore = Restore(CONTEXT.with(|c| c.replace(ctx as *mut HookContext as *mut ())));
+
+        match f.call::<Dynamic>(&self.engine, &script.ast, args) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("Script {} failed: {}", script.name, e);
+                None
+            },
+        }
+    }
+
+    /// Calls the handlers of an event, in the order the scripts were loaded in,
+    /// until one cancels it. Handlers are given the event too, if there is one.
+    fn handle(&self, ctx: &mut HookContext, event: &str, id: client::Id, mut ev: Option<&mut Map>) -> Flow {
+        for script in &self.scripts {
+            for (_, f) in script.handlers.iter().filter(|h| h.0 == event) {
+                let result = match ev {
+                    Some(ref m) => self.call(ctx, script, f, (id as INT, (**m).clone())),
+                    None => self.call(ctx, script, f, (id as INT,)),
+                };
+
+                match result {
+                    Some(ref v) if v.as_bool() == Ok(false) => return Flow::Cancel,
+                    Some(v) => {
+                        if let (Some(m), Some(changed)) = (ev.as_deref_mut(), v.try_cast::<Map>()) {
+                            *m = changed;
+                        }
+                    },
+                    None => {},
+                }
+            }
+        }
+
+        Flow::Continue
+    }
+
+    /// Handles `/scripts`, which admins use to list and reload the scripts.
+    fn handle_scripts(&mut self, ctx: &mut HookContext, id: client::Id, args: &[&str]) {
+        if ctx.role(id) < Role::Admin {
+            ctx.tell(id, &format!("You must be a {} to do that.", Role::Admin));
+            return;
+        }
+
+        match *args {
+            [] if self.scripts.is_empty() => ctx.tell(id, "No scripts are loaded."),
+            [] => {
+                let names: Vec<_> = self.scripts.iter().map(|s| s.name.as_str()).collect();
+                ctx.tell(id, &format!("Scripts: {}", names.join(", ")));
+            },
+            ["reload"] => {
+                for e in self.reload() {
+                    ctx.tell(id, &e);
+                }
+
+                ctx.tell(id, &format!("Loaded {} scripts.", self.scripts.len()));
+            },
+            _ => ctx.tell(id, "Usage: /scripts [reload]"),
+        }
+    }
+}
+
+impl ServerHook for Scripts {
+    fn on_join(&mut self, ctx: &mut HookContext, id: client::Id) -> Flow {
+        self.handle(ctx, "join", id, None)
+    }
+
+    fn on_leave(&mut self, ctx: &mut HookContext, id: client::Id) {
+        self.handle(ctx, "leave", id, None);
+    }
+
+    fn on_chat(&mut self, ctx: &mut HookContext, id: client::Id, ev: &mut TalkEvent) -> Flow {
+        let mut map = Map::new();
+        map.insert("text".into(), ev.text.clone().into());
+
+        let flow = self.handle(ctx, "chat", id, Some(&mut map));
+        update_text(&map, "text", &mut ev.text);
+
+        flow
+    }
+
+    fn on_block(&mut self, ctx: &mut HookContext, id: client::Id, ev: &mut BlockEvent) -> Flow {
+        let mut map = position_map(ev.x, ev.y, ev.z);
+        map.insert("w".into(), (ev.w as INT).into());
+
+        let flow = self.handle(ctx, "block", id, Some(&mut map));
+        update_position(&map, &mut ev.x, &mut ev.y, &mut ev.z);
+        update_int(&map, "w", &mut ev.w);
+
+        flow
+    }
+
+    fn on_sign(&mut self, ctx: &mut HookContext, id: client::Id, ev: &mut SignEvent) -> Flow {
+        let mut map = position_map(ev.x, ev.y, ev.z);
+        map.insert("face".into(), (ev.face as INT).into());
+        map.insert("text".into(), ev.text.clone().into());
+
+        let flow = self.handle(ctx, "sign", id, Some(&mut map));
+        update_position(&map, &mut ev.x, &mut ev.y, &mut ev.z);
+        update_int(&map, "face", &mut ev.face);
+        update_text(&map, "text", &mut ev.text);
+
+        flow
+    }
+
+    fn on_light(&mut self, ctx: &mut HookContext, id: client::Id, ev: &mut LightEvent) -> Flow {
+        let mut map = position_map(ev.x, ev.y, ev.z);
+        map.insert("w".into(), (ev.w as INT).into());
+
+        let flow = self.handle(ctx, "light", id, Some(&mut map));
+        update_position(&map, &mut ev.x, &mut ev.y, &mut ev.z);
+        update_int(&map, "w", &mut ev.w);
+
+        flow
+    }
+
+    fn on_command(&mut self, ctx: &mut HookContext, id: client::Id, command: &mut String) -> Flow {
+        let args: Vec<&str> = command.split_whitespace().collect();
+        let name = args.first().cloned().unwrap_or("");
+
+        if name == "scripts" {
+            self.handle_scripts(ctx, id, &args[1..]);
+            return Flow::Cancel;
+        }
+
+        let found = self.scripts.iter().find_map(|s| s.commands.get(name).map(|f| (s, f)));
+
+        match found {
+            Some((script, f)) => {
+                let args: Array = args[1..].iter().map(|&a| a.into()).collect();
+
+                if self.call(ctx, script, f, (id as INT, args)).is_none() {
+                    ctx.tell(id, "That command failed, because its script failed.");
+                }
+
+                Flow::Cancel
+            },
+            None => Flow::Continue,
+        }
+    }
+}
+
+/// Creates the engine that runs scripts, with the functions that scripts use.
+fn engine(registry: &Arc<Mutex<Registry>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| info!("{}", text));
+    engine.on_debug(|text, source, pos| debug!("{} at {}: {}", source.unwrap_or("script"), pos, text));
+
+    let r = registry.clone();
+    engine.register_fn("command", move |name: &str, f: FnPtr| -> Result<(), Box<EvalAltResult>> {
+        let mut r = r.lock().unwrap();
+
+        if !r.loading {
+            return Err("commands can only be registered while the script is loaded".into());
+        }
+
+        r.commands.insert(name.to_string(), f);
+        Ok(())
+    });
+
+    let r = registry.clone();
+    engine.register_fn("on", move |event: &str, f: FnPtr| -> Result<(), Box<EvalAltResult>> {
+        let mut r = r.lock().unwrap();
+
+        if !r.loading {
+            return Err("handlers can only be registered while the script is loaded".into());
+        }
+        if !EVENTS.contains(&event) {
+            return Err(format!("there is no event called {}", event).into());
+        }
+
+        r.handlers.push((event.to_string(), f));
+        Ok(())
+    });
+
+    engine.register_fn("players", || with_context(|ctx| {
+        ctx.players().into_iter().map(|id| Dynamic::from(id as INT)).collect::<Array>()
+    }));
+    engine.register_fn("nick", |id: INT| with_context(|ctx| {
+        client_id(id).and_then(|id| ctx.nick(id)).map_or(Dynamic::UNIT, Dynamic::from)
+    }));
+    engine.register_fn("role", |id: INT| with_context(|ctx| {
+        client_id(id).map_or(Role::Player, |id| ctx.role(id)).to_string()
+    }));
+    engine.register_fn("position", |id: INT| with_context(|ctx| {
+        match client_id(id).and_then(|id| ctx.position(id)) {
+            Some((x, y, z, rx, ry)) => {
+                let mut map = Map::new();
+                for &(key, v) in &[("x", x), ("y", y), ("z", z), ("rx", rx), ("ry", ry)] {
+                    map.insert(key.into(), Dynamic::from_float(v.into()));
+                }
+
+                map.into()
+            },
+            None => Dynamic::UNIT,
+        }
+    }));
+    engine.register_fn("tell", |id: INT, text: &str| with_context(|ctx| {
+        if let Some(id) = client_id(id) {
+            ctx.tell(id, text);
+        }
+    }));
+    engine.register_fn("broadcast", |text: &str| with_context(|ctx| ctx.broadcast(text)));
+    engine.register_fn("kick", |id: INT, reason: &str| with_context(|ctx| {
+        if let Some(id) = client_id(id) {
+            ctx.kick(id, reason);
+        }
+    }));
+
+    engine.register_fn("block", |x: INT, y: INT, z: INT| -> Result<INT, Box<EvalAltResult>> {
+        let xyz = xyz(x, y, z)?;
+        with_context(|ctx| ctx.world().get_block(xyz).map(|b| b.0 as INT))?.map_err(|e| e.to_string().into())
+    });
+    engine.register_fn("set_block", |x: INT, y: INT, z: INT, w: INT| {
+        set(xyz(x, y, z)?, Value::Block(block(i8::try_from(w).ok().map(Block))?))
+    });
+    engine.register_fn("set_block", |x: INT, y: INT, z: INT, name: &str| {
+        set(xyz(x, y, z)?, Value::Block(block(block_type_by_name(name).map(|t| Block(t.id)))?))
+    });
+    engine.register_fn("sign", |x: INT, y: INT, z: INT, face: INT| -> Result<String, Box<EvalAltResult>> {
+        let (xyz, face) = (xyz(x, y, z)?, sign_face(face)?);
+        with_context(|ctx| ctx.world().get_sign(xyz, face).map(|s| s.0))?.map_err(|e| e.to_string().into())
+    });
+    engine.register_fn("set_sign", |x: INT, y: INT, z: INT, face: INT, text: &str| {
+        set(xyz(x, y, z)?, Value::Sign(sign_face(face)?, Sign(text.to_string())))
+    });
+    engine.register_fn("light", |x: INT, y: INT, z: INT| -> Result<INT, Box<EvalAltResult>> {
+        let xyz = xyz(x, y, z)?;
+        with_context(|ctx| ctx.world().light_at(xyz).map(|l| l.0 as INT))?.map_err(|e| e.to_string().into())
+    });
+    engine.register_fn("set_light", |x: INT, y: INT, z: INT, w: INT| {
+        match u8::try_from(w) {
+            Ok(w) if w <= MAX_LIGHT => set(xyz(x, y, z)?, Value::Light(Light(w))),
+            _ => Err(format!("there is no light level {}", w).into()),
+        }
+    });
+    engine.register_fn("highest_block", |x: INT, z: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+        let (x, _, z) = xyz(x, 1, z)?;
+        let highest = with_context(|ctx| ctx.world().highest_block(x, z))?.map_err(|e| e.to_string())?;
+
+        Ok(highest.map_or(Dynamic::UNIT, |(y, _)| Dynamic::from(y as INT)))
+    });
+
+    engine
+}
+
+/// Calls `f` with the context of the hook that is running a script.
+/// # Errors
+/// Returns an error if no hook is running a script, such as while a script is loaded.
+fn with_context<T, F: FnOnce(&mut HookContext) -> T>(f: F) -> Result<T, Box<EvalAltResult>> {
+    let ctx = CONTEXT.with(Cell::get) as *mut HookContext;
+
+    if ctx.is_null() {
+        return Err("this can only be used by commands and event handlers".into());
+    }
+
+    // The pointer was set by `Scripts::call` from a context that outlives the call,
+    // and the hook doesn't use the context itself until the script returns. The
+    // functions that scripts use don't run scripts, so this is the only reference.
+    Ok(f(unsafe { &mut *ctx }))
+}
+
+/// Changes the world and sends the change to every client.
+fn set(xyz: (i32, i32, i32), value: Value) -> Result<(), Box<EvalAltResult>> {
+    with_context(|ctx| ctx.edit(&[(xyz, value)]))?.map_err(|e| e.to_string().into())
+}
+
+fn client_id(id: INT) -> Option<client::Id> {
+    client::Id::try_from(id).ok()
+}
+
+fn xyz(x: INT, y: INT, z: INT) -> Result<(i32, i32, i32), Box<EvalAltResult>> {
+    match (i32::try_from(x), i32::try_from(y), i32::try_from(z)) {
+        (Ok(x), Ok(y), Ok(z)) if editable_height(y) && within_world(x, z) => Ok((x, y, z)),
+        _ => Err(format!("{}, {}, {} is outside the world", x, y, z).into()),
+    }
+}
+
+fn block(block: Option<Block>) -> Result<Block, Box<EvalAltResult>> {
+    block.filter(|b| b.is_air() || b.block_type().is_some()).ok_or_else(|| "there is no such block".into())
+}
+
+fn sign_face(face: INT) -> Result<u8, Box<EvalAltResult>> {
+    match u8::try_from(face) {
+        Ok(face) if face <= MAX_SIGN_FACE => Ok(face),
+        _ => Err(format!("there is no sign face {}", face).into()),
+    }
+}
+
+fn position_map(x: i32, y: i32, z: i32) -> Map {
+    let mut map = Map::new();
+    map.insert("x".into(), (x as INT).into());
+    map.insert("y".into(), (y as INT).into());
+    map.insert("z".into(), (z as INT).into());
+
+    map
+}
+
+fn update_position(map: &Map, x: &mut i32, y: &mut i32, z: &mut i32) {
+    update_int(map, "x", x);
+    update_int(map, "y", y);
+    update_int(map, "z", z);
+}
+
+/// Copies a number from a map that a handler returned into an event, if it fits.
+fn update_int<T: TryFrom<INT>>(map: &Map, key: &str, field: &mut T) {
+    if let Some(v) = map.get(key).and_then(|v| v.as_int().ok()).and_then(|v| T::try_from(v).ok()) {
+        *field = v;
+    }
+}
+
+/// Copies text from a map that a handler returned into an event.
+fn update_text(map: &Map, key: &str, field: &mut String) {
+    if let Some(text) = map.get(key).and_then(|v| v.clone().into_string().ok()) {
+        *field = text;
+    }
+}
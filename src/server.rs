@@ -5,15 +5,35 @@ use std::net::TcpListener;
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::thread;
+use audit::{Actor, AuditLog};
 use client;
 use commands::CommandHandler;
+use config::Config;
+use console::{self, Consoles};
+use edit;
+use health::{Health, Heartbeat};
+use hook::{Flow, HookContext, ServerHook};
+use http::{self, StatusSource};
 use event::{BlockEvent, ChunkRequestEvent, Event, IdEvent, PositionEvent,
             LightEvent, SignEvent, TalkEvent};
+use limit::{EditKind, Verdict};
+use logging;
+use metrics::Metrics;
 use nick::NickManager;
-use world::{Block, Light, Sign, World};
+use rcon;
+use role::{Role, RoleManager};
+use script::Scripts;
+use world::{Backups, Block, ChunkBlocks, ChunkLights, ChunkSigns, editable_height, Flag, Light, MAX_LIGHT,
+            MAX_SIGN_FACE, MAX_SIGN_LENGTH, Sign, Value, World, WorldError};
 
 pub const DAY_LENGTH: u32 = 600;
 
+/// Everything in a chunk that is sent to clients.
+type ChunkContents = (Option<ChunkBlocks>, Option<ChunkSigns>, Option<ChunkLights>);
+
+/// How often the event thread checks whether the world is being saved, when no events arrive.
+const PERSISTENCE_CHECK: Duration = Duration::from_secs(1);
+
 /// The core server wrapper.
 ///
 /// Runs the show, working with the incoming connections and handling
@@ -25,37 +45,55 @@ pub struct Server {
     disconnects: (mpsc::Sender<client::Id>, mpsc::Receiver<client::Id>),
     channel: (mpsc::Sender<IdEvent>, mpsc::Receiver<IdEvent>),
     nicks: Arc<Mutex<NickManager>>,
+    roles: Arc<Mutex<RoleManager>>,
     daytime: ServerTime,
     world: World,
+    backups: Backups,
+    consoles: Consoles,
+    health: Health,
+    metrics: Metrics,
+    audit: AuditLog,
+    hooks: Vec<Box<dyn ServerHook>>,
 }
 
 impl Server {
-    /// Creates a new server and launches it. The server socket will be bound,
-    /// and the server listener and event threads will start immediately.
+    /// Creates a new server without hooks other than the scripts, and launches it.
     pub fn run() {
-        let s = Server {
-            listener: TcpListener::bind("0.0.0.0:4080").unwrap(),
-            clients: Arc::new(Mutex::new(HashMap::new())),
-            current_id: 1,
-            disconnects: mpsc::channel(),
-            channel: mpsc::channel(),
-            nicks: Arc::new(Mutex::new(NickManager::new())),
-            daytime: ServerTime {
-                        from: Instant::now(),
-                        offset: Duration::new(DAY_LENGTH as u64 / 2, 0),
-            },
-            world: World::new(),
-        };
+        ServerBuilder::new().run();
+    }
 
-        s.listener();
+    /// Returns a builder for a server with hooks.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
     }
 
     fn listener(mut self) {
-        EventThread::run(self.channel.1,
-                         self.clients.clone(),
-                         self.disconnects.0,
-                         self.world,
-                         self.nicks.clone());
+        let _running = self.health.listener.guard();
+
+        let command = CommandHandler::new(self.clients.clone(),
+                                          self.nicks.clone(),
+                                          self.roles.clone(),
+                                          self.backups,
+                                          self.consoles.clone(),
+                                          self.metrics.clone(),
+                                          self.audit.clone());
+
+        let e = EventThread {
+            rx: self.channel.1,
+            clients: self.clients.clone(),
+            disconnects: self.disconnects.0,
+            world: self.world,
+            roles: self.roles.clone(),
+            command,
+            consoles: self.consoles,
+            heartbeat: self.health.event_thread.clone(),
+            metrics: self.metrics.clone(),
+            audit: self.audit.clone(),
+            hooks: self.hooks,
+            degraded: false,
+        };
+
+        e.event_thread();
 
         for i in self.listener.incoming() {
             let stream = i.unwrap();
@@ -68,99 +106,288 @@ impl Server {
                 self.current_id += 1;
             }
 
-            let nick = match self.nicks.lock().unwrap().get(&stream.peer_addr().unwrap().ip()) {
+            let ip = stream.peer_addr().unwrap().ip();
+            let nick = match self.nicks.lock().unwrap().get(&ip) {
                 Some(s) => s.to_string(),
-                None => "guest".to_string() + &id.to_string(),
+                None => format!("guest{}", id),
             };
 
-            let mut clients = self.clients.lock().unwrap();
+            let mut clients = self.metrics.lock_clients(&self.clients);
 
             if let Ok(c) = client::Client::run(stream,
                                                self.channel.0.clone(),
                                                id,
                                                nick,
                                                self.daytime,
-                                               &mut clients) {
+                                               &mut clients,
+                                               self.metrics.clone()) {
+                self.audit.record("join", &Actor::player(c.nick(), ip), "", "");
                 clients.insert(id, c);
             }
         }
     }
 }
 
+/// Builds a server with hooks that extend what it does.
+#[derive(Default)]
+pub struct ServerBuilder {
+    hooks: Vec<Box<dyn ServerHook>>,
+}
+
+impl ServerBuilder {
+    /// Creates a builder for a server without hooks.
+    pub fn new() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Adds a hook. Hooks are called in the order they are added.
+    pub fn hook<H: ServerHook + 'static>(mut self, hook: H) -> ServerBuilder {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Creates the server and launches it. The server socket will be bound,
+    /// and the server listener and event threads will start immediately.
+    /// The scripts are loaded as a hook after the hooks that were added.
+    pub fn run(mut self) {
+        let config = Config::load();
+        logging::init(&config.log);
+
+        self.hooks.push(Box::new(Scripts::load()));
+
+        let world = match World::new(&config.database) {
+            Ok(w) => w,
+            Err(e) => {
+                error!("Can't load the world: {}", e);
+                return;
+            },
+        };
+
+        let s = Server {
+            listener: TcpListener::bind("0.0.0.0:4080").unwrap(),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            current_id: 1,
+            disconnects: mpsc::channel(),
+            channel: mpsc::channel(),
+            nicks: Arc::new(Mutex::new(NickManager::new())),
+            roles: Arc::new(Mutex::new(RoleManager::new())),
+            daytime: ServerTime {
+                        from: Instant::now(),
+                        offset: Duration::new(DAY_LENGTH as u64 / 2, 0),
+            },
+            health: Health {
+                listener: Heartbeat::new(),
+                event_thread: Heartbeat::new(),
+                world: world.monitor(),
+            },
+            world,
+            backups: Backups::start(&config.backup),
+            consoles: Consoles::new(),
+            metrics: Metrics::new(),
+            audit: AuditLog::new(),
+            hooks: self.hooks,
+        };
+
+        console::run(s.channel.0.clone(), &s.consoles);
+        rcon::run(&config.rcon, s.channel.0.clone(), &s.consoles);
+
+        if let Some(address) = config.http.address {
+            http::run(address, StatusSource {
+                clients: s.clients.clone(),
+                daytime: s.daytime,
+                started: Instant::now(),
+                health: s.health.clone(),
+                metrics: s.metrics.clone(),
+            });
+        }
+
+        s.listener();
+    }
+}
+
 struct EventThread {
     rx: mpsc::Receiver<IdEvent>,
     clients: Arc<Mutex<HashMap<client::Id, client::Client>>>,
     disconnects: mpsc::Sender<client::Id>,
     world: World,
+    roles: Arc<Mutex<RoleManager>>,
     command: CommandHandler,
+    consoles: Consoles,
+    heartbeat: Heartbeat,
+    metrics: Metrics,
+    audit: AuditLog,
+    hooks: Vec<Box<dyn ServerHook>>,
+    degraded: bool,
 }
 
 impl EventThread {
-    fn run(rx: mpsc::Receiver<IdEvent>,
-           clients: Arc<Mutex<HashMap<client::Id, client::Client>>>,
-           disconnects: mpsc::Sender<client::Id>,
-           world: World,
-           nicks: Arc<Mutex<NickManager>>) {
-        let command = CommandHandler::new(clients.clone(), nicks);
-
-        let e = EventThread {
-            rx,
-            clients,
-            disconnects,
-            world,
-            command,
-        };
-
-        e.event_thread();
-    }
-
     fn event_thread(mut self) {
         thread::spawn(move || {
+            let _running = self.heartbeat.guard();
+
             loop {
-                if let Ok(ev) = self.rx.recv() {
-                    match ev.event {
-                        Event::Disconnected => {
-                            self.handle_disconnect_event(ev.id);
-                        },
-                        Event::Position(p) => {
-                            println!("{:?}", p);
-                            self.handle_position_event(ev.id, p);
-                        },
-                        Event::Talk(t) => {
-                            if t.text.starts_with('/') {
-                                self.command.handle_command(ev.id, &t.text[1..]);
-                            } else {
-                                println!("CHAT: {} {}", ev.peer, t.text.lines().next().unwrap_or(""));
-                                self.handle_talk_event(ev.id, t);
-                            }
-                        },
-                        Event::Block(b) => {
-                            println!("{:?}", b);
-                            self.handle_block_event(b);
-                        }
-                        Event::ChunkRequest(c) => {
-                            println!("{:?}", c);
-                            self.handle_chunk_event(ev.id, c);
-                        }
-                        Event::Sign(s) => {
-                            println!("{:?}", s);
-                            self.handle_sign_event(s);
-                        }
-                        Event::Light(l) => {
-                            println!("{:?}", l);
-                            self.handle_light_event(l);
-                        }
-                    }
+                self.heartbeat.beat();
+
+                if let Ok(ev) = self.rx.recv_timeout(PERSISTENCE_CHECK) {
+                    self.metrics.count_event(&ev.event);
+                    self.handle_event(ev);
                 }
+
+                self.check_persistence();
             }
         });
     }
 
+    /// Lets the hooks see an event, then handles it unless a hook cancelled it.
+    fn handle_event(&mut self, ev: IdEvent) {
+        use world::chunked;
+
+        let id = ev.id;
+
+        match ev.event {
+            Event::Join => {
+                if !self.run_hooks(|h, ctx| h.on_join(ctx, id)) {
+                    if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                        c.kick("You may not join this server.");
+                    }
+                }
+            },
+            Event::Disconnected => {
+                self.run_hooks(|h, ctx| {
+                    h.on_leave(ctx, id);
+                    Flow::Continue
+                });
+                self.handle_disconnect_event(id);
+            },
+            Event::Position(p) => {
+                trace!(client = id; "{:?}", p);
+                self.handle_position_event(id, p);
+            },
+            Event::Talk(mut t) => {
+                if t.text.starts_with('/') {
+                    let mut command = t.text[1..].to_string();
+
+                    if self.run_hooks(|h, ctx| h.on_command(ctx, id, &mut command)) {
+                        self.command.handle_command(id, &command, &mut self.world);
+                    }
+                } else {
+                    info!(client = id, peer:% = ev.peer; "Chat: {}", t.text.lines().next().unwrap_or(""));
+
+                    if self.run_hooks(|h, ctx| h.on_chat(ctx, id, &mut t)) {
+                        self.handle_talk_event(id, t);
+                    }
+                }
+            },
+            Event::Block(mut b) => {
+                debug!(client = id, p = chunked(b.x), q = chunked(b.z); "{:?}", b);
+
+                // The client already shows its edit, so it is undone there if a
+                // hook cancels it or moves it elsewhere.
+                let xyz = (b.x, b.y, b.z);
+                let handled = self.run_hooks(|h, ctx| h.on_block(ctx, id, &mut b));
+                let moved = (b.x, b.y, b.z) != xyz;
+
+                if handled {
+                    self.handle_block_event(id, b);
+                }
+                if !handled || moved {
+                    if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                        self.revert_block(c, xyz);
+                    }
+                }
+            },
+            Event::ChunkRequest(mut c) => {
+                debug!(client = id, p = c.p, q = c.q; "{:?}", c);
+
+                if self.run_hooks(|h, ctx| h.on_chunk_request(ctx, id, &mut c)) {
+                    self.handle_chunk_event(id, c);
+                }
+            },
+            Event::Sign(mut s) => {
+                debug!(client = id, p = chunked(s.x), q = chunked(s.z); "{:?}", s);
+
+                let (xyz, face) = ((s.x, s.y, s.z), s.face);
+                let handled = self.run_hooks(|h, ctx| h.on_sign(ctx, id, &mut s));
+                let moved = ((s.x, s.y, s.z), s.face) != (xyz, face);
+
+                if handled {
+                    self.handle_sign_event(id, s);
+                }
+                if !handled || moved {
+                    if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                        self.revert_sign(c, xyz, face);
+                    }
+                }
+            },
+            Event::Light(mut l) => {
+                debug!(client = id, p = chunked(l.x), q = chunked(l.z); "{:?}", l);
+
+                let xyz = (l.x, l.y, l.z);
+                let handled = self.run_hooks(|h, ctx| h.on_light(ctx, id, &mut l));
+                let moved = (l.x, l.y, l.z) != xyz;
+
+                if handled {
+                    self.handle_light_event(id, l);
+                }
+                if !handled || moved {
+                    if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+                        self.revert_light(c, xyz);
+                    }
+                }
+            },
+            Event::Console(mut command) => {
+                info!(console = id; "Console command: {}", command);
+                self.audit.record("console", &Actor::console(), "", &command);
+
+                if self.run_hooks(|h, ctx| h.on_command(ctx, id, &mut command)) {
+                    self.command.handle_command(id, &command, &mut self.world);
+                }
+
+                self.consoles.done(id);
+            },
+        }
+    }
+
+    /// Calls the hooks in the order they were registered, until one cancels the event.
+    /// # Return value
+    /// Returns false if the event was cancelled.
+    fn run_hooks<F>(&mut self, mut call: F) -> bool
+            where F: FnMut(&mut dyn ServerHook, &mut HookContext) -> Flow {
+        let mut ctx = HookContext::new(&mut self.world, &self.clients, &self.roles, &self.consoles,
+                                       &self.metrics);
+
+        self.hooks.iter_mut().all(|h| call(h.as_mut(), &mut ctx) == Flow::Continue)
+    }
+
+    /// Tells everyone when the world stops being saved, and when it is saved again.
+    fn check_persistence(&mut self) {
+        let degraded = self.world.is_degraded();
+
+        if degraded == self.degraded {
+            return;
+        }
+
+        self.degraded = degraded;
+
+        let text = if degraded {
+            "Warning: the world can't be saved right now. Changes are kept until it can be."
+        } else {
+            "The world is being saved again."
+        };
+
+        for c in self.metrics.lock_clients(&self.clients).values_mut() {
+            c.broadcast_talk(text);
+        }
+    }
+
     fn handle_disconnect_event(&mut self, id: client::Id) {
-        let mut clients = self.clients.lock().unwrap();
+        let mut clients = self.metrics.lock_clients(&self.clients);
 
         let msg = match clients.get(&id) {
-            Some(client) => Some(client.nick().to_string() + " has left the game"),
+            Some(client) => {
+                self.audit.record("leave", &Actor::player(client.nick(), *client.addr()), "", "");
+                Some(client.nick().to_string() + " has left the game")
+            },
             None => None,
         };
 
@@ -173,14 +400,14 @@ impl EventThread {
                 i.1.send_disconnect(id);
 
                 if let Some(ref m) = msg {
-                    i.1.broadcast_talk(&m);
+                    i.1.broadcast_talk(m);
                 }
             }
         }
     }
 
     fn handle_position_event(&self, id: client::Id, ev: PositionEvent) {
-        for i in self.clients.lock().unwrap().iter_mut() {
+        for i in self.metrics.lock_clients(&self.clients).iter_mut() {
             if *i.0 != id {
                 i.1.send_position(id, &ev);
             } else {
@@ -190,7 +417,7 @@ impl EventThread {
     }
 
     fn handle_talk_event(&self, id: client::Id, mut ev: TalkEvent) {
-        let mut clients = self.clients.lock().unwrap();
+        let mut clients = self.metrics.lock_clients(&self.clients);
 
         ev.text = format!("{}> {}", clients.get(&id).unwrap().nick(), ev.text);
 
@@ -199,42 +426,66 @@ impl EventThread {
         }
     }
 
-    fn handle_block_event(&mut self, ev: BlockEvent) {
-        use world::chunked;
+    fn handle_block_event(&mut self, id: client::Id, ev: BlockEvent) {
+        let xyz = (ev.x, ev.y, ev.z);
+
+        // Blocks hit in inspection mode are not changed. The moderator is told
+        // their history instead.
+        let inspecting = match self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            Some(c) if c.is_inspecting() => {
+                self.revert_block(c, xyz);
+                true
+            },
+            _ => false,
+        };
+
+        if inspecting {
+            self.command.blame(id, xyz, &self.world);
+            return;
+        }
 
-        let (p, q) = (chunked(ev.x), chunked(ev.z));
+        // Blocks hit while selecting are not changed either. Mining a block selects
+        // the first corner, and placing one selects the second.
+        if let Some(c) = self.metrics.lock_clients(&self.clients).get_mut(&id) {
+            if c.is_selecting() {
+                self.revert_block(c, xyz);
 
-        let mut clients = self.clients.lock().unwrap();
+                let corner = if Block(ev.w).is_air() { 0 } else { 1 };
+                c.set_selection_corner(corner, xyz);
+                c.broadcast_talk(&format!("Position {} set to {:?}.", corner + 1, xyz));
 
-        for i in clients.values_mut() {
-            i.send_block(&ev);
-            i.broadcast_redraw((p, q));
+                return;
+            }
         }
 
-        self.world.set_block((ev.x, ev.y, ev.z), (p, q), Block(ev.w));
-
-        // Craft overlaps chunks by 2 blocks.
-        // ______________
-        // |    #|#     |
-        // | 0  #|#  1  |
-        // |____#|#____ |
-        //
-        // We must update adjacent chunks as well if the new block
-        // lies on this line.
-
-        for dx in -1..2 {
-            for dz in -1..2 {
-                if      (dx == 0 && dz == 0) ||
-                        (dx != 0 && chunked(ev.x + dx) == p) ||
-                        (dz != 0 && chunked(ev.z + dz) == q) {
-                    continue;
-                }
+        let mut clients = self.metrics.lock_clients(&self.clients);
 
-                self.world.set_block((ev.x, ev.y, ev.z), (p + dx, q + dz), Block(-ev.w));
-                for i in clients.values_mut() {
-                    i.broadcast_block(((ev.x, ev.y, ev.z), &Block(-ev.w)), (p + dx, q + dz));
-                    i.broadcast_redraw((p + dx, q + dz));
-                }
+        let valid = editable_height(ev.y) && Block(ev.w).is_placeable();
+        if !valid {
+            warn!(client = id; "Rejected invalid {:?}", ev);
+        }
+
+        if !valid ||
+           !self.edit_allowed(&mut clients, id, EditKind::Block) ||
+           !self.edit_permitted(&mut clients, id, (ev.x, ev.y, ev.z), Flag::Build) {
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_block(c, (ev.x, ev.y, ev.z));
+            }
+
+            return;
+        }
+
+        let author = clients[&id].author();
+        let result = edit::apply(&mut self.world,
+                                 &mut clients,
+                                 Some(&author),
+                                 &[((ev.x, ev.y, ev.z), Value::Block(Block(ev.w)))]);
+
+        if let Err(e) = result {
+            Self::report_edit_error(&mut clients, id, &e);
+
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_block(c, (ev.x, ev.y, ev.z));
             }
         }
     }
@@ -242,12 +493,22 @@ impl EventThread {
     fn handle_chunk_event(&self, id: client::Id, ev: ChunkRequestEvent) {
         use world::CHUNK_SIZE;
 
-        let mut clients = self.clients.lock().unwrap();
+        let mut clients = self.metrics.lock_clients(&self.clients);
 
         if let Some(c) = clients.get_mut(&id) {
+            let (blocks, signs, lights) = match self.load_chunk((ev.p, ev.q)) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    error!(client = id, p = ev.p, q = ev.q; "Can't load a chunk: {}", e);
+                    c.broadcast_talk("Part of the world can't be loaded right now.");
+
+                    return;
+                },
+            };
+
             let mut redraw = false;
 
-            if let Some(it) = self.world.blocks_in_chunk((ev.p, ev.q)) {
+            if let Some(it) = blocks {
                 for (xyz, w) in it {
                     //println!("BLOCK: {}, {}, {}: {:?}", xyz.0, xyz.1, xyz.2, w);
 
@@ -256,28 +517,28 @@ impl EventThread {
                     let xyz = (xyz.0 as i32 + (ev.p * CHUNK_SIZE as i32) - 1,
                                xyz.1 as i32,
                                xyz.2 as i32 + (ev.q * CHUNK_SIZE as i32) - 1);
-                    c.broadcast_block((xyz, w), (ev.p, ev.q));
+                    c.broadcast_block((xyz, &w), (ev.p, ev.q));
 
                     redraw = true;
                 }
             }
 
-            if let Some(it) = self.world.signs_in_chunk((ev.p, ev.q)) {
+            if let Some(it) = signs {
                 for (xyz_face, sign) in it {
                     //println!("SIGN: {}, {}, {}: {}", xyz_face.0, xyz_face.1, xyz_face.2, sign.0);
 
-                    c.broadcast_sign((xyz_face.0, xyz_face.1, xyz_face.2), xyz_face.3, sign);
+                    c.broadcast_sign((xyz_face.0, xyz_face.1, xyz_face.2), xyz_face.3, &sign);
 
                     redraw = true;
                 }
             }
 
-            if let Some(it) = self.world.lights_in_chunk((ev.p, ev.q)) {
+            if let Some(it) = lights {
                 for (xyz, w) in it {
                     let xyz = (xyz.0 as i32 + (ev.p * CHUNK_SIZE as i32) - 1,
                                xyz.1 as i32,
                                xyz.2 as i32 + (ev.q * CHUNK_SIZE as i32) - 1);
-                    c.broadcast_light((xyz, w), (ev.p, ev.q));
+                    c.broadcast_light((xyz, &w), (ev.p, ev.q));
 
                     redraw = true;
                 }
@@ -286,35 +547,210 @@ impl EventThread {
             if redraw {
                 c.broadcast_redraw((ev.p, ev.q));
             }
+
+            self.metrics.count_chunk_request();
         }
     }
 
-    fn handle_sign_event(&mut self, ev: SignEvent) {
-        use world::chunked;
+    /// Reads everything in a chunk that is sent to clients.
+    fn load_chunk(&self, pq: (i32, i32)) -> Result<ChunkContents, WorldError> {
+        Ok((self.world.blocks_in_chunk(pq)?,
+            self.world.signs_in_chunk(pq)?,
+            self.world.lights_in_chunk(pq)?))
+    }
+
+    fn handle_sign_event(&mut self, id: client::Id, ev: SignEvent) {
+        let mut clients = self.metrics.lock_clients(&self.clients);
+
+        let valid = editable_height(ev.y) && ev.face <= MAX_SIGN_FACE && ev.text.len() <= MAX_SIGN_LENGTH;
+        if !valid {
+            warn!(client = id; "Rejected invalid {:?}", ev);
+        }
 
-        let chunk = (chunked(ev.x), chunked(ev.z));
+        if !valid ||
+           !self.edit_allowed(&mut clients, id, EditKind::Sign) ||
+           !self.edit_permitted(&mut clients, id, (ev.x, ev.y, ev.z), Flag::Sign) {
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_sign(c, (ev.x, ev.y, ev.z), ev.face);
+            }
 
-        for i in self.clients.lock().unwrap().values_mut() {
-            i.send_sign(&ev);
-            i.broadcast_redraw(chunk);
+            return;
         }
 
-        self.world.set_sign((ev.x, ev.y, ev.z), chunk, ev.face, Sign(ev.text));
+        let author = clients[&id].author();
+        let face = ev.face;
+        let result = edit::apply(&mut self.world,
+                                 &mut clients,
+                                 Some(&author),
+                                 &[((ev.x, ev.y, ev.z), Value::Sign(ev.face, Sign(ev.text)))]);
+
+        if let Err(e) = result {
+            Self::report_edit_error(&mut clients, id, &e);
+
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_sign(c, (ev.x, ev.y, ev.z), face);
+            }
+        }
     }
 
-    fn handle_light_event(&mut self, ev: LightEvent) {
-        use world::chunked;
+    fn handle_light_event(&mut self, id: client::Id, ev: LightEvent) {
+        let mut clients = self.metrics.lock_clients(&self.clients);
 
-        let (p, q) = (chunked(ev.x), chunked(ev.z));
+        let valid = editable_height(ev.y) && ev.w <= MAX_LIGHT;
+        if !valid {
+            warn!(client = id; "Rejected invalid {:?}", ev);
+        }
 
-        let mut clients = self.clients.lock().unwrap();
+        if !valid ||
+           !self.edit_allowed(&mut clients, id, EditKind::Light) ||
+           !self.edit_permitted(&mut clients, id, (ev.x, ev.y, ev.z), Flag::Light) {
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_light(c, (ev.x, ev.y, ev.z));
+            }
 
-        for i in clients.values_mut() {
-            i.send_light(&ev);
-            i.broadcast_redraw((p, q));
+            return;
         }
 
-        self.world.set_light((ev.x, ev.y, ev.z), (p, q), Light(ev.w));
+        let author = clients[&id].author();
+        let result = edit::apply(&mut self.world,
+                                 &mut clients,
+                                 Some(&author),
+                                 &[((ev.x, ev.y, ev.z), Value::Light(Light(ev.w)))]);
+
+        if let Err(e) = result {
+            Self::report_edit_error(&mut clients, id, &e);
+
+            if let Some(c) = clients.get_mut(&id) {
+                self.revert_light(c, (ev.x, ev.y, ev.z));
+            }
+        }
+    }
+
+    /// Checks the edit rate limits of the client that sent an edit.
+    /// Clients that keep exceeding their limits are kicked.
+    /// # Return value
+    /// Returns false if the edit must be rejected.
+    fn edit_allowed(&self,
+                    clients: &mut HashMap<client::Id, client::Client>,
+                    id: client::Id,
+                    kind: EditKind) -> bool {
+        match clients.get_mut(&id) {
+            Some(c) => match c.check_edit(kind) {
+                Verdict::Allow => true,
+                Verdict::Deny => false,
+                Verdict::Kick => {
+                    warn!(client = id, peer:% = c.addr(); "Kicking a client for editing too quickly.");
+                    self.audit.record("kick", &Actor::server(), c.nick(),
+                                      &format!("editing too quickly from {}", c.addr()));
+                    c.kick("You were kicked for editing the world too quickly.");
+
+                    false
+                },
+            },
+            None => false,
+        }
+    }
+
+    /// Checks the protected regions at the position of an edit. Admins may edit anywhere.
+    /// Clients are told when they try to edit a region they may not.
+    /// # Return value
+    /// Returns false if the edit must be rejected.
+    fn edit_permitted(&self,
+                      clients: &mut HashMap<client::Id, client::Client>,
+                      id: client::Id,
+                      xyz: (i32, i32, i32),
+                      flag: Flag) -> bool {
+        let c = match clients.get_mut(&id) {
+            Some(c) => c,
+            None => return false,
+        };
+
+        if self.world.regions().allows(c.addr(), xyz, flag) ||
+           self.roles.lock().unwrap().get(c.addr()) == Role::Admin {
+            true
+        } else {
+            c.broadcast_talk("You may not edit this area.");
+
+            false
+        }
+    }
+
+    /// Tells the sender of an edit that the world could not be changed.
+    fn report_edit_error(clients: &mut HashMap<client::Id, client::Client>,
+                         id: client::Id,
+                         e: &WorldError) {
+        let text = match *e {
+            WorldError::SignOnAir => "Signs can only be placed on blocks.",
+            WorldError::OutOfBounds => "Your change was not made, because it is past the edge of the world.",
+            WorldError::QueueFull => "Your change was not made, because the world can't be saved right now.",
+            _ => {
+                error!(client = id; "Can't apply an edit: {}", e);
+                "Your change was not made, because the world can't be read right now."
+            },
+        };
+
+        if let Some(c) = clients.get_mut(&id) {
+            c.broadcast_talk(text);
+        }
+    }
+
+    /// Sends the authoritative block at a position to a client, including the
+    /// copies in neighboring chunks. This undoes a rejected edit on the client.
+    ///
+    /// Only blocks that the world stores can be sent back. Craft generates the
+    /// terrain everywhere else itself, so for those positions the client is only
+    /// told to redraw the chunks, rather than being sent air.
+    fn revert_block(&self, c: &mut client::Client, xyz: (i32, i32, i32)) {
+        use world::{border_chunks, chunked};
+
+        let pq = (chunked(xyz.0), chunked(xyz.2));
+        let block = match self.world.block_at(xyz) {
+            Ok(b) => b,
+            Err(e) => {
+                error!("Can't read the block at {:?}: {}", xyz, e);
+                return;
+            },
+        };
+
+        if let Some(ref block) = block {
+            c.broadcast_block((xyz, block), pq);
+        }
+        c.broadcast_redraw(pq);
+
+        for pq in border_chunks(xyz.0, xyz.2) {
+            if let Some(ref block) = block {
+                c.broadcast_block((xyz, &Block(-block.0)), pq);
+            }
+            c.broadcast_redraw(pq);
+        }
+    }
+
+    /// Sends the authoritative sign text on a block face to a client.
+    fn revert_sign(&self, c: &mut client::Client, xyz: (i32, i32, i32), face: u8) {
+        use world::chunked;
+
+        match self.world.get_sign(xyz, face) {
+            Ok(sign) => {
+                c.broadcast_sign(xyz, face, &sign);
+                c.broadcast_redraw((chunked(xyz.0), chunked(xyz.2)));
+            },
+            Err(e) => error!("Can't read the sign at {:?}: {}", xyz, e),
+        }
+    }
+
+    /// Sends the authoritative light at a position to a client.
+    fn revert_light(&self, c: &mut client::Client, xyz: (i32, i32, i32)) {
+        use world::chunked;
+
+        let pq = (chunked(xyz.0), chunked(xyz.2));
+
+        match self.world.light_at(xyz) {
+            Ok(light) => {
+                c.broadcast_light((xyz, &light), pq);
+                c.broadcast_redraw(pq);
+            },
+            Err(e) => error!("Can't read the light at {:?}: {}", xyz, e),
+        }
     }
 }
 
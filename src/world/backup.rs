@@ -0,0 +1,240 @@
+//! Backs up the world database while the server runs, and restores backups while it doesn't.
+//!
+//! Backups are copied page by page with SQLite's online backup API, so they are consistent
+//! snapshots of everything the database thread had written when the copy started. Changes
+//! still waiting in the database thread's queue are not included.
+//!
+//! Backups are named after the UTC time they were taken, like `world-20240131-235959.db`,
+//! so sorting their names sorts them by age.
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use sqlite::{self, Connection};
+use sqlite3_sys as ffi;
+use config::BackupConfig;
+use super::{migrations, queries, BUSY_TIMEOUT, FILE, unix_time, WorldError};
+
+/// How long a backup waits before trying again when the database is locked.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Called with the path of a finished backup, or the reason it failed.
+pub type BackupCallback = Box<dyn FnOnce(Result<PathBuf, WorldError>) + Send>;
+
+/// Takes backups of the world on a schedule and on request, on a thread of its own.
+pub struct Backups {
+    tx: mpsc::Sender<BackupCallback>,
+}
+
+impl Backups {
+    /// Starts the backup thread.
+    pub fn start(config: &BackupConfig) -> Backups {
+        let (tx, rx) = mpsc::channel();
+        let config = config.clone();
+
+        thread::spawn(move || backup_thread(rx, config));
+
+        Backups {
+            tx,
+        }
+    }
+
+    /// Takes a backup as soon as possible, then calls `done` with the result.
+    pub fn request(&self, done: BackupCallback) {
+        if let Err(mpsc::SendError(done)) = self.tx.send(done) {
+            done(Err(WorldError::Stopped));
+        }
+    }
+}
+
+fn backup_thread(rx: mpsc::Receiver<BackupCallback>, config: BackupConfig) {
+    let mut next = config.interval.map(|i| Instant::now() + i);
+
+    loop {
+        let request = match next {
+            Some(at) => rx.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        let result = match request {
+            Ok(_) | Err(RecvTimeoutError::Timeout) => back_up(&config),
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        match result {
+            Ok(ref path) => info!("Backed up the world to {}.", path.display()),
+            Err(ref e) => error!("Can't back up the world: {}", e),
+        }
+
+        match request {
+            Ok(done) => done(result),
+            Err(_) => next = config.interval.map(|i| Instant::now() + i),
+        }
+    }
+}
+
+/// Takes a backup, then deletes the oldest backups beyond the number to keep.
+fn back_up(config: &BackupConfig) -> Result<PathBuf, WorldError> {
+    fs::create_dir_all(&config.directory)?;
+
+    let path = config.directory.join(format!("world-{}.db", timestamp(unix_time())));
+    snapshot(Path::new(FILE), &path)?;
+
+    if let Some(keep) = config.keep {
+        let mut backups = list(&config.directory)?;
+        let excess = backups.len().saturating_sub(keep);
+
+        for old in backups.drain(..excess) {
+            fs::remove_file(old)?;
+        }
+    }
+
+    Ok(path)
+}
+
+/// Returns the backups in a directory, oldest first.
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>, WorldError> {
+    let mut backups = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        if name.starts_with("world-") && name.ends_with(".db") {
+            backups.push(path);
+        }
+    }
+
+    backups.sort();
+
+    Ok(backups)
+}
+
+/// Copies the database at `from` into a new file at `to`. The copy is written under
+/// a temporary name first, so that an interrupted backup never looks complete.
+fn snapshot(from: &Path, to: &Path) -> Result<(), WorldError> {
+    let partial = to.with_extension("db.partial");
+
+    let result = (|| {
+        let mut source = sqlite::open(from)?;
+        source.set_busy_timeout(BUSY_TIMEOUT)?;
+
+        copy(&source, &sqlite::open(&partial)?)
+    })();
+
+    match result {
+        Ok(()) => Ok(fs::rename(&partial, to)?),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e.into())
+        },
+    }
+}
+
+/// Replaces the world with a backup. The current world is backed up first, as
+/// `pre-restore-<time>.db` in the backup directory, and is never deleted automatically.
+///
+/// The backup is refused if it is damaged, or if it was made by a newer version of
+/// the server. The world is left as it was then.
+/// # Note
+/// This must only be done while the server is stopped.
+/// # Return value
+/// Returns the path of the backup of the replaced world.
+pub fn restore(backup: &Path, config: &BackupConfig) -> Result<PathBuf, WorldError> {
+    let source = sqlite::open(backup)?;
+    check_integrity(&source)?;
+    migrations::check_version(&source)?;
+
+    fs::create_dir_all(&config.directory)?;
+    let previous = config.directory.join(format!("pre-restore-{}.db", timestamp(unix_time())));
+    snapshot(Path::new(FILE), &previous)?;
+
+    let mut world = sqlite::open(FILE)?;
+    world.set_busy_timeout(BUSY_TIMEOUT)?;
+    copy(&source, &world)?;
+
+    Ok(previous)
+}
+
+/// Runs SQLite's quick integrity check, which lists the problems it finds, or
+/// returns the single row `ok`.
+fn check_integrity(conn: &Connection) -> Result<(), WorldError> {
+    let mut cursor = conn.prepare(queries::QUICK_CHECK)?.cursor();
+    let mut problems = Vec::new();
+
+    while let Some(row) = cursor.next()? {
+        problems.push(row[0].as_string().unwrap_or("").to_string());
+    }
+
+    if problems.len() == 1 && problems[0] == "ok" {
+        Ok(())
+    } else {
+        Err(WorldError::Damaged(problems.join("; ")))
+    }
+}
+
+/// Copies every page of one database into another with SQLite's online backup API.
+fn copy(from: &Connection, to: &Connection) -> sqlite::Result<()> {
+    let main = CString::new("main").unwrap();
+    let attempts = BUSY_TIMEOUT / RETRY_DELAY.as_millis() as usize;
+
+    unsafe {
+        let backup = ffi::sqlite3_backup_init(to.as_raw(), main.as_ptr(), from.as_raw(), main.as_ptr());
+        if backup.is_null() {
+            return Err(last_error(to, ffi::SQLITE_ERROR));
+        }
+
+        let mut code = ffi::SQLITE_OK;
+        for _ in 0..attempts {
+            code = ffi::sqlite3_backup_step(backup, -1);
+
+            if code != ffi::SQLITE_BUSY && code != ffi::SQLITE_LOCKED {
+                break;
+            }
+
+            thread::sleep(RETRY_DELAY);
+        }
+
+        ffi::sqlite3_backup_finish(backup);
+
+        if code == ffi::SQLITE_DONE {
+            Ok(())
+        } else {
+            Err(last_error(to, code))
+        }
+    }
+}
+
+/// Returns the error that the last call on a connection failed with, along with its code.
+fn last_error(conn: &Connection, code: i32) -> sqlite::Error {
+    let message = unsafe {
+        CStr::from_ptr(ffi::sqlite3_errmsg(conn.as_raw())).to_string_lossy().into_owned()
+    };
+
+    sqlite::Error {
+        code: Some(code as isize),
+        message: Some(message),
+    }
+}
+
+/// Formats a Unix time as a UTC date and time, like `20240131-235959`.
+pub fn timestamp(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let secs = time % 86400;
+
+    // Converts days since 1970 to a civil date. See http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
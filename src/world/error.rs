@@ -0,0 +1,83 @@
+//! Errors that occur while loading or saving the world.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use sqlite;
+
+/// Describes why the world could not be read or changed.
+#[derive(Debug)]
+pub enum WorldError {
+    /// A query on the world database failed.
+    Database(sqlite::Error),
+
+    /// A file belonging to the world, such as a backup, could not be read or written.
+    Io(io::Error),
+
+    /// The database thread has stopped, so changes can no longer be saved.
+    Stopped,
+
+    /// Some changes could not be written to the database yet. They are kept in
+    /// memory, and writing them is retried.
+    Unsaved,
+
+    /// Too many changes are waiting to be written to the database, so no more
+    /// are accepted until they are.
+    QueueFull,
+
+    /// A database failed SQLite's integrity check, with the problems it found.
+    Damaged(String),
+
+    /// A change was made past the edge of the world.
+    OutOfBounds,
+
+    /// A sign was placed on a block that was mined to air.
+    SignOnAir,
+
+    /// The database was made by a newer version of the server, with a schema
+    /// version this server does not know.
+    NewerSchema {
+        found: i64,
+        supported: i64,
+    },
+}
+
+impl Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WorldError::Database(ref e) => write!(f, "database error: {}", e),
+            WorldError::Io(ref e) => write!(f, "file error: {}", e),
+            WorldError::Stopped => write!(f, "the database thread has stopped"),
+            WorldError::Unsaved => write!(f, "some changes could not be saved"),
+            WorldError::QueueFull => write!(f, "too many changes are waiting to be saved"),
+            WorldError::Damaged(ref problems) => write!(f, "the database is damaged: {}", problems),
+            WorldError::OutOfBounds => write!(f, "the position is past the edge of the world"),
+            WorldError::SignOnAir => write!(f, "signs can't be placed on air"),
+            WorldError::NewerSchema { found, supported } =>
+                write!(f, "the database has schema version {}, but this server only supports up to {}",
+                       found, supported),
+        }
+    }
+}
+
+impl Error for WorldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            WorldError::Database(ref e) => Some(e),
+            WorldError::Io(ref e) => Some(e),
+            WorldError::Stopped | WorldError::Unsaved | WorldError::QueueFull | WorldError::Damaged(_) | WorldError::OutOfBounds | WorldError::SignOnAir | WorldError::NewerSchema { .. } => None,
+        }
+    }
+}
+
+impl From<sqlite::Error> for WorldError {
+    fn from(e: sqlite::Error) -> WorldError {
+        WorldError::Database(e)
+    }
+}
+
+impl From<io::Error> for WorldError {
+    fn from(e: io::Error) -> WorldError {
+        WorldError::Io(e)
+    }
+}
@@ -0,0 +1,68 @@
+//! The recorded history of changes made to the world by players.
+
+use std::net::IpAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use super::{Block, Light, Sign};
+
+/// Identifies the player who made a change.
+#[derive(Clone, Debug)]
+pub struct Author {
+    pub ip: IpAddr,
+    pub nick: String,
+}
+
+/// A value stored at a position in the world.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Block(Block),
+
+    /// A sign on the given face of a block. Empty text means there is no sign.
+    Sign(u8, Sign),
+
+    Light(Light),
+}
+
+/// A single recorded change.
+#[derive(Clone, Debug)]
+pub struct Change {
+    /// The unique, increasing number of this change.
+    pub id: i64,
+
+    /// The time of the change in seconds since the Unix epoch.
+    pub time: u64,
+
+    pub author: Author,
+    pub xyz: (i32, i32, i32),
+    pub old: Value,
+    pub new: Value,
+
+    /// Whether the change has been undone or rolled back.
+    pub reverted: bool,
+}
+
+/// Selects recorded changes. Every criterion that is set must match.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryQuery {
+    /// Only changes made by this player.
+    pub ip: Option<IpAddr>,
+
+    /// Only changes made at or after this time, in seconds since the Unix epoch.
+    pub since: Option<u64>,
+
+    /// Only changes at this exact position.
+    pub at: Option<(i32, i32, i32)>,
+
+    /// Only changes within the given distance on every axis of a position.
+    pub near: Option<((i32, i32, i32), i32)>,
+
+    /// Also return changes that have already been undone or rolled back.
+    pub include_reverted: bool,
+
+    /// Return at most this many changes.
+    pub limit: Option<usize>,
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,146 @@
+//! Merges the world saved by Craft's single-player mode into the server's world.
+//!
+//! Craft keeps single-player worlds in `craft.db`, using the same `block`, `sign`
+//! and `light` tables as the server. An import moves everything in it by an X and
+//! Z offset and writes it into the world database in one transaction, so a failed
+//! import leaves the world as it was.
+//!
+//! Imports write to the database directly and are not recorded in the world's
+//! history, so they should be run while the server is stopped.
+
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::str::FromStr;
+use sqlite::{self, Connection, State, Value};
+use super::{BUSY_TIMEOUT, FILE, migrations, queries, WorldError};
+
+/// What an import does where the world already holds something.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// Imported blocks, signs and lights replace those in the world.
+    Overwrite,
+
+    /// Blocks, signs and lights in the world are kept, and only imported
+    /// where the world holds nothing yet.
+    KeepExisting,
+
+    /// Chunks that hold anything in the world are not imported at all,
+    /// so that no build is mixed with another.
+    SkipConflicts,
+}
+
+impl Display for ImportPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ImportPolicy::Overwrite => "overwrite",
+            ImportPolicy::KeepExisting => "keep-existing",
+            ImportPolicy::SkipConflicts => "skip-conflicts",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ImportPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ImportPolicy, ()> {
+        match s {
+            "overwrite" => Ok(ImportPolicy::Overwrite),
+            "keep-existing" => Ok(ImportPolicy::KeepExisting),
+            "skip-conflicts" => Ok(ImportPolicy::SkipConflicts),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The numbers of rows an import found and wrote, for each kind of row.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportCounts {
+    pub blocks: (u64, u64),
+    pub signs: (u64, u64),
+    pub lights: (u64, u64),
+}
+
+impl Display for ImportCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "imported {} of {} blocks, {} of {} signs and {} of {} lights",
+               self.blocks.1, self.blocks.0,
+               self.signs.1, self.signs.0,
+               self.lights.1, self.lights.0)
+    }
+}
+
+/// Imports a Craft database into the world database, moving every position by
+/// `offset` blocks along X and Z.
+/// # Return value
+/// Returns how many rows were found and written, or an error if the import failed,
+/// in which case nothing was written.
+pub fn import_craft_db<P: AsRef<Path>>(source: P,
+                                       offset: (i32, i32),
+                                       policy: ImportPolicy) -> Result<ImportCounts, WorldError> {
+    let mut conn = sqlite::open(FILE)?;
+    conn.set_busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute(queries::JOURNAL_MODE)?;
+
+    migrations::migrate(&conn)?;
+
+    let source = source.as_ref().to_string_lossy().into_owned();
+    let mut s = conn.prepare(queries::ATTACH_CRAFT)?;
+    s.bind(1, source.as_str())?;
+    while let State::Row = s.next()? {}
+    drop(s);
+
+    conn.execute(queries::BEGIN)?;
+
+    let result = import(&conn, offset, policy);
+    match result {
+        Ok(_) => conn.execute(queries::COMMIT)?,
+        Err(_) => {
+            let _ = conn.execute(queries::ROLLBACK);
+        },
+    }
+
+    conn.execute(queries::DETACH_CRAFT)?;
+
+    result.map_err(WorldError::from)
+}
+
+fn import(conn: &Connection, offset: (i32, i32), policy: ImportPolicy) -> sqlite::Result<ImportCounts> {
+    conn.execute(queries::INITIAL_IMPORT_SKIP)?;
+    if policy == ImportPolicy::SkipConflicts {
+        conn.execute(queries::SKIP_OCCUPIED_CHUNKS)?;
+    }
+
+    let insert = match policy {
+        ImportPolicy::Overwrite => "INSERT OR REPLACE",
+        ImportPolicy::KeepExisting | ImportPolicy::SkipConflicts => "INSERT OR IGNORE",
+    };
+
+    Ok(ImportCounts {
+        blocks: (count(conn, queries::COUNT_CRAFT_BLOCKS)?,
+                 copy(conn, insert, queries::IMPORT_BLOCKS, offset)?),
+        signs: (count(conn, queries::COUNT_CRAFT_SIGNS)?,
+                copy(conn, insert, queries::IMPORT_SIGNS, offset)?),
+        lights: (count(conn, queries::COUNT_CRAFT_LIGHTS)?,
+                 copy(conn, insert, queries::IMPORT_LIGHTS, offset)?),
+    })
+}
+
+/// Runs one of the import queries and returns the number of rows it wrote.
+fn copy(conn: &Connection, insert: &str, query: &str, offset: (i32, i32)) -> sqlite::Result<u64> {
+    let mut cursor = conn.prepare(format!("{}{}", insert, query))?.cursor();
+    cursor.bind(&[Value::Integer(offset.0 as i64), Value::Integer(offset.1 as i64)])?;
+    while cursor.next()?.is_some() {}
+
+    count(conn, queries::CHANGES)
+}
+
+fn count(conn: &Connection, query: &str) -> sqlite::Result<u64> {
+    let mut cursor = conn.prepare(query)?.cursor();
+
+    match cursor.next()? {
+        Some(record) => Ok(record[0].as_integer().unwrap_or(0) as u64),
+        None => Ok(0),
+    }
+}
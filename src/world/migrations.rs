@@ -0,0 +1,119 @@
+//! Brings the schema of the world database up to date.
+//!
+//! The schema version is kept in the `metadata` table. Every migration raises it by one,
+//! and the migrations a database lacks run in order, in one transaction, when it is opened.
+//! Databases without a version, including those made by Craft's own server, are at version 0.
+
+use sqlite::{self, Connection, Value};
+use super::{queries, WorldError};
+
+/// The key of the schema version in the `metadata` table.
+const VERSION_KEY: &str = "schema_version";
+
+/// A step that raises the schema version by one.
+struct Migration {
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// The migrations, oldest first. Migration N brings a database to version N + 1.
+/// Migrations must never be changed or reordered once released; add a new one instead.
+static MIGRATIONS: [Migration; 5] = [
+    Migration {
+        description: "create the block, sign and light tables",
+        sql: queries::INITIAL,
+    },
+    Migration {
+        description: "remove the copies of blocks stored in neighboring chunks",
+        sql: queries::COLLAPSE_BORDER_COPIES,
+    },
+    Migration {
+        description: "create the region tables",
+        sql: queries::INITIAL_REGIONS,
+    },
+    Migration {
+        description: "create the history table",
+        sql: queries::INITIAL_HISTORY,
+    },
+    Migration {
+        description: "remove signs without text or without a block",
+        sql: queries::DELETE_STRAY_SIGNS,
+    },
+];
+
+/// Returns the schema version this server uses.
+pub fn schema_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+/// Checks that a database wasn't made by a newer version of the server, without
+/// changing it.
+/// # Return value
+/// Returns the schema version of the database.
+pub fn check_version(conn: &Connection) -> Result<i64, WorldError> {
+    let found = if conn.prepare(queries::HAS_METADATA)?.cursor().next()?.is_some() {
+        load_version(conn)?
+    } else {
+        0
+    };
+    let supported = schema_version();
+
+    if found > supported {
+        return Err(WorldError::NewerSchema { found, supported });
+    }
+
+    Ok(found)
+}
+
+/// Runs the migrations the database lacks.
+/// # Return value
+/// Returns an error if a migration fails, in which case the database is left as
+/// it was, or if the database was made by a newer version of the server.
+pub fn migrate(conn: &Connection) -> Result<(), WorldError> {
+    conn.execute(queries::INITIAL_METADATA)?;
+
+    let found = load_version(conn)?;
+    let supported = schema_version();
+
+    if found > supported {
+        return Err(WorldError::NewerSchema { found, supported });
+    }
+
+    if found == supported {
+        return Ok(());
+    }
+
+    conn.execute(queries::BEGIN)?;
+
+    match run(conn, found) {
+        Ok(()) => conn.execute(queries::COMMIT).map_err(WorldError::from),
+        Err(e) => {
+            let _ = conn.execute(queries::ROLLBACK);
+            Err(e.into())
+        },
+    }
+}
+
+fn run(conn: &Connection, from: i64) -> sqlite::Result<()> {
+    for (i, m) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        info!("Migrating to schema version {} ({}).", i + 1, m.description);
+        conn.execute(m.sql)?;
+    }
+
+    let mut s = conn.prepare(queries::SET_METADATA)?;
+    s.bind(1, VERSION_KEY)?;
+    s.bind(2, schema_version().to_string().as_str())?;
+    while let sqlite::State::Row = s.next()? {}
+
+    Ok(())
+}
+
+fn load_version(conn: &Connection) -> sqlite::Result<i64> {
+    let mut cursor = conn.prepare(queries::LOAD_METADATA)?.cursor();
+    cursor.bind(&[Value::String(VERSION_KEY.to_string())])?;
+
+    match cursor.next()? {
+        Some(record) => Ok(record[0].as_string().and_then(|v| v.parse().ok()).unwrap_or(0)),
+        None => Ok(0),
+    }
+}
@@ -1,19 +1,74 @@
 //! This module contains the necessary functionality for representing
 //! the world, both on disk and in memory.
 
+mod backup;
+mod error;
+mod history;
+mod import;
+mod migrations;
+mod monitor;
 mod queries;
+mod region;
+mod registry;
+pub mod storage;
+
+pub use self::backup::{BackupCallback, Backups, list as list_backups, restore, timestamp};
+pub use self::error::WorldError;
+pub use self::history::{Author, Change, HistoryQuery, unix_time, Value};
+pub use self::import::{import_craft_db, ImportCounts, ImportPolicy};
+pub use self::monitor::WorldMonitor;
+pub use self::region::{Flag, Region, RegionManager};
+pub use self::registry::{block_type, block_type_by_name, block_types, BlockType};
 
 const FILE: &str = "world.db";
 
-use std::collections::hash_map;
-use std::collections::HashMap;
-use std::sync::mpsc;
+/// The most chunks kept in memory at once. Chunks with changes that the database
+/// has not written yet are kept beyond this limit until it has.
+const MAX_LOADED_CHUNKS: usize = 1024;
+
+/// How long, in milliseconds, a connection waits for another to release the database.
+const BUSY_TIMEOUT: usize = 5000;
+
+/// The most commands that may wait for the database thread. Changes are refused
+/// beyond this, so that a database that can't be written doesn't use up memory.
+const MAX_QUEUED: usize = 100_000;
+
+/// The number of times in a row that writing the queue may fail before the
+/// commands in it are written one by one, to find the ones that fail.
+const MAX_FAILED_FLUSHES: u32 = 3;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use std::vec;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
 use std::thread;
 use sqlite::{self, Connection, Statement};
+use config::DatabaseConfig;
+use metrics::Histogram;
+use self::monitor::RunningGuard;
+use self::storage::Column;
 
 /// The square X and Z dimensions of a world sector.
 pub const CHUNK_SIZE: u8 = 32;
 
+/// The height of the world. Players can edit from Y = 1 up to, but excluding, this height.
+pub const WORLD_HEIGHT: i32 = 256;
+
+/// How far the world reaches from the origin on the X and Z axes. Craft keeps
+/// positions as f32, which can't tell neighboring blocks apart any further out.
+pub const WORLD_RADIUS: i32 = 1 << 24;
+
+/// The highest face number a sign can be placed on.
+pub const MAX_SIGN_FACE: u8 = 7;
+
+/// The longest text, in bytes, that a sign can hold.
+pub const MAX_SIGN_LENGTH: usize = 64;
+
+/// The brightest light level.
+pub const MAX_LIGHT: u8 = 15;
+
 /// Type of block IDs.
 #[derive(Clone, Debug)]
 pub struct Block(pub i8);
@@ -23,8 +78,31 @@ impl Block {
     pub fn is_air(&self) -> bool {
         self.0 == 0
     }
+
+    /// Returns the type of this block from the registry, if it is a known block.
+    pub fn block_type(&self) -> Option<&'static BlockType> {
+        block_type(self.0)
+    }
+
+    /// Returns true if players may set this block, either by placing it or by
+    /// mining to air.
+    pub fn is_placeable(&self) -> bool {
+        self.is_air() || self.block_type().is_some_and(|t| t.placeable)
+    }
 }
 
+/// Iterates over the blocks of a chunk by their local positions.
+pub type ChunkBlocks = vec::IntoIter<((u8, u8, u8), Block)>;
+
+/// Iterates over the signs of a chunk by their global positions and faces.
+pub type ChunkSigns = vec::IntoIter<((i32, i32, i32, u8), Sign)>;
+
+/// Iterates over the lights of a chunk by their local positions.
+pub type ChunkLights = vec::IntoIter<((u8, u8, u8), Light)>;
+
+/// Iterates over values in a cuboid by their global positions.
+pub type CuboidIter<T> = vec::IntoIter<((i32, i32, i32), T)>;
+
 /// Type of Craft signs.
 #[derive(Clone, Debug)]
 pub struct Sign(pub String);
@@ -37,264 +115,810 @@ pub struct Light(pub u8);
 pub */
 #[derive(Debug)]
 struct Chunk {
-    blocks: HashMap<(u8, u8, u8), Block>,
-    signs: HashMap<(i32, i32, i32, u8), Sign>,
-    lights: HashMap<(u8, u8, u8), Light>,
+    // Positions that were never set hold None, and show whatever Craft generates there.
+    // Mined blocks hold Some(0), so that they stay mined.
+    blocks: Column<Option<i8>>,
+    lights: Column<u8>,
+    // Keyed by local X, Y, local Z and face.
+    signs: BTreeMap<(u8, i32, u8, u8), Sign>,
+
+    // When the chunk was last used, for evicting the least recently used chunk.
+    last_used: u64,
+
+    // The number of the last change made to the chunk that was sent to the database.
+    last_write: u64,
 }
 
 impl Chunk {
     fn new() -> Chunk {
         Chunk {
-            blocks: HashMap::new(),
-            signs: HashMap::new(),
-            lights: HashMap::new(),
+            blocks: Column::new(),
+            lights: Column::new(),
+            signs: BTreeMap::new(),
+            last_used: 0,
+            last_write: 0,
         }
     }
+}
+
+/// Keeps the most recently used chunks in memory, and loads the others
+/// from the database when they are needed.
+struct ChunkManager {
+    conn: Connection,
+    chunks: HashMap<(i32, i32), Chunk>,
+    clock: u64,
+    written: Arc<AtomicU64>,
+    loaded: Arc<AtomicUsize>,
+}
 
-    fn set_block(&mut self, local_pos: (u8, u8, u8), block: Block) {
-        self.blocks.insert(local_pos, block);
+impl ChunkManager {
+    /// Creates a chunk manager that reads chunks with the given connection.
+    /// `written` counts the changes the database thread has written, and
+    /// `loaded` is kept at the number of chunks in memory.
+    fn new(conn: Connection, written: Arc<AtomicU64>, loaded: Arc<AtomicUsize>) -> ChunkManager {
+        ChunkManager {
+            conn,
+            chunks: HashMap::new(),
+            clock: 0,
+            written,
+            loaded,
+        }
     }
 
-    fn set_sign(&mut self, global_pos: (i32, i32, i32), face: u8, sign: Sign) {
-        let key = (global_pos.0, global_pos.1, global_pos.2, face);
+    /// Sets a block. `change` numbers the change sent to the database for it.
+    /// Setting a block to air removes the signs on it, which are returned.
+    fn set_block(&mut self,
+                 global_pos: (i32, i32, i32),
+                 block: Block,
+                 change: u64) -> Result<Vec<(u8, Sign)>, WorldError> {
+        // P and Q are chunk/sector x and z.
+        let (pq, (x, y, z)) = Self::locate(global_pos);
+        let chunk = self.load(pq)?;
+
+        chunk.blocks.set(x, y, z, Some(block.0));
+        chunk.last_write = change;
 
-        self.signs.insert(key, sign);
+        let mut removed = Vec::new();
+        if block.is_air() {
+            let faces: Vec<u8> = chunk.signs.range((x, y, z, 0)..=(x, y, z, u8::MAX))
+                                            .map(|(&(_, _, _, face), _)| face)
+                                            .collect();
+
+            for face in faces {
+                let sign = chunk.signs.remove(&(x, y, z, face)).unwrap();
+                removed.push((face, sign));
+            }
+        }
+
+        Ok(removed)
     }
 
-    fn set_light(&mut self, local_pos: (u8, u8, u8), light: Light) {
-        self.lights.insert(local_pos, light);
+    /// Sets a sign. A sign without text is removed.
+    fn set_sign(&mut self,
+                global_pos: (i32, i32, i32),
+                face: u8,
+                sign: Sign,
+                change: u64) -> Result<(), WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
+        let chunk = self.load(pq)?;
+
+        if sign.0.is_empty() {
+            chunk.signs.remove(&(x, y, z, face));
+        } else {
+            chunk.signs.insert((x, y, z, face), sign);
+        }
+        chunk.last_write = change;
+
+        Ok(())
     }
 
-    fn signs(&self) -> hash_map::Iter<(i32, i32, i32, u8), Sign> {
-        self.signs.iter()
+    fn set_light(&mut self,
+                 global_pos: (i32, i32, i32),
+                 light: Light,
+                 change: u64) -> Result<(), WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
+        let chunk = self.load(pq)?;
+
+        chunk.lights.set(x, y, z, light.0);
+        chunk.last_write = change;
+
+        Ok(())
     }
 
-    fn lights(&self) -> hash_map::Iter<(u8, u8, u8), Light> {
-        self.lights.iter()
+    /// Returns the block at a position, or None if it was never set.
+    fn block(&mut self, global_pos: (i32, i32, i32)) -> Result<Option<Block>, WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
+
+        Ok(self.load(pq)?.blocks.get(x, y, z).map(Block))
     }
-}
 
-impl<'a> IntoIterator for &'a Chunk {
-    type Item = (&'a (u8, u8, u8), &'a Block);
-    type IntoIter = hash_map::Iter<'a, (u8, u8, u8), Block>;
+    fn sign(&mut self, global_pos: (i32, i32, i32), face: u8) -> Result<Option<Sign>, WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.blocks.iter()
+        Ok(self.load(pq)?.signs.get(&(x, y, z, face)).cloned())
     }
-}
 
-struct ChunkManager {
-    chunks: HashMap<(i32, i32), Chunk>,
-}
+    fn signs_at(&mut self, global_pos: (i32, i32, i32)) -> Result<Vec<(u8, Sign)>, WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
 
-impl ChunkManager {
-    fn new() -> ChunkManager {
-        ChunkManager {
-            chunks: HashMap::new(),
+        Ok(self.load(pq)?.signs.range((x, y, z, 0)..=(x, y, z, u8::MAX))
+                             .map(|(&(_, _, _, face), sign)| (face, sign.clone()))
+                             .collect())
+    }
+
+    fn light(&mut self, global_pos: (i32, i32, i32)) -> Result<Light, WorldError> {
+        let (pq, (x, y, z)) = Self::locate(global_pos);
+
+        Ok(Light(self.load(pq)?.lights.get(x, y, z)))
+    }
+
+    /// Collects the blocks a client needs for a chunk: the chunk's own blocks, and
+    /// negated copies of the neighboring blocks that lie on its borders.
+    fn blocks_with_borders(&mut self, pq: (i32, i32)) -> Result<ChunkBlocks, WorldError> {
+        const LAST: u8 = CHUNK_SIZE - 1;
+
+        for dp in -1..2 {
+            for dq in -1..2 {
+                self.load((pq.0 + dp, pq.1 + dq))?;
+            }
+        }
+
+        let mut blocks = Vec::new();
+
+        if let Some(c) = self.chunks.get(&pq) {
+            c.blocks.for_each(|(x, y, z), w| {
+                if let (Some(y), Some(w)) = (served_height(y), w) {
+                    blocks.push(((x + 1, y, z + 1), Block(w)));
+                }
+            });
         }
+
+        for dp in -1..2 {
+            for dq in -1..2 {
+                if dp == 0 && dq == 0 {
+                    continue;
+                }
+
+                let neighbor = match self.chunks.get(&(pq.0 + dp, pq.1 + dq)) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                // Only the rows of the neighbor that touch this chunk are copied.
+                let edge = |d| match d {
+                    -1 => LAST..=LAST,
+                    0 => 0..=LAST,
+                    _ => 0..=0,
+                };
+
+                neighbor.blocks.for_each_in(edge(dp), edge(dq), |(x, y, z), w| {
+                    // Local coordinates are offset by one, so that the copies of the
+                    // neighbors' border blocks sit at 0 and CHUNK_SIZE + 1.
+                    let x = x as i32 + 1 + dp * CHUNK_SIZE as i32;
+                    let z = z as i32 + 1 + dq * CHUNK_SIZE as i32;
+
+                    if let (Some(y), Some(w)) = (served_height(y), w) {
+                        blocks.push(((x as u8, y, z as u8), Block(-w)));
+                    }
+                });
+            }
+        }
+
+        Ok(blocks.into_iter())
     }
 
-    fn set_block(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), block: Block) {
-        // P and Q are chunk/sector x and z.
-        //let (p, q) = (chunked(global_pos.0), chunked(global_pos.2));
+    fn signs(&mut self, pq: (i32, i32)) -> Result<ChunkSigns, WorldError> {
+        let (x0, z0) = (pq.0 * CHUNK_SIZE as i32, pq.1 * CHUNK_SIZE as i32);
 
-        //println!("(p, q): {}, {}", p, q);
-        let local_pos = ((global_pos.0 - pq.0 * CHUNK_SIZE as i32 + 1) as u8,
-                          global_pos.1 as u8,
-                         (global_pos.2 - pq.1 * CHUNK_SIZE as i32 + 1) as u8);
-        //println!("local_pos: {:?}", local_pos);
-        //println!("test: {}", (global_pos.0 - pq.0 * CHUNK_SIZE as i32));
+        let signs: Vec<_> = self.load(pq)?.signs.iter()
+                                     .map(|(&(x, y, z, face), sign)| {
+                                         ((x0 + x as i32, y, z0 + z as i32, face), sign.clone())
+                                     })
+                                     .collect();
 
-        let entry = self.chunks.entry(pq);
-        let chunk = entry.or_insert(Chunk::new());
-        chunk.set_block(local_pos, block);
+        Ok(signs.into_iter())
+    }
 
-        //println!("entire world: {:?}", self.chunks);
-        /*
-        if let Some(c) = self.chunks.get_mut(&(p, q)) {
-            println!("case 1");
+    /// Returns the height of the highest block at these X and Z coordinates
+    /// that is not air, and the block.
+    fn highest_block(&mut self, x: i32, z: i32) -> Result<Option<(i32, Block)>, WorldError> {
+        let (pq, (x, _, z)) = Self::locate((x, 0, z));
+        let blocks = &self.load(pq)?.blocks;
 
-            c.set_block(local_pos, block);
-        } else {
-            println!("case 2");
+        Ok(blocks.highest(x, z, |w| w != Some(0))
+                 .map(|y| (y, Block(blocks.get(x, y, z).unwrap()))))
+    }
+
+    /// Calls `f` with every chunk that overlaps a cuboid, along with the chunk's
+    /// global X and Z origin and the ranges of its local X and Z coordinates inside the cuboid.
+    fn for_each_chunk_in<F>(&mut self,
+                            min: (i32, i32, i32),
+                            max: (i32, i32, i32),
+                            mut f: F) -> Result<(), WorldError>
+            where F: FnMut(&Chunk, (i32, i32), RangeInclusive<u8>, RangeInclusive<u8>) {
+        let size = CHUNK_SIZE as i32;
+        let local = |lo: i32, hi: i32, origin: i32| {
+            (lo - origin).max(0) as u8..=(hi - origin).min(size - 1) as u8
+        };
+
+        for p in chunked(min.0)..=chunked(max.0) {
+            for q in chunked(min.2)..=chunked(max.2) {
+                let origin = (p * size, q * size);
+                let chunk = self.load((p, q))?;
+
+                f(chunk, origin, local(min.0, max.0, origin.0), local(min.2, max.2, origin.1));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn lights(&mut self, pq: (i32, i32)) -> Result<ChunkLights, WorldError> {
+        let mut lights = Vec::new();
+
+        self.load(pq)?.lights.for_each(|(x, y, z), w| {
+            if let Some(y) = served_height(y) {
+                lights.push(((x + 1, y, z + 1), Light(w)));
+            }
+        });
+
+        Ok(lights.into_iter())
+    }
+
+    /// Returns a chunk, reading it from the database if it is not in memory.
+    fn load(&mut self, pq: (i32, i32)) -> Result<&mut Chunk, WorldError> {
+        if !self.chunks.contains_key(&pq) {
+            self.evict();
 
-            let mut c = Chunk::new((p, q));
-            c.set_block(local_pos, block);
-            self.chunks.insert((p, q), c);
+            let chunk = self.read(pq)?;
+            self.chunks.insert(pq, chunk);
+            self.loaded.store(self.chunks.len(), Ordering::SeqCst);
         }
-        */
 
-        /*
-        print!("chunks: ");
-        for i in self.chunks.keys() {
-            print!("{:?}, ", i);
+        self.clock += 1;
+
+        let chunk = self.chunks.get_mut(&pq).unwrap();
+        chunk.last_used = self.clock;
+        Ok(chunk)
+    }
+
+    /// Makes room for another chunk by dropping the least recently used chunks.
+    /// Chunks with changes that the database has not written yet are never dropped,
+    /// because reading them back would lose those changes.
+    fn evict(&mut self) {
+        let written = self.written.load(Ordering::SeqCst);
+
+        while self.chunks.len() >= MAX_LOADED_CHUNKS {
+            let oldest = self.chunks.iter()
+                                    .filter(|&(_, c)| c.last_write <= written)
+                                    .min_by_key(|&(_, c)| c.last_used)
+                                    .map(|(pq, _)| *pq);
+
+            match oldest {
+                Some(pq) => { self.chunks.remove(&pq); },
+                None => break,
+            }
         }
-        println!();
-        */
     }
 
-    fn set_sign(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), face: u8, sign: Sign) {
-        let entry = self.chunks.entry(pq);
-        let chunk = entry.or_insert(Chunk::new());
-        chunk.set_sign(global_pos, face, sign);
+    fn read(&self, pq: (i32, i32)) -> sqlite::Result<Chunk> {
+        use sqlite::Value as SqlValue;
+
+        let mut chunk = Chunk::new();
+        let params = [SqlValue::Integer(pq.0 as i64), SqlValue::Integer(pq.1 as i64)];
+
+        let mut cursor = self.conn.prepare(queries::LOAD_BLOCKS)?.cursor();
+        cursor.bind(&params)?;
 
-        //println!("all blocks and signs: {:?}", self.chunks);
+        while let Some(record) = cursor.next()? {
+            let (xyz, w) = ((record[0].as_integer().unwrap() as i32,
+                             record[1].as_integer().unwrap() as i32,
+                             record[2].as_integer().unwrap() as i32),
+                             record[3].as_integer().unwrap() as i8);
+
+            let (_, (x, y, z)) = Self::locate(xyz);
+            chunk.blocks.set(x, y, z, Some(w));
+        }
+
+        let mut cursor = self.conn.prepare(queries::LOAD_SIGNS)?.cursor();
+        cursor.bind(&params)?;
+
+        while let Some(record) = cursor.next()? {
+            let (xyz, face, text) = ((record[0].as_integer().unwrap() as i32,
+                                      record[1].as_integer().unwrap() as i32,
+                                      record[2].as_integer().unwrap() as i32),
+                                      record[3].as_integer().unwrap() as u8,
+                                      record[4].as_string().unwrap().to_string());
+
+            let (_, (x, y, z)) = Self::locate(xyz);
+            chunk.signs.insert((x, y, z, face), Sign(text));
+        }
+
+        let mut cursor = self.conn.prepare(queries::LOAD_LIGHTS)?.cursor();
+        cursor.bind(&params)?;
+
+        while let Some(record) = cursor.next()? {
+            let (xyz, w) = ((record[0].as_integer().unwrap() as i32,
+                             record[1].as_integer().unwrap() as i32,
+                             record[2].as_integer().unwrap() as i32),
+                             record[3].as_integer().unwrap() as u8);
+
+            let (_, (x, y, z)) = Self::locate(xyz);
+            chunk.lights.set(x, y, z, w);
+        }
+
+        Ok(chunk)
     }
 
-    fn set_light(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), light: Light) {
-        let local_pos = ((global_pos.0 - pq.0 * CHUNK_SIZE as i32 + 1) as u8,
-                          global_pos.1 as u8,
-                         (global_pos.2 - pq.1 * CHUNK_SIZE as i32 + 1) as u8);
+    /// Returns the chunk a position falls in and its position within that chunk.
+    /// Local X and Z range from 0 to `CHUNK_SIZE - 1`.
+    fn locate(global_pos: (i32, i32, i32)) -> ((i32, i32), (u8, i32, u8)) {
+        let pq = (chunked(global_pos.0), chunked(global_pos.2));
+        let local_pos = (global_pos.0.rem_euclid(CHUNK_SIZE as i32) as u8,
+                         global_pos.1,
+                         global_pos.2.rem_euclid(CHUNK_SIZE as i32) as u8);
 
-        let entry = self.chunks.entry(pq);
-        let chunk = entry.or_insert(Chunk::new());
-        chunk.set_light(local_pos, light);
+        (pq, local_pos)
     }
+}
+
+/// Refuses changes past `WORLD_RADIUS`, which the chunks can't hold.
+fn check_position(xyz: (i32, i32, i32)) -> Result<(), WorldError> {
+    if within_world(xyz.0, xyz.2) { Ok(()) } else { Err(WorldError::OutOfBounds) }
+}
 
-    fn get(&self, pq: (i32, i32)) -> Option<&Chunk> {
-        self.chunks.get(&pq)
+/// Returns the height as Craft's chunk messages carry it, if Craft can show it.
+fn served_height(y: i32) -> Option<u8> {
+    if (0..WORLD_HEIGHT).contains(&y) {
+        Some(y as u8)
+    } else {
+        None
     }
 }
 
 /// Manages a world and the SQLite connection to persist it on disk.
 pub struct World {
-    chunk_mgr: ChunkManager,
+    chunk_mgr: RefCell<ChunkManager>,
+    regions: RegionManager,
     tx: mpsc::Sender<DatabaseCommand>,
+    changes: u64,
+    monitor: WorldMonitor,
+    database_thread: Option<thread::JoinHandle<()>>,
+
+    // Commands held back until the batch they belong to ends.
+    batch: RefCell<Option<Vec<DatabaseCommand>>>,
 }
 
 impl World {
     /// Create a new world manager. The SQLite database will be created
     /// or opened.
-    /// # Panics
-    /// This function panics if the SQLite connection fails or the necessary
-    /// initial queries can not be performed successfully.
-    pub fn new() -> World {
-        print!("Loading world... ");
+    ///
+    /// Chunks are not loaded here, but when they are first used.
+    /// # Return value
+    /// Returns an error if the database can't be opened or set up.
+    pub fn new(config: &DatabaseConfig) -> Result<World, WorldError> {
+        info!("Loading the world...");
+
+        let mut conn = sqlite::open(FILE)?;
+        conn.set_busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute(queries::JOURNAL_MODE)?;
+        conn.execute(format!("PRAGMA synchronous = {};", config.synchronous))?;
+
+        let mut read_conn = sqlite::open(FILE)?;
+        read_conn.set_busy_timeout(BUSY_TIMEOUT)?;
 
-        let conn = sqlite::open(FILE).unwrap();
         let channel = mpsc::channel();
+        let written = Arc::new(AtomicU64::new(0));
+        let monitor = WorldMonitor::default();
 
         let mut w = World {
-            chunk_mgr: ChunkManager::new(),
+            chunk_mgr: RefCell::new(ChunkManager::new(read_conn, written.clone(), monitor.loaded_chunks.clone())),
+            regions: RegionManager::new(),
             tx: channel.0,
+            changes: 0,
+            monitor: monitor.clone(),
+            database_thread: None,
+            batch: RefCell::new(None),
         };
 
-        w.initial_queries(&conn);
-        w.load_blocks(&conn);
-        w.load_signs(&conn);
-        w.load_lights(&conn);
+        migrations::migrate(&conn)?;
+        w.load_regions(&conn)?;
 
-        println!("OK");
+        w.database_thread = Some(DatabaseThread::run(conn, channel.1, written, monitor, config.clone())?);
 
-        DatabaseThread::run(conn, channel.1);
+        info!("Loaded the world.");
 
-        w
+        Ok(w)
     }
 
-    /// Set a block in the world with the given global coordinates. The chunk is set
-    /// manually to avoid troubles with chunk borders.
-    pub fn set_block(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), block: Block) {
-        self.chunk_mgr.set_block(global_pos, pq, block.clone());
-        self.tx.send(DatabaseCommand::SetBlock(SetBlockCommand {
+    /// Set a block in the world with the given global coordinates.
+    ///
+    /// Each block is stored once, in the chunk it falls in. The copies that Craft
+    /// keeps in neighboring chunks are derived when chunks are served.
+    ///
+    /// Setting a block to air also removes the signs on it. Blocks can't be set
+    /// past `WORLD_RADIUS`, and trying returns `WorldError::OutOfBounds`.
+    ///
+    /// If an author is given, the change is recorded in the world's history.
+    pub fn set_block(&mut self,
+                     global_pos: (i32, i32, i32),
+                     block: Block,
+                     author: Option<&Author>) -> Result<(), WorldError> {
+        check_position(global_pos)?;
+        self.check_queue()?;
+
+        let old = self.get_block(global_pos)?;
+
+        let removed = self.chunk_mgr.get_mut().set_block(global_pos, block.clone(), self.changes + 1)?;
+        self.send_change(DatabaseCommand::SetBlock(SetBlockCommand {
             xyz: global_pos,
-            pq,
-            block,
-        })).unwrap();
+            pq: (chunked(global_pos.0), chunked(global_pos.2)),
+            block: block.clone(),
+        }))?;
+
+        if let Some(author) = author {
+            // The removed signs are recorded before the block, so that undoing
+            // the changes in order puts the block back before its signs.
+            for (face, sign) in removed {
+                self.record(author, global_pos, Value::Sign(face, sign), Value::Sign(face, Sign(String::new())))?;
+            }
+
+            self.record(author, global_pos, Value::Block(old), Value::Block(block))?;
+        }
+
+        Ok(())
     }
 
-    /// Set a sign in the world using absolute world coordinates and chunk coordinates.
-    pub fn set_sign(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), face: u8, sign: Sign) {
-        self.chunk_mgr.set_sign(global_pos, pq, face, sign.clone());
-        self.tx.send(DatabaseCommand::SetSign(SetSignCommand {
+    /// Set a sign in the world using absolute world coordinates.
+    ///
+    /// A sign without text is removed. Signs can't be placed on blocks that were mined
+    /// to air, and trying returns `WorldError::SignOnAir`.
+    ///
+    /// If an author is given, the change is recorded in the world's history.
+    pub fn set_sign(&mut self,
+                    global_pos: (i32, i32, i32),
+                    face: u8,
+                    sign: Sign,
+                    author: Option<&Author>) -> Result<(), WorldError> {
+        check_position(global_pos)?;
+        self.check_queue()?;
+
+        let on_air = self.chunk_mgr.get_mut().block(global_pos)?.is_some_and(|b| b.is_air());
+        if on_air && !sign.0.is_empty() {
+            return Err(WorldError::SignOnAir);
+        }
+
+        let old = self.get_sign(global_pos, face)?;
+
+        self.chunk_mgr.get_mut().set_sign(global_pos, face, sign.clone(), self.changes + 1)?;
+        self.send_change(DatabaseCommand::SetSign(SetSignCommand {
             xyz: global_pos,
             face,
-            sign,
-        })).unwrap();
+            sign: sign.clone(),
+        }))?;
+
+        match author {
+            Some(author) => self.record(author, global_pos, Value::Sign(face, old), Value::Sign(face, sign)),
+            None => Ok(()),
+        }
     }
 
-    /// Set a light in the world using absolute world coordinates and chunk coordinates.
-    pub fn set_light(&mut self, global_pos: (i32, i32, i32), pq: (i32, i32), light: Light) {
-        self.chunk_mgr.set_light(global_pos, pq, light.clone());
-        self.tx.send(DatabaseCommand::SetLight(SetLightCommand {
+    /// Set a light in the world using absolute world coordinates.
+    ///
+    /// If an author is given, the change is recorded in the world's history.
+    pub fn set_light(&mut self,
+                     global_pos: (i32, i32, i32),
+                     light: Light,
+                     author: Option<&Author>) -> Result<(), WorldError> {
+        check_position(global_pos)?;
+        self.check_queue()?;
+
+        let old = self.light_at(global_pos)?;
+
+        self.chunk_mgr.get_mut().set_light(global_pos, light.clone(), self.changes + 1)?;
+        self.send_change(DatabaseCommand::SetLight(SetLightCommand {
             xyz: global_pos,
-            pq,
-            light,
-        })).unwrap();
+            pq: (chunked(global_pos.0), chunked(global_pos.2)),
+            light: light.clone(),
+        }))?;
+
+        match author {
+            Some(author) => self.record(author, global_pos, Value::Light(old), Value::Light(light)),
+            None => Ok(()),
+        }
     }
 
-    /// Iterate over the blocks in the chunk with these (P, Q) (as in (X, Z)) coordinates.
-    pub fn blocks_in_chunk(&self, chunk: (i32, i32)) -> Option<hash_map::Iter<(u8, u8, u8), Block>> {
-        match self.chunk_mgr.get(chunk) {
-            Some(c) => Some(c.into_iter()),
-            None => None,
+    /// Look up recorded changes, newest first.
+    ///
+    /// This waits for the database to apply all changes made so far.
+    pub fn history(&self, query: &HistoryQuery) -> Result<Vec<Change>, WorldError> {
+        let (tx, rx) = mpsc::channel();
+
+        // Queries are never held back in a batch, since the answer is needed now.
+        self.tx.send(DatabaseCommand::LoadHistory(query.clone(), tx)).map_err(|_| WorldError::Stopped)?;
+
+        rx.recv().map_err(|_| WorldError::Stopped)?
+    }
+
+    /// Starts a batch of changes. The changes made until `end_batch` is called
+    /// are written to the database in one transaction.
+    pub fn begin_batch(&mut self) {
+        let batch = self.batch.get_mut();
+
+        if batch.is_none() {
+            *batch = Some(Vec::new());
         }
     }
 
-    /// Iterate over the signs in the chunk with these coordinates.
-    pub fn signs_in_chunk(&self, chunk: (i32, i32)) -> Option<hash_map::Iter<(i32, i32, i32, u8), Sign>> {
-        match self.chunk_mgr.get(chunk) {
-            Some(c) => Some(c.signs()),
-            None => None,
+    /// Ends a batch of changes, and sends them to the database.
+    pub fn end_batch(&mut self) -> Result<(), WorldError> {
+        match self.batch.get_mut().take() {
+            Some(ref cmds) if cmds.is_empty() => Ok(()),
+            Some(cmds) => self.queue(DatabaseCommand::Batch(cmds)),
+            None => Ok(()),
         }
     }
 
-    /// Iterate over the lights in the chunk with these coordinates.
-    pub fn lights_in_chunk(&self, chunk: (i32, i32)) -> Option<hash_map::Iter<(u8, u8, u8), Light>> {
-        match self.chunk_mgr.get(chunk) {
-            Some(c) => Some(c.lights()),
-            None => None,
+    /// Writes every change made so far to the database now, instead of when the
+    /// flush interval is over.
+    /// # Return value
+    /// Returns an error if some changes could not be saved.
+    pub fn save(&self) -> Result<(), WorldError> {
+        let (tx, rx) = mpsc::channel();
+
+        self.tx.send(DatabaseCommand::Flush(tx)).map_err(|_| WorldError::Stopped)?;
+
+        rx.recv().map_err(|_| WorldError::Stopped)?
+    }
+
+    /// Closes the world, waiting until every change made so far is written to the database.
+    /// # Return value
+    /// Returns an error if some changes could not be saved.
+    pub fn close(mut self) -> Result<(), WorldError> {
+        self.end_batch()?;
+
+        let World { tx, monitor, database_thread, .. } = self;
+
+        // The database thread writes what is still queued once the channel closes.
+        drop(tx);
+
+        if let Some(handle) = database_thread {
+            handle.join().map_err(|_| WorldError::Stopped)?;
+        }
+
+        if monitor.is_degraded() {
+            Err(WorldError::Unsaved)
+        } else {
+            Ok(())
         }
     }
 
-    fn initial_queries(&self, conn: &Connection) {
-        conn.execute(queries::INITIAL).unwrap();
+    /// Mark recorded changes as undone, so that they are not undone or rolled back again.
+    pub fn set_reverted(&mut self, ids: Vec<i64>) -> Result<(), WorldError> {
+        self.check_queue()?;
+        self.send(DatabaseCommand::SetReverted(ids))
+    }
+
+    /// Returns true while changes to the world can't be saved. The changes are
+    /// kept in memory and saving them is retried.
+    pub fn is_degraded(&self) -> bool {
+        self.monitor.is_degraded()
+    }
+
+    /// Returns a view of the world's state for other threads.
+    pub fn monitor(&self) -> WorldMonitor {
+        self.monitor.clone()
+    }
+
+    /// Returns the protected regions of the world.
+    pub fn regions(&self) -> &RegionManager {
+        &self.regions
+    }
+
+    /// Adds a region, or replaces the region with the same name.
+    pub fn set_region(&mut self, region: Region) -> Result<(), WorldError> {
+        self.check_queue()?;
+        self.regions.insert(region.clone());
+        self.send(DatabaseCommand::SetRegion(region))
     }
 
-    fn load_blocks(&mut self, conn: &Connection) {
-        let mut cursor = conn.prepare(queries::LOAD_BLOCKS).unwrap().cursor();
+    /// Removes the region with the given name.
+    /// # Return value
+    /// Returns the removed region, if it existed.
+    pub fn remove_region(&mut self, name: &str) -> Result<Option<Region>, WorldError> {
+        self.check_queue()?;
 
-        while let Some(record) = cursor.next().unwrap() {
-            let (pq, xyz, w) = ((record[0].as_integer().unwrap() as i32,
-                                 record[1].as_integer().unwrap() as i32),
-                                (record[2].as_integer().unwrap() as i32,
-                                 record[3].as_integer().unwrap() as i32,
-                                 record[4].as_integer().unwrap() as i32),
-                                 record[5].as_integer().unwrap() as i8);
+        let region = self.regions.remove(name);
 
-            //println!("values: ({}, {}, {}): {}", x, y, z, w);
-            self.chunk_mgr.set_block(xyz, pq, Block(w));
+        if region.is_some() {
+            self.send(DatabaseCommand::RemoveRegion(name.to_string()))?;
         }
+
+        Ok(region)
+    }
+
+    /// Returns the block stored at the given global coordinates.
+    /// Positions that were never set are air.
+    pub fn get_block(&self, global_pos: (i32, i32, i32)) -> Result<Block, WorldError> {
+        Ok(self.block_at(global_pos)?.unwrap_or(Block(0)))
     }
 
-    fn load_signs(&mut self, conn: &Connection) {
-        let mut cursor = conn.prepare(queries::LOAD_SIGNS).unwrap().cursor();
+    /// Returns the block at the given global coordinates, or None if it was never set.
+    /// The server only knows the blocks that were placed or mined; Craft generates
+    /// the terrain at every other position itself.
+    pub fn block_at(&self, global_pos: (i32, i32, i32)) -> Result<Option<Block>, WorldError> {
+        self.chunk_mgr.borrow_mut().block(global_pos)
+    }
 
-        while let Some(record) = cursor.next().unwrap() {
-            let (pq, xyz, face, text) = ((record[0].as_integer().unwrap() as i32,
-                                          record[1].as_integer().unwrap() as i32),
-                                         (record[2].as_integer().unwrap() as i32,
-                                          record[3].as_integer().unwrap() as i32,
-                                          record[4].as_integer().unwrap() as i32),
-                                          record[5].as_integer().unwrap() as u8,
-                                          record[6].as_string().unwrap().to_string());
+    /// Returns the height of the highest placed block at these X and Z coordinates,
+    /// and the block. Like `block_at`, this knows nothing of the generated terrain.
+    pub fn highest_block(&self, x: i32, z: i32) -> Result<Option<(i32, Block)>, WorldError> {
+        self.chunk_mgr.borrow_mut().highest_block(x, z)
+    }
+
+    /// Returns the blocks that were placed or mined in the cuboid between two opposite
+    /// corners, by their global positions.
+    pub fn blocks_in(&self, a: (i32, i32, i32), b: (i32, i32, i32)) -> Result<CuboidIter<Block>, WorldError> {
+        let (min, max) = cuboid(a, b);
+        let mut blocks = Vec::new();
+
+        self.chunk_mgr.borrow_mut().for_each_chunk_in(min, max, |chunk, (x0, z0), xs, zs| {
+            chunk.blocks.for_each_in(xs, zs, |(x, y, z), w| {
+                if let (true, Some(w)) = (min.1 <= y && y <= max.1, w) {
+                    blocks.push(((x0 + x as i32, y, z0 + z as i32), Block(w)));
+                }
+            });
+        })?;
+
+        Ok(blocks.into_iter())
+    }
 
-            self.chunk_mgr.set_sign(xyz, pq, face, Sign(text));
+    /// Returns the signs in the cuboid between two opposite corners, by their
+    /// global positions, with their faces.
+    pub fn signs_in(&self, a: (i32, i32, i32), b: (i32, i32, i32)) -> Result<CuboidIter<(u8, Sign)>, WorldError> {
+        let (min, max) = cuboid(a, b);
+        let mut signs = Vec::new();
+
+        self.chunk_mgr.borrow_mut().for_each_chunk_in(min, max, |chunk, (x0, z0), xs, zs| {
+            for (&(x, y, z, face), sign) in &chunk.signs {
+                if xs.contains(&x) && zs.contains(&z) && min.1 <= y && y <= max.1 {
+                    signs.push(((x0 + x as i32, y, z0 + z as i32), (face, sign.clone())));
+                }
+            }
+        })?;
+
+        Ok(signs.into_iter())
+    }
+
+    /// Returns the lights in the cuboid between two opposite corners, by their global positions.
+    pub fn lights_in(&self, a: (i32, i32, i32), b: (i32, i32, i32)) -> Result<CuboidIter<Light>, WorldError> {
+        let (min, max) = cuboid(a, b);
+        let mut lights = Vec::new();
+
+        self.chunk_mgr.borrow_mut().for_each_chunk_in(min, max, |chunk, (x0, z0), xs, zs| {
+            chunk.lights.for_each_in(xs, zs, |(x, y, z), w| {
+                if min.1 <= y && y <= max.1 {
+                    lights.push(((x0 + x as i32, y, z0 + z as i32), Light(w)));
+                }
+            });
+        })?;
+
+        Ok(lights.into_iter())
+    }
+
+    /// Returns the text of the sign on the given face of a block.
+    /// If there is no sign, the text is empty.
+    pub fn get_sign(&self, global_pos: (i32, i32, i32), face: u8) -> Result<Sign, WorldError> {
+        Ok(self.chunk_mgr.borrow_mut().sign(global_pos, face)?.unwrap_or(Sign(String::new())))
+    }
+
+    /// Returns the faces and texts of the signs on a block.
+    pub fn signs_at(&self, global_pos: (i32, i32, i32)) -> Result<Vec<(u8, Sign)>, WorldError> {
+        self.chunk_mgr.borrow_mut().signs_at(global_pos)
+    }
+
+    /// Returns the light stored at the given global coordinates.
+    /// Positions that were never set are dark.
+    pub fn light_at(&self, global_pos: (i32, i32, i32)) -> Result<Light, WorldError> {
+        self.chunk_mgr.borrow_mut().light(global_pos)
+    }
+
+    /// Iterate over the blocks in the chunk with these (P, Q) (as in (X, Z)) coordinates.
+    /// This includes the negated copies of neighboring blocks on the chunk's borders.
+    ///
+    /// Blocks are visited from the bottom up. Only heights that Craft can show,
+    /// from 0 up to `WORLD_HEIGHT`, are included.
+    pub fn blocks_in_chunk(&self, chunk: (i32, i32)) -> Result<Option<ChunkBlocks>, WorldError> {
+        Ok(non_empty(self.chunk_mgr.borrow_mut().blocks_with_borders(chunk)?))
+    }
+
+    /// Iterate over the signs in the chunk with these coordinates.
+    pub fn signs_in_chunk(&self, chunk: (i32, i32)) -> Result<Option<ChunkSigns>, WorldError> {
+        Ok(non_empty(self.chunk_mgr.borrow_mut().signs(chunk)?))
+    }
+
+    /// Iterate over the lights in the chunk with these coordinates.
+    /// Like blocks, only heights from 0 up to `WORLD_HEIGHT` are included.
+    pub fn lights_in_chunk(&self, chunk: (i32, i32)) -> Result<Option<ChunkLights>, WorldError> {
+        Ok(non_empty(self.chunk_mgr.borrow_mut().lights(chunk)?))
+    }
+
+    fn send(&self, cmd: DatabaseCommand) -> Result<(), WorldError> {
+        if let Some(ref mut batch) = *self.batch.borrow_mut() {
+            batch.push(cmd);
+            return Ok(());
         }
+
+        self.queue(cmd)
+    }
+
+    /// Refuses changes while too many commands wait for the database thread.
+    fn check_queue(&self) -> Result<(), WorldError> {
+        if self.monitor.queued_commands() >= MAX_QUEUED {
+            Err(WorldError::QueueFull)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sends a command to the database thread to be queued for the next write.
+    fn queue(&self, cmd: DatabaseCommand) -> Result<(), WorldError> {
+        self.tx.send(cmd).map_err(|_| WorldError::Stopped)?;
+        self.monitor.queued.fetch_add(1, Ordering::SeqCst);
+        self.monitor.queued_total.fetch_add(1, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Sends a change of a chunk to the database thread. The database thread counts
+    /// the changes it has written, so the chunk must already be marked with the
+    /// number of this change, one more than the changes sent before it.
+    fn send_change(&mut self, cmd: DatabaseCommand) -> Result<(), WorldError> {
+        self.send(cmd)?;
+        self.changes += 1;
+
+        Ok(())
     }
 
-    fn load_lights(&mut self, conn: &Connection) {
-        let mut cursor = conn.prepare(queries::LOAD_LIGHTS).unwrap().cursor();
+    fn record(&self, author: &Author, xyz: (i32, i32, i32), old: Value, new: Value) -> Result<(), WorldError> {
+        self.send(DatabaseCommand::AddHistory(AddHistoryCommand {
+            time: unix_time(),
+            author: author.clone(),
+            xyz,
+            old,
+            new,
+        }))
+    }
+
+    fn load_regions(&mut self, conn: &Connection) -> sqlite::Result<()> {
+        let mut cursor = conn.prepare(queries::LOAD_REGIONS)?.cursor();
+
+        while let Some(record) = cursor.next()? {
+            let owner = record[1].as_string().and_then(|s| s.parse().ok());
+            let corner = |i: usize| (record[i].as_integer().unwrap() as i32,
+                                     record[i + 1].as_integer().unwrap() as i32,
+                                     record[i + 2].as_integer().unwrap() as i32);
+
+            let mut region = Region::new(record[0].as_string().unwrap(), owner, corner(2), corner(5));
+            region.build = record[8].as_integer().unwrap() != 0;
+            region.sign = record[9].as_integer().unwrap() != 0;
+            region.light = record[10].as_integer().unwrap() != 0;
+
+            self.regions.insert(region);
+        }
+
+        let mut cursor = conn.prepare(queries::LOAD_REGION_TRUST)?.cursor();
 
-        while let Some(record) = cursor.next().unwrap() {
-            let (pq, xyz, w) = ((record[0].as_integer().unwrap() as i32,
-                                 record[1].as_integer().unwrap() as i32),
-                                (record[2].as_integer().unwrap() as i32,
-                                 record[3].as_integer().unwrap() as i32,
-                                 record[4].as_integer().unwrap() as i32),
-                                 record[5].as_integer().unwrap() as u8);
+        while let Some(record) = cursor.next()? {
+            let name = record[0].as_string().unwrap();
 
-            self.chunk_mgr.set_light(xyz, pq, Light(w));
+            if let (Some(region), Ok(ip)) = (self.regions.get_mut(name),
+                                             record[1].as_string().unwrap().parse()) {
+                region.trusted.insert(ip);
+            }
         }
+
+        Ok(())
     }
 }
 
@@ -316,116 +940,603 @@ struct SetLightCommand {
     pub light: Light,
 }
 
+struct AddHistoryCommand {
+    pub time: u64,
+    pub author: Author,
+    pub xyz: (i32, i32, i32),
+    pub old: Value,
+    pub new: Value,
+}
+
 enum DatabaseCommand {
     SetBlock(SetBlockCommand),
     SetSign(SetSignCommand),
     SetLight(SetLightCommand),
+    SetRegion(Region),
+    RemoveRegion(String),
+    AddHistory(AddHistoryCommand),
+    LoadHistory(HistoryQuery, mpsc::Sender<Result<Vec<Change>, WorldError>>),
+    SetReverted(Vec<i64>),
+
+    // Asks for everything queued to be written now. The answer tells whether it was.
+    Flush(mpsc::Sender<Result<(), WorldError>>),
+
+    // Commands that must be written in the same transaction.
+    Batch(Vec<DatabaseCommand>),
+}
+
+impl DatabaseCommand {
+    /// Returns the number of changes to chunks that the command makes.
+    fn chunk_writes(&self) -> u64 {
+        match *self {
+            DatabaseCommand::SetBlock(_) | DatabaseCommand::SetSign(_) | DatabaseCommand::SetLight(_) => 1,
+            DatabaseCommand::Batch(ref cmds) => cmds.iter().map(DatabaseCommand::chunk_writes).sum(),
+            _ => 0,
+        }
+    }
+
+    /// Describes the command for the log.
+    fn describe(&self) -> String {
+        match *self {
+            DatabaseCommand::SetBlock(ref c) => format!("block {} at {:?}", c.block.0, c.xyz),
+            DatabaseCommand::SetSign(ref c) => format!("sign on face {} at {:?}: {:?}", c.face, c.xyz, c.sign.0),
+            DatabaseCommand::SetLight(ref c) => format!("light {} at {:?}", c.light.0, c.xyz),
+            DatabaseCommand::SetRegion(ref r) => format!("region {}", r.name),
+            DatabaseCommand::RemoveRegion(ref name) => format!("removal of region {}", name),
+            DatabaseCommand::AddHistory(ref c) => format!("history of {:?} by {}", c.xyz, c.author.nick),
+            DatabaseCommand::LoadHistory(..) => "history query".to_string(),
+            DatabaseCommand::SetReverted(ref ids) => format!("{} reverted changes", ids.len()),
+            DatabaseCommand::Flush(_) => "flush".to_string(),
+            DatabaseCommand::Batch(ref cmds) => format!("batch of {} commands", cmds.len()),
+        }
+    }
 }
 
 struct DatabaseThread<'l> {
-    _conn: &'l Connection,
+    conn: &'l Connection,
     statements: PreparedStatements<'l>,
     rx: mpsc::Receiver<DatabaseCommand>,
+    written: Arc<AtomicU64>,
+    queued: Arc<AtomicUsize>,
+    committed: Arc<AtomicU64>,
+    flush_latency: Histogram,
+    degraded: Arc<AtomicBool>,
+    failed_flushes: u32,
+    config: DatabaseConfig,
 }
 
 impl<'l> DatabaseThread<'l> {
-    fn run(conn: Connection, rx: mpsc::Receiver<DatabaseCommand>) {
-        thread::spawn(move || {
+    /// Starts the database thread.
+    /// # Return value
+    /// Returns the thread, or an error if it could not prepare its statements.
+    fn run(conn: Connection,
+           rx: mpsc::Receiver<DatabaseCommand>,
+           written: Arc<AtomicU64>,
+           monitor: WorldMonitor,
+           config: DatabaseConfig) -> Result<thread::JoinHandle<()>, WorldError> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        monitor.database_running.store(true, Ordering::SeqCst);
+
+        let handle = thread::spawn(move || {
+            let _running = RunningGuard(monitor.database_running.clone());
+
+            let statements = match PreparedStatements::new(&conn) {
+                Ok(s) => s,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                },
+            };
+
+            let _ = ready_tx.send(Ok(()));
+
             let mut d = DatabaseThread {
-                _conn: &conn,
-                statements: PreparedStatements::new(&conn),
+                conn: &conn,
+                statements,
                 rx,
+                written,
+                queued: monitor.queued,
+                committed: monitor.committed,
+                flush_latency: monitor.flush_latency,
+                degraded: monitor.degraded,
+                failed_flushes: 0,
+                config,
             };
 
             d.database_thread();
         });
+
+        ready_rx.recv().map_err(|_| WorldError::Stopped)?.map_err(WorldError::from)?;
+
+        Ok(handle)
     }
 
+    /// Queues commands as they arrive, and applies them in one transaction when
+    /// the flush interval is over or enough commands are waiting.
+    ///
+    /// Commands that fail to apply stay queued, and are retried after every flush interval.
+    /// If that keeps failing, the commands are applied one by one, and those that
+    /// fail while others succeed are dropped.
     fn database_thread(&mut self) {
-        use std::time::Duration;
+        use std::sync::mpsc::RecvTimeoutError;
+        use std::time::{Duration, Instant};
+
+        let mut queue = Vec::new();
+        let mut last_flush = Instant::now();
 
         loop {
-            let mut changed = false;
+            let timeout = self.config.flush_interval
+                    .checked_sub(last_flush.elapsed())
+                    .unwrap_or_else(|| Duration::from_secs(0));
+
+            match self.rx.recv_timeout(timeout) {
+                Ok(DatabaseCommand::LoadHistory(q, tx)) => {
+                    // The answer should include every change made before the query.
+                    if !self.is_degraded() {
+                        self.flush(&mut queue);
+                    }
+
+                    let _ = tx.send(self.handle_load_history(&q).map_err(WorldError::from));
+                },
+                Ok(DatabaseCommand::Flush(tx)) => {
+                    self.flush(&mut queue);
+                    last_flush = Instant::now();
+
+                    let _ = tx.send(if self.is_degraded() { Err(WorldError::Unsaved) } else { Ok(()) });
+                },
+                Ok(cmd) => queue.push(cmd),
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush(&mut queue);
+                    break;
+                },
+            }
 
-            while let Ok(cmd) = self.rx.try_recv() {
-                changed = true;
+            let full = queue.len() >= self.config.flush_threshold && !self.is_degraded();
 
-                match cmd {
-                    DatabaseCommand::SetBlock(c) => self.handle_set_block(&c),
-                    DatabaseCommand::SetSign(c) => self.handle_set_sign(&c),
-                    DatabaseCommand::SetLight(c) => self.handle_set_light(&c),
-                }
+            if full || last_flush.elapsed() >= self.config.flush_interval {
+                self.flush(&mut queue);
+                last_flush = Instant::now();
             }
+        }
+    }
+
+    /// Applies the queued commands in one transaction. If that fails, the
+    /// transaction is rolled back and the commands are kept for another try.
+    /// After `MAX_FAILED_FLUSHES` failures in a row, the commands are isolated.
+    fn flush(&mut self, queue: &mut Vec<DatabaseCommand>) {
+        use std::time::Instant;
+
+        if queue.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
+        let result = self.apply(queue);
+        self.flush_latency.observe(start.elapsed());
+
+        match result {
+            Ok(chunk_writes) => {
+                let commands = queue.len();
+                self.queued.fetch_sub(commands, Ordering::SeqCst);
+                self.committed.fetch_add(commands as u64, Ordering::SeqCst);
+                queue.clear();
+
+                // Only now may the chunks changed by these commands be read back from the database.
+                self.written.fetch_add(chunk_writes, Ordering::SeqCst);
+
+                self.failed_flushes = 0;
+                if self.degraded.swap(false, Ordering::SeqCst) {
+                    info!("The world can be saved again.");
+                }
 
-            if changed {
-                //self.conn.execute(queries::COMMIT).unwrap();
-                println!("Saved the world.");
+                debug!(commands = commands; "Saved the world.");
+            },
+            Err(e) => {
+                let _ = self.conn.execute(queries::ROLLBACK);
+
+                self.degraded.store(true, Ordering::SeqCst);
+                self.failed_flushes += 1;
+
+                error!(waiting = queue.len(); "Can't save the world: {}", e);
+
+                if self.failed_flushes >= MAX_FAILED_FLUSHES {
+                    self.isolate(queue);
+                }
+            },
+        }
+    }
+
+    /// Applies the queued commands one by one, each in its own transaction, to
+    /// find the commands that can't be written.
+    ///
+    /// If some commands succeed, the ones that fail are dropped and reported, so
+    /// that they no longer hold back the others. If every command fails, the
+    /// database itself can't be written, so all of them are kept for another try.
+    fn isolate(&mut self, queue: &mut Vec<DatabaseCommand>) {
+        let mut failed = Vec::new();
+        let mut written = 0;
+
+        for (i, cmd) in queue.iter().enumerate() {
+            match self.apply(std::slice::from_ref(cmd)) {
+                Ok(_) => written += 1,
+                Err(e) => {
+                    let _ = self.conn.execute(queries::ROLLBACK);
+                    failed.push((i, e));
+                },
             }
+        }
 
-            thread::sleep(Duration::from_secs(5));
+        if written == 0 {
+            warn!(waiting = queue.len(); "No queued command can be saved, so they are all kept.");
+            self.failed_flushes = 0;
+            return;
+        }
+
+        for &(i, ref e) in &failed {
+            error!("Dropped a change that can't be saved: {}: {}", queue[i].describe(), e);
+        }
+
+        // The dropped commands count as written, since they won't ever be.
+        let chunk_writes = queue.iter().map(DatabaseCommand::chunk_writes).sum();
+        let commands = queue.len();
+
+        self.queued.fetch_sub(commands, Ordering::SeqCst);
+        self.committed.fetch_add(written, Ordering::SeqCst);
+        queue.clear();
+
+        self.written.fetch_add(chunk_writes, Ordering::SeqCst);
+
+        self.failed_flushes = 0;
+        if self.degraded.swap(false, Ordering::SeqCst) {
+            info!(dropped = failed.len(); "The world can be saved again.");
         }
     }
 
-    fn handle_set_block(&mut self, cmd: &SetBlockCommand) {
-        {
-            let s = self.statements.set_block();
+    /// Runs the commands in a transaction.
+    /// # Return value
+    /// Returns the number of commands that changed chunks.
+    fn apply(&mut self, queue: &[DatabaseCommand]) -> sqlite::Result<u64> {
+        self.conn.execute(queries::BEGIN)?;
+        let chunk_writes = self.run_commands(queue)?;
+        self.conn.execute(queries::COMMIT)?;
 
-            s.0.bind(1, cmd.pq.0 as i64).unwrap();
-            s.0.bind(2, cmd.pq.1 as i64).unwrap();
-            s.0.bind(3, cmd.xyz.0 as i64).unwrap();
-            s.0.bind(4, cmd.xyz.1 as i64).unwrap();
-            s.0.bind(5, cmd.xyz.2 as i64).unwrap();
-            s.0.bind(6, cmd.block.0 as i64).unwrap();
+        Ok(chunk_writes)
+    }
+
+    /// Runs commands within the current transaction.
+    /// # Return value
+    /// Returns the number of commands that changed chunks.
+    fn run_commands(&mut self, cmds: &[DatabaseCommand]) -> sqlite::Result<u64> {
+        let mut chunk_writes = 0;
+
+        for cmd in cmds {
+            match *cmd {
+                DatabaseCommand::SetBlock(ref c) => {
+                    self.handle_set_block(c)?;
+                    chunk_writes += 1;
+                },
+                DatabaseCommand::SetSign(ref c) => {
+                    self.handle_set_sign(c)?;
+                    chunk_writes += 1;
+                },
+                DatabaseCommand::SetLight(ref c) => {
+                    self.handle_set_light(c)?;
+                    chunk_writes += 1;
+                },
+                DatabaseCommand::SetRegion(ref r) => self.handle_set_region(r)?,
+                DatabaseCommand::RemoveRegion(ref n) => self.handle_remove_region(n)?,
+                DatabaseCommand::AddHistory(ref c) => self.handle_add_history(c)?,
+                DatabaseCommand::LoadHistory(ref q, ref tx) => {
+                    let _ = tx.send(self.handle_load_history(q).map_err(WorldError::from));
+                },
+                DatabaseCommand::SetReverted(ref ids) => self.handle_set_reverted(ids)?,
+                DatabaseCommand::Flush(_) => {},
+                DatabaseCommand::Batch(ref cmds) => chunk_writes += self.run_commands(cmds)?,
+            }
+        }
+
+        Ok(chunk_writes)
+    }
+
+    fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::SeqCst)
+    }
+
+    fn handle_set_block(&mut self, cmd: &SetBlockCommand) -> sqlite::Result<()> {
+        {
+            let mut s = self.statements.set_block();
+
+            s.0.bind(1, cmd.pq.0 as i64)?;
+            s.0.bind(2, cmd.pq.1 as i64)?;
+            s.0.bind(3, cmd.xyz.0 as i64)?;
+            s.0.bind(4, cmd.xyz.1 as i64)?;
+            s.0.bind(5, cmd.xyz.2 as i64)?;
+            s.0.bind(6, cmd.block.0 as i64)?;
+            s.execute()?;
         }
 
         if cmd.block.is_air() {
-            let s = self.statements.delete_signs();
+            let mut s = self.statements.delete_signs();
 
-            s.0.bind(1, cmd.xyz.0 as i64).unwrap();
-            s.0.bind(2, cmd.xyz.1 as i64).unwrap();
-            s.0.bind(3, cmd.xyz.2 as i64).unwrap();
+            s.0.bind(1, cmd.xyz.0 as i64)?;
+            s.0.bind(2, cmd.xyz.1 as i64)?;
+            s.0.bind(3, cmd.xyz.2 as i64)?;
+            s.execute()?;
         }
+
+        Ok(())
     }
 
-    fn handle_set_sign(&mut self, cmd: &SetSignCommand) {
-        if cmd.sign.0 == "" {
-            let s = self.statements.delete_individual_sign();
+    fn handle_set_sign(&mut self, cmd: &SetSignCommand) -> sqlite::Result<()> {
+        if cmd.sign.0.is_empty() {
+            let mut s = self.statements.delete_individual_sign();
 
-            s.0.bind(1, cmd.xyz.0 as i64).unwrap();
-            s.0.bind(2, cmd.xyz.1 as i64).unwrap();
-            s.0.bind(3, cmd.xyz.2 as i64).unwrap();
-            s.0.bind(4, cmd.face as i64).unwrap();
+            s.0.bind(1, cmd.xyz.0 as i64)?;
+            s.0.bind(2, cmd.xyz.1 as i64)?;
+            s.0.bind(3, cmd.xyz.2 as i64)?;
+            s.0.bind(4, cmd.face as i64)?;
+            s.execute()
         } else {
             use ::std::ops::Deref;
 
             let pq = (chunked(cmd.xyz.0), chunked(cmd.xyz.2));
-            let s = self.statements.set_sign();
+            let mut s = self.statements.set_sign();
+
+            s.0.bind(1, pq.0 as i64)?;
+            s.0.bind(2, pq.1 as i64)?;
+            s.0.bind(3, cmd.xyz.0 as i64)?;
+            s.0.bind(4, cmd.xyz.1 as i64)?;
+            s.0.bind(5, cmd.xyz.2 as i64)?;
+            s.0.bind(6, cmd.face as i64)?;
+            s.0.bind(7, cmd.sign.0.deref())?;
+            s.execute()
+        }
+    }
+
+    fn handle_set_light(&mut self, cmd: &SetLightCommand) -> sqlite::Result<()> {
+        let mut s = self.statements.set_light();
+
+        s.0.bind(1, cmd.pq.0 as i64)?;
+        s.0.bind(2, cmd.pq.1 as i64)?;
+        s.0.bind(3, cmd.xyz.0 as i64)?;
+        s.0.bind(4, cmd.xyz.1 as i64)?;
+        s.0.bind(5, cmd.xyz.2 as i64)?;
+        s.0.bind(6, cmd.light.0 as i64)?;
+        s.execute()
+    }
 
-            s.0.bind(1, pq.0 as i64).unwrap();
-            s.0.bind(2, pq.1 as i64).unwrap();
-            s.0.bind(3, cmd.xyz.0 as i64).unwrap();
-            s.0.bind(4, cmd.xyz.1 as i64).unwrap();
-            s.0.bind(5, cmd.xyz.2 as i64).unwrap();
-            s.0.bind(6, cmd.face as i64).unwrap();
-            s.0.bind(7, cmd.sign.0.deref()).unwrap();
+    fn handle_add_history(&mut self, cmd: &AddHistoryCommand) -> sqlite::Result<()> {
+        use ::std::ops::Deref;
+
+        let (kind, face, old_w, new_w, old_text, new_text) = match (&cmd.old, &cmd.new) {
+            (Value::Block(old), Value::Block(new)) =>
+                (0, 0, old.0 as i64, new.0 as i64, "", ""),
+            (Value::Sign(face, old), Value::Sign(_, new)) =>
+                (1, *face, 0, 0, old.0.deref(), new.0.deref()),
+            (Value::Light(old), Value::Light(new)) =>
+                (2, 0, old.0 as i64, new.0 as i64, "", ""),
+            _ => return Ok(()),
+        };
+
+        let mut s = self.statements.add_history();
+
+        s.0.bind(1, cmd.time as i64)?;
+        s.0.bind(2, cmd.author.ip.to_string().as_str())?;
+        s.0.bind(3, cmd.author.nick.as_str())?;
+        s.0.bind(4, kind as i64)?;
+        s.0.bind(5, cmd.xyz.0 as i64)?;
+        s.0.bind(6, cmd.xyz.1 as i64)?;
+        s.0.bind(7, cmd.xyz.2 as i64)?;
+        s.0.bind(8, face as i64)?;
+        s.0.bind(9, old_w)?;
+        s.0.bind(10, new_w)?;
+        s.0.bind(11, old_text)?;
+        s.0.bind(12, new_text)?;
+        s.execute()
+    }
+
+    fn handle_load_history(&mut self, query: &HistoryQuery) -> sqlite::Result<Vec<Change>> {
+        use sqlite::Value as SqlValue;
+
+        let mut sql = queries::LOAD_HISTORY.to_string();
+        let mut values = Vec::new();
+
+        if let Some(ip) = query.ip {
+            sql += " AND ip = ?";
+            values.push(SqlValue::String(ip.to_string()));
+        }
+
+        if let Some(since) = query.since {
+            sql += " AND time >= ?";
+            values.push(SqlValue::Integer(since as i64));
+        }
+
+        if let Some(xyz) = query.at {
+            sql += " AND x = ? AND y = ? AND z = ?";
+            values.push(SqlValue::Integer(xyz.0 as i64));
+            values.push(SqlValue::Integer(xyz.1 as i64));
+            values.push(SqlValue::Integer(xyz.2 as i64));
+        }
+
+        if let Some((xyz, r)) = query.near {
+            sql += " AND x BETWEEN ? AND ? AND y BETWEEN ? AND ? AND z BETWEEN ? AND ?";
+            for &n in &[xyz.0, xyz.1, xyz.2] {
+                values.push(SqlValue::Integer(n as i64 - r as i64));
+                values.push(SqlValue::Integer(n as i64 + r as i64));
+            }
+        }
+
+        if !query.include_reverted {
+            sql += " AND reverted = 0";
+        }
+
+        sql += " ORDER BY id DESC";
+
+        if let Some(limit) = query.limit {
+            sql += &format!(" LIMIT {}", limit);
+        }
+
+        let mut changes = Vec::new();
+        let mut cursor = self.conn.prepare(sql)?.cursor();
+        cursor.bind(&values)?;
+
+        while let Some(record) = cursor.next()? {
+            let int = |i: usize| record[i].as_integer().unwrap();
+            let text = |i: usize| record[i].as_string().unwrap().to_string();
+
+            let (old, new) = match int(4) {
+                0 => (Value::Block(Block(int(9) as i8)), Value::Block(Block(int(10) as i8))),
+                1 => (Value::Sign(int(8) as u8, Sign(text(11))), Value::Sign(int(8) as u8, Sign(text(12)))),
+                _ => (Value::Light(Light(int(9) as u8)), Value::Light(Light(int(10) as u8))),
+            };
+
+            let ip = match record[2].as_string().unwrap().parse() {
+                Ok(ip) => ip,
+                Err(_) => continue,
+            };
+
+            changes.push(Change {
+                id: int(0),
+                time: int(1) as u64,
+                author: Author {
+                    ip,
+                    nick: text(3),
+                },
+                xyz: (int(5) as i32, int(6) as i32, int(7) as i32),
+                old,
+                new,
+                reverted: int(13) != 0,
+            });
         }
+
+        Ok(changes)
     }
 
-    fn handle_set_light(&mut self, cmd: &SetLightCommand) {
-        let s = self.statements.set_light();
+    fn handle_set_reverted(&mut self, ids: &[i64]) -> sqlite::Result<()> {
+        for &id in ids {
+            let mut s = self.statements.set_history_reverted();
+
+            s.0.bind(1, id)?;
+            s.execute()?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_set_region(&mut self, region: &Region) -> sqlite::Result<()> {
+        {
+            let mut s = self.statements.set_region();
+
+            s.0.bind(1, region.name.as_str())?;
+            match region.owner {
+                Some(ip) => s.0.bind(2, ip.to_string().as_str())?,
+                None => s.0.bind(2, ())?,
+            }
+            s.0.bind(3, region.min.0 as i64)?;
+            s.0.bind(4, region.min.1 as i64)?;
+            s.0.bind(5, region.min.2 as i64)?;
+            s.0.bind(6, region.max.0 as i64)?;
+            s.0.bind(7, region.max.1 as i64)?;
+            s.0.bind(8, region.max.2 as i64)?;
+            s.0.bind(9, region.build as i64)?;
+            s.0.bind(10, region.sign as i64)?;
+            s.0.bind(11, region.light as i64)?;
+            s.execute()?;
+        }
+
+        {
+            let mut s = self.statements.delete_region_trust();
 
-        s.0.bind(1, cmd.pq.0 as i64).unwrap();
-        s.0.bind(2, cmd.pq.1 as i64).unwrap();
-        s.0.bind(3, cmd.xyz.0 as i64).unwrap();
-        s.0.bind(4, cmd.xyz.1 as i64).unwrap();
-        s.0.bind(5, cmd.xyz.2 as i64).unwrap();
-        s.0.bind(6, cmd.light.0 as i64).unwrap();
+            s.0.bind(1, region.name.as_str())?;
+            s.execute()?;
+        }
+
+        for ip in &region.trusted {
+            let mut s = self.statements.add_region_trust();
+
+            s.0.bind(1, region.name.as_str())?;
+            s.0.bind(2, ip.to_string().as_str())?;
+            s.execute()?;
+        }
+
+        Ok(())
     }
+
+    fn handle_remove_region(&mut self, name: &str) -> sqlite::Result<()> {
+        {
+            let mut s = self.statements.delete_region();
+
+            s.0.bind(1, name)?;
+            s.execute()?;
+        }
+
+        let mut s = self.statements.delete_region_trust();
+
+        s.0.bind(1, name)?;
+        s.execute()
+    }
+}
+
+fn non_empty<I: ExactSizeIterator>(items: I) -> Option<I> {
+    if items.len() == 0 {
+        None
+    } else {
+        Some(items)
+    }
+}
+
+/// Returns the lowest and the highest corner of the cuboid between two opposite corners.
+pub fn cuboid(a: (i32, i32, i32), b: (i32, i32, i32)) -> ((i32, i32, i32), (i32, i32, i32)) {
+    ((a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+     (a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)))
+}
+
+/// Returns true if players may edit blocks at this height.
+pub fn editable_height(y: i32) -> bool {
+    y > 0 && y < WORLD_HEIGHT
+}
+
+/// Returns true if these X and Z coordinates lie within `WORLD_RADIUS` of the origin.
+pub fn within_world(x: i32, z: i32) -> bool {
+    let inside = |n: i32| n > -WORLD_RADIUS && n < WORLD_RADIUS;
+
+    inside(x) && inside(z)
 }
 
 /// Return the chunk that a block falls in on one axis.
 pub fn chunked(n: i32) -> i32 {
-    (n as f32 / CHUNK_SIZE as f32).floor() as i32
+    n.div_euclid(CHUNK_SIZE as i32)
+}
+
+/// Return the chunks, other than its own, that also hold a copy of the block
+/// at these X and Z coordinates.
+pub fn border_chunks(x: i32, z: i32) -> Vec<(i32, i32)> {
+    // Craft overlaps chunks by 2 blocks.
+    // ______________
+    // |    #|#     |
+    // | 0  #|#  1  |
+    // |____#|#____ |
+    //
+    // A block that lies on this line is stored in the adjacent chunks as well.
+
+    let (p, q) = (chunked(x), chunked(z));
+    let mut chunks = Vec::new();
+
+    for dx in -1..2 {
+        for dz in -1..2 {
+            if      (dx == 0 && dz == 0) ||
+                    (dx != 0 && chunked(x + dx) == p) ||
+                    (dz != 0 && chunked(z + dz) == q) {
+                continue;
+            }
+
+            chunks.push((p + dx, q + dz));
+        }
+    }
+
+    chunks
 }
 
 struct PreparedStatements<'l> {
@@ -434,17 +1545,29 @@ struct PreparedStatements<'l> {
     delete_individual_sign: Statement<'l>,
     delete_signs: Statement<'l>,
     set_light: Statement<'l>,
+    set_region: Statement<'l>,
+    delete_region: Statement<'l>,
+    delete_region_trust: Statement<'l>,
+    add_region_trust: Statement<'l>,
+    add_history: Statement<'l>,
+    set_history_reverted: Statement<'l>,
 }
 
 impl<'l> PreparedStatements<'l> {
-    fn new(conn: &Connection) -> PreparedStatements {
-        PreparedStatements {
-            set_block: conn.prepare(queries::SET_BLOCK).unwrap(),
-            set_sign: conn.prepare(queries::SET_SIGN).unwrap(),
-            delete_individual_sign: conn.prepare(queries::DELETE_INDIVIDUAL_SIGN).unwrap(),
-            delete_signs: conn.prepare(queries::DELETE_SIGNS).unwrap(),
-            set_light: conn.prepare(queries::SET_LIGHT).unwrap(),
-        }
+    fn new(conn: &Connection) -> sqlite::Result<PreparedStatements<'_>> {
+        Ok(PreparedStatements {
+            set_block: conn.prepare(queries::SET_BLOCK)?,
+            set_sign: conn.prepare(queries::SET_SIGN)?,
+            delete_individual_sign: conn.prepare(queries::DELETE_INDIVIDUAL_SIGN)?,
+            delete_signs: conn.prepare(queries::DELETE_SIGNS)?,
+            set_light: conn.prepare(queries::SET_LIGHT)?,
+            set_region: conn.prepare(queries::SET_REGION)?,
+            delete_region: conn.prepare(queries::DELETE_REGION)?,
+            delete_region_trust: conn.prepare(queries::DELETE_REGION_TRUST)?,
+            add_region_trust: conn.prepare(queries::ADD_REGION_TRUST)?,
+            add_history: conn.prepare(queries::ADD_HISTORY)?,
+            set_history_reverted: conn.prepare(queries::SET_HISTORY_REVERTED)?,
+        })
     }
 
     fn set_block<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
@@ -466,16 +1589,47 @@ impl<'l> PreparedStatements<'l> {
     fn set_light<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
         StatementWrapper(&mut self.set_light)
     }
+
+    fn set_region<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.set_region)
+    }
+
+    fn delete_region<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.delete_region)
+    }
+
+    fn delete_region_trust<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.delete_region_trust)
+    }
+
+    fn add_region_trust<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.add_region_trust)
+    }
+
+    fn add_history<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.add_history)
+    }
+
+    fn set_history_reverted<'p>(&'p mut self) -> StatementWrapper<'l, 'p> {
+        StatementWrapper(&mut self.set_history_reverted)
+    }
 }
 
 struct StatementWrapper<'l, 'p>(&'p mut Statement<'l>) where 'l: 'p;
 
-impl<'l, 'p> Drop for StatementWrapper<'l, 'p> {
-    fn drop(&mut self) {
+impl<'l, 'p> StatementWrapper<'l, 'p> {
+    /// Runs the statement to completion.
+    fn execute(&mut self) -> sqlite::Result<()> {
         use ::sqlite::State;
 
-        while let Ok(State::Row) = self.0.next() {}
+        while let State::Row = self.0.next()? {}
 
+        Ok(())
+    }
+}
+
+impl<'l, 'p> Drop for StatementWrapper<'l, 'p> {
+    fn drop(&mut self) {
         let _ = self.0.reset();
     }
 }
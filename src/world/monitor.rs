@@ -0,0 +1,65 @@
+//! Lets other threads watch a world that the event thread owns.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use metrics::Histogram;
+
+/// A view of the state of a world that any thread may read, even while the
+/// world is busy.
+#[derive(Clone, Debug, Default)]
+pub struct WorldMonitor {
+    pub(super) loaded_chunks: Arc<AtomicUsize>,
+    pub(super) queued: Arc<AtomicUsize>,
+    pub(super) queued_total: Arc<AtomicU64>,
+    pub(super) committed: Arc<AtomicU64>,
+    pub(super) flush_latency: Histogram,
+    pub(super) database_running: Arc<AtomicBool>,
+    pub(super) degraded: Arc<AtomicBool>,
+}
+
+impl WorldMonitor {
+    /// Returns the number of chunks kept in memory.
+    pub fn loaded_chunks(&self) -> usize {
+        self.loaded_chunks.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of commands sent to the database thread that it has not written yet.
+    pub fn queued_commands(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of commands ever sent to the database thread.
+    pub fn queued_total(&self) -> u64 {
+        self.queued_total.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of commands the database thread has written.
+    pub fn committed(&self) -> u64 {
+        self.committed.load(Ordering::SeqCst)
+    }
+
+    /// Returns how long the database thread has taken to write its queue.
+    pub fn flush_latency(&self) -> &Histogram {
+        &self.flush_latency
+    }
+
+    /// Returns true while the database thread runs.
+    pub fn is_database_running(&self) -> bool {
+        self.database_running.load(Ordering::SeqCst)
+    }
+
+    /// Returns true while changes to the world can't be saved.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::SeqCst)
+    }
+}
+
+/// Marks the database thread as stopped when it is dropped, including when the
+/// thread panics.
+pub(super) struct RunningGuard(pub(super) Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
@@ -35,8 +35,14 @@ pub const INITIAL: &str =
     light (p, q, x, y, z);"
 ;
 
-/// Loads blocks from the database.
-pub const LOAD_BLOCKS: &str = "SELECT p, q, x, y, z, w FROM block;";
+/// Removes the copies of blocks that earlier versions of this server stored in
+/// every chunk overlapping a block. Only the row in the chunk the block falls in is kept.
+/// Chunks are 32 blocks wide, so a coordinate's chunk is the coordinate shifted right by 5 bits.
+pub const COLLAPSE_BORDER_COPIES: &str =
+    "DELETE FROM block WHERE p != (x >> 5) OR q != (z >> 5);";
+
+/// Loads the blocks of a chunk from the database.
+pub const LOAD_BLOCKS: &str = "SELECT x, y, z, w FROM block WHERE p = ? AND q = ?;";
 
 /// Sets a block.
 /* pub const SET_BLOCK: &str = "INSERT OR REPLACE INTO block (p, q, x, y, z, w) VALUES "; */
@@ -44,8 +50,15 @@ pub const SET_BLOCK: &str =
     "INSERT OR REPLACE INTO block (p, q, x, y, z, w) VALUES \
     (?, ?, ?, ?, ?, ?);";
 
-/// Loads signs from the database.
-pub const LOAD_SIGNS: &str = "SELECT p, q, x, y, z, face, text FROM sign;";
+/// Removes signs without text, and signs on blocks that were mined to air, which
+/// earlier versions of this server kept.
+pub const DELETE_STRAY_SIGNS: &str =
+    "DELETE FROM sign WHERE text = '' OR EXISTS \
+    (SELECT 1 FROM block b WHERE b.p = sign.p AND b.q = sign.q AND \
+    b.x = sign.x AND b.y = sign.y AND b.z = sign.z AND b.w = 0);";
+
+/// Loads the signs of a chunk from the database.
+pub const LOAD_SIGNS: &str = "SELECT x, y, z, face, text FROM sign WHERE p = ? AND q = ?;";
 
 /// Sets a sign.
 /* pub const SET_SIGN: &str = "INSERT OR REPLACE INTO sign (p, q, x, y, z, face, text) VALUES "; */
@@ -60,14 +73,186 @@ pub const DELETE_INDIVIDUAL_SIGN: &str = "DELETE FROM sign WHERE x = ? and y = ?
 /* pub const DELETE_SIGN: &str = "DELETE FROM sign WHERE "; */
 pub const DELETE_SIGNS: &str = "DELETE FROM sign WHERE x = ? AND y = ? AND z = ?";
 
-/// Loads lights from the database.
-pub const LOAD_LIGHTS: &str = "SELECT p, q, x, y, z, w FROM light;";
+/// Loads the lights of a chunk from the database.
+pub const LOAD_LIGHTS: &str = "SELECT x, y, z, w FROM light WHERE p = ? AND q = ?;";
 
 /// Sets a light.
 pub const SET_LIGHT: &str =
     "INSERT OR REPLACE INTO light (p, q, x, y, z, w) VALUES \
     (?, ?, ?, ?, ?, ?);";
 
-/* /// Commit the database transactions.
+/// Begin a database transaction.
+pub const BEGIN: &str = "BEGIN;";
+
+/// Commit the database transactions.
 pub const COMMIT: &str = "COMMIT;";
-*/
+
+/// Abandon the database transaction.
+pub const ROLLBACK: &str = "ROLLBACK;";
+
+// The following queries are specific to this server.
+
+/// Lets the database be read while it is written, and makes commits cheaper.
+pub const JOURNAL_MODE: &str = "PRAGMA journal_mode = WAL;";
+
+/// Sets up the tables for protected regions.
+pub const INITIAL_REGIONS: &str =
+    "CREATE TABLE IF NOT EXISTS region (\
+    name TEXT NOT NULL, \
+    owner TEXT, \
+    x1 INT NOT NULL, \
+    y1 INT NOT NULL, \
+    z1 INT NOT NULL, \
+    x2 INT NOT NULL, \
+    y2 INT NOT NULL, \
+    z2 INT NOT NULL, \
+    build INT NOT NULL, \
+    sign INT NOT NULL, \
+    light INT NOT NULL); \
+    CREATE UNIQUE INDEX IF NOT EXISTS region_name_idx ON \
+    region (name); \
+    CREATE TABLE IF NOT EXISTS region_trust (\
+    region TEXT NOT NULL, \
+    ip TEXT NOT NULL); \
+    CREATE UNIQUE INDEX IF NOT EXISTS region_trust_idx ON \
+    region_trust (region, ip);"
+;
+
+/// Loads regions from the database.
+pub const LOAD_REGIONS: &str =
+    "SELECT name, owner, x1, y1, z1, x2, y2, z2, build, sign, light FROM region;";
+
+/// Loads the trusted players of all regions from the database.
+pub const LOAD_REGION_TRUST: &str = "SELECT region, ip FROM region_trust;";
+
+/// Sets a region.
+pub const SET_REGION: &str =
+    "INSERT OR REPLACE INTO region (name, owner, x1, y1, z1, x2, y2, z2, build, sign, light) VALUES \
+    (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+
+/// Deletes a region.
+pub const DELETE_REGION: &str = "DELETE FROM region WHERE name = ?;";
+
+/// Deletes all trusted players of a region.
+pub const DELETE_REGION_TRUST: &str = "DELETE FROM region_trust WHERE region = ?;";
+
+/// Trusts a player in a region.
+pub const ADD_REGION_TRUST: &str =
+    "INSERT OR REPLACE INTO region_trust (region, ip) VALUES (?, ?);";
+
+/// Sets up the table recording the history of changes.
+///
+/// `kind` is 0 for blocks, 1 for signs and 2 for lights. Blocks and lights store
+/// their values in `old_w` and `new_w`, signs store their face in `face` and their
+/// text in `old_text` and `new_text`.
+pub const INITIAL_HISTORY: &str =
+    "CREATE TABLE IF NOT EXISTS history (\
+    id INTEGER PRIMARY KEY, \
+    time INT NOT NULL, \
+    ip TEXT NOT NULL, \
+    nick TEXT NOT NULL, \
+    kind INT NOT NULL, \
+    x INT NOT NULL, \
+    y INT NOT NULL, \
+    z INT NOT NULL, \
+    face INT NOT NULL, \
+    old_w INT NOT NULL, \
+    new_w INT NOT NULL, \
+    old_text TEXT NOT NULL, \
+    new_text TEXT NOT NULL, \
+    reverted INT NOT NULL DEFAULT 0); \
+    CREATE INDEX IF NOT EXISTS history_xyz_idx ON \
+    history (x, y, z); \
+    CREATE INDEX IF NOT EXISTS history_ip_time_idx ON \
+    history (ip, time);"
+;
+
+/// Records a change.
+pub const ADD_HISTORY: &str =
+    "INSERT INTO history (time, ip, nick, kind, x, y, z, face, old_w, new_w, old_text, new_text) VALUES \
+    (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);";
+
+/// Selects changes. The conditions are appended when a query is run.
+pub const LOAD_HISTORY: &str =
+    "SELECT id, time, ip, nick, kind, x, y, z, face, old_w, new_w, old_text, new_text, reverted \
+    FROM history WHERE 1";
+
+/// Marks a change as undone.
+pub const SET_HISTORY_REVERTED: &str = "UPDATE history SET reverted = 1 WHERE id = ?;";
+
+/// Sets up the table of facts about the database itself, such as its schema version.
+pub const INITIAL_METADATA: &str =
+    "CREATE TABLE IF NOT EXISTS metadata (\
+    key TEXT PRIMARY KEY, \
+    value TEXT NOT NULL);"
+;
+
+/// Finds the metadata table, which databases at schema version 0 may lack.
+pub const HAS_METADATA: &str = "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'metadata';";
+
+/// Checks the database for damage. Returns the single row `ok` if none was found.
+pub const QUICK_CHECK: &str = "PRAGMA quick_check;";
+
+/// Loads a fact about the database.
+pub const LOAD_METADATA: &str = "SELECT value FROM metadata WHERE key = ?;";
+
+/// Sets a fact about the database.
+pub const SET_METADATA: &str = "INSERT OR REPLACE INTO metadata (key, value) VALUES (?, ?);";
+
+/// Opens a Craft database alongside the world, to import from.
+pub const ATTACH_CRAFT: &str = "ATTACH DATABASE ? AS craft;";
+
+/// Closes the Craft database opened by `ATTACH_CRAFT`.
+pub const DETACH_CRAFT: &str = "DETACH DATABASE craft;";
+
+/// Holds the chunks that must not be touched by an import.
+pub const INITIAL_IMPORT_SKIP: &str =
+    "CREATE TEMP TABLE IF NOT EXISTS import_skip (\
+    p INT NOT NULL, \
+    q INT NOT NULL, \
+    PRIMARY KEY (p, q)); \
+    DELETE FROM import_skip;"
+;
+
+/// Marks every chunk that already holds something as not to be touched by an import.
+pub const SKIP_OCCUPIED_CHUNKS: &str =
+    "INSERT OR IGNORE INTO import_skip (p, q) \
+    SELECT p, q FROM main.block UNION \
+    SELECT p, q FROM main.sign UNION \
+    SELECT p, q FROM main.light;"
+;
+
+/// Counts the blocks in a Craft database, leaving out the copies it keeps in neighboring chunks.
+pub const COUNT_CRAFT_BLOCKS: &str = "SELECT COUNT(*) FROM craft.block WHERE p = x >> 5 AND q = z >> 5;";
+
+/// Counts the signs in a Craft database.
+pub const COUNT_CRAFT_SIGNS: &str = "SELECT COUNT(*) FROM craft.sign;";
+
+/// Counts the lights in a Craft database.
+pub const COUNT_CRAFT_LIGHTS: &str = "SELECT COUNT(*) FROM craft.light;";
+
+/// Counts the rows changed by the last statement.
+pub const CHANGES: &str = "SELECT changes();";
+
+/// Copies the blocks of a Craft database into the world, moved by an X and Z offset.
+/// Rows are placed in the chunks they fall in after moving, and chunks in `import_skip` are left out.
+/// The insert, with its conflict clause, is prepended when the query is run.
+pub const IMPORT_BLOCKS: &str =
+    " INTO main.block (p, q, x, y, z, w) \
+    SELECT (c.x + ?1) >> 5, (c.z + ?2) >> 5, c.x + ?1, c.y, c.z + ?2, c.w FROM craft.block c \
+    WHERE c.p = c.x >> 5 AND c.q = c.z >> 5 AND NOT EXISTS \
+    (SELECT 1 FROM import_skip s WHERE s.p = (c.x + ?1) >> 5 AND s.q = (c.z + ?2) >> 5);";
+
+/// Like `IMPORT_BLOCKS`, but for signs.
+pub const IMPORT_SIGNS: &str =
+    " INTO main.sign (p, q, x, y, z, face, text) \
+    SELECT (c.x + ?1) >> 5, (c.z + ?2) >> 5, c.x + ?1, c.y, c.z + ?2, c.face, c.text FROM craft.sign c \
+    WHERE NOT EXISTS \
+    (SELECT 1 FROM import_skip s WHERE s.p = (c.x + ?1) >> 5 AND s.q = (c.z + ?2) >> 5);";
+
+/// Like `IMPORT_BLOCKS`, but for lights.
+pub const IMPORT_LIGHTS: &str =
+    " INTO main.light (p, q, x, y, z, w) \
+    SELECT (c.x + ?1) >> 5, (c.z + ?2) >> 5, c.x + ?1, c.y, c.z + ?2, c.w FROM craft.light c \
+    WHERE NOT EXISTS \
+    (SELECT 1 FROM import_skip s WHERE s.p = (c.x + ?1) >> 5 AND s.q = (c.z + ?2) >> 5);";
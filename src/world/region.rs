@@ -0,0 +1,182 @@
+//! Protected regions of the world. Regions are either land claims owned by
+//! a player, or server regions defined by admins.
+
+use std::collections::{hash_map, HashMap, HashSet};
+use std::net::IpAddr;
+use super::cuboid;
+
+/// The kinds of edits that a region can allow or deny.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flag {
+    Build,
+    Sign,
+    Light,
+}
+
+impl Flag {
+    /// Parses a flag by its name, as used in commands.
+    pub fn from_name(name: &str) -> Option<Flag> {
+        match name {
+            "build" => Some(Flag::Build),
+            "sign" => Some(Flag::Sign),
+            "light" => Some(Flag::Light),
+            _ => None,
+        }
+    }
+}
+
+/// A cuboid part of the world with restricted editing.
+#[derive(Clone, Debug)]
+pub struct Region {
+    /// The unique name of the region.
+    pub name: String,
+
+    /// The player who claimed the region, or `None` for server regions.
+    pub owner: Option<IpAddr>,
+
+    /// The lowest corner of the region, inclusive.
+    pub min: (i32, i32, i32),
+
+    /// The highest corner of the region, inclusive.
+    pub max: (i32, i32, i32),
+
+    /// Whether everyone may build in the region.
+    pub build: bool,
+
+    /// Whether everyone may place signs in the region.
+    pub sign: bool,
+
+    /// Whether everyone may toggle lights in the region.
+    pub light: bool,
+
+    /// The players the owner trusts to edit the region.
+    pub trusted: HashSet<IpAddr>,
+}
+
+impl Region {
+    /// Creates a region from any two opposite corners. Nobody but the owner may edit it.
+    pub fn new(name: &str,
+               owner: Option<IpAddr>,
+               a: (i32, i32, i32),
+               b: (i32, i32, i32)) -> Region {
+        let (min, max) = cuboid(a, b);
+
+        Region {
+            name: name.to_string(),
+            owner,
+            min,
+            max,
+            build: false,
+            sign: false,
+            light: false,
+            trusted: HashSet::new(),
+        }
+    }
+
+    /// Returns true if the position lies inside the region.
+    pub fn contains(&self, xyz: (i32, i32, i32)) -> bool {
+        xyz.0 >= self.min.0 && xyz.0 <= self.max.0 &&
+        xyz.1 >= self.min.1 && xyz.1 <= self.max.1 &&
+        xyz.2 >= self.min.2 && xyz.2 <= self.max.2
+    }
+
+    /// Returns true if the two regions share at least one block.
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.min.0 <= other.max.0 && self.max.0 >= other.min.0 &&
+        self.min.1 <= other.max.1 && self.max.1 >= other.min.1 &&
+        self.min.2 <= other.max.2 && self.max.2 >= other.min.2
+    }
+
+    /// Returns the number of blocks in the region.
+    /// The sides are measured in i64, and the product saturates, so that huge
+    /// regions can't overflow.
+    pub fn volume(&self) -> i64 {
+        let side = |min: i32, max: i32| max as i64 - min as i64 + 1;
+
+        side(self.min.0, self.max.0).saturating_mul(side(self.min.1, self.max.1))
+                                    .saturating_mul(side(self.min.2, self.max.2))
+    }
+
+    /// Returns the value of a flag.
+    pub fn flag(&self, flag: Flag) -> bool {
+        match flag {
+            Flag::Build => self.build,
+            Flag::Sign => self.sign,
+            Flag::Light => self.light,
+        }
+    }
+
+    /// Sets the value of a flag.
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        match flag {
+            Flag::Build => self.build = value,
+            Flag::Sign => self.sign = value,
+            Flag::Light => self.light = value,
+        }
+    }
+
+    /// Returns true if the player at this address may make this kind of edit in the region.
+    /// Roles are not taken into account.
+    pub fn allows(&self, ip: &IpAddr, flag: Flag) -> bool {
+        self.flag(flag) || self.owner.as_ref() == Some(ip) || self.trusted.contains(ip)
+    }
+}
+
+/// Keeps all regions of the world in memory.
+#[derive(Debug)]
+pub struct RegionManager {
+    regions: HashMap<String, Region>,
+}
+
+impl RegionManager {
+    /// Creates an empty region manager.
+    pub fn new() -> RegionManager {
+        RegionManager {
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Returns the region with the given name.
+    pub fn get(&self, name: &str) -> Option<&Region> {
+        self.regions.get(name)
+    }
+
+    /// Iterates over all regions.
+    pub fn iter<'a>(&'a self) -> hash_map::Values<'a, String, Region> {
+        self.regions.values()
+    }
+
+    /// Returns the regions owned by a player.
+    pub fn owned_by(&self, ip: &IpAddr) -> Vec<&Region> {
+        self.regions.values().filter(|r| r.owner.as_ref() == Some(ip)).collect()
+    }
+
+    /// Returns the regions that contain the position.
+    pub fn at(&self, xyz: (i32, i32, i32)) -> Vec<&Region> {
+        self.regions.values().filter(|r| r.contains(xyz)).collect()
+    }
+
+    /// Returns true if every region at the position allows the player this kind of edit.
+    /// Roles are not taken into account.
+    pub fn allows(&self, ip: &IpAddr, xyz: (i32, i32, i32), flag: Flag) -> bool {
+        self.regions.values().all(|r| !r.contains(xyz) || r.allows(ip, flag))
+    }
+
+    pub(super) fn get_mut(&mut self, name: &str) -> Option<&mut Region> {
+        self.regions.get_mut(name)
+    }
+
+    pub(super) fn insert(&mut self, region: Region) {
+        self.regions.insert(region.name.clone(), region);
+    }
+
+    pub(super) fn remove(&mut self, name: &str) -> Option<Region> {
+        self.regions.remove(name)
+    }
+}
+
+impl Default for RegionManager {
+    fn default() -> RegionManager {
+        RegionManager::new()
+    }
+}
@@ -0,0 +1,125 @@
+//! The registry of block types. It mirrors the item table of the Craft client.
+//! See `item.h` and `item.c` here: https://github.com/fogleman/Craft/tree/master/src
+
+/// The properties of a kind of block.
+#[derive(Debug)]
+pub struct BlockType {
+    /// The ID of the block, as sent in `B` messages.
+    pub id: i8,
+
+    /// The name of the block, as used in commands.
+    pub name: &'static str,
+
+    /// Whether the block is a plant, which is drawn as crossed sprites.
+    pub plant: bool,
+
+    /// Whether players collide with the block.
+    pub obstacle: bool,
+
+    /// Whether the blocks behind this block can be seen.
+    pub transparent: bool,
+
+    /// Whether players can mine the block.
+    pub destructable: bool,
+
+    /// Whether players can place the block.
+    pub placeable: bool,
+}
+
+macro_rules! solid {
+    ($id:expr, $name:expr) => {
+        BlockType { id: $id, name: $name, plant: false, obstacle: true,
+                    transparent: false, destructable: true, placeable: true }
+    };
+}
+
+macro_rules! plant {
+    ($id:expr, $name:expr) => {
+        BlockType { id: $id, name: $name, plant: true, obstacle: false,
+                    transparent: true, destructable: true, placeable: true }
+    };
+}
+
+static BLOCK_TYPES: [BlockType; 56] = [
+    BlockType { id: 0, name: "air", plant: false, obstacle: false,
+                transparent: true, destructable: false, placeable: false },
+    solid!(1, "grass"),
+    solid!(2, "sand"),
+    solid!(3, "stone"),
+    solid!(4, "brick"),
+    solid!(5, "wood"),
+    solid!(6, "cement"),
+    solid!(7, "dirt"),
+    solid!(8, "plank"),
+    solid!(9, "snow"),
+    BlockType { id: 10, name: "glass", plant: false, obstacle: true,
+                transparent: true, destructable: true, placeable: true },
+    solid!(11, "cobble"),
+    solid!(12, "light_stone"),
+    solid!(13, "dark_stone"),
+    solid!(14, "chest"),
+    BlockType { id: 15, name: "leaves", plant: false, obstacle: true,
+                transparent: true, destructable: true, placeable: true },
+    BlockType { id: 16, name: "cloud", plant: false, obstacle: false,
+                transparent: false, destructable: false, placeable: false },
+    plant!(17, "tall_grass"),
+    plant!(18, "yellow_flower"),
+    plant!(19, "red_flower"),
+    plant!(20, "purple_flower"),
+    plant!(21, "sun_flower"),
+    plant!(22, "white_flower"),
+    plant!(23, "blue_flower"),
+    solid!(32, "color_00"),
+    solid!(33, "color_01"),
+    solid!(34, "color_02"),
+    solid!(35, "color_03"),
+    solid!(36, "color_04"),
+    solid!(37, "color_05"),
+    solid!(38, "color_06"),
+    solid!(39, "color_07"),
+    solid!(40, "color_08"),
+    solid!(41, "color_09"),
+    solid!(42, "color_10"),
+    solid!(43, "color_11"),
+    solid!(44, "color_12"),
+    solid!(45, "color_13"),
+    solid!(46, "color_14"),
+    solid!(47, "color_15"),
+    solid!(48, "color_16"),
+    solid!(49, "color_17"),
+    solid!(50, "color_18"),
+    solid!(51, "color_19"),
+    solid!(52, "color_20"),
+    solid!(53, "color_21"),
+    solid!(54, "color_22"),
+    solid!(55, "color_23"),
+    solid!(56, "color_24"),
+    solid!(57, "color_25"),
+    solid!(58, "color_26"),
+    solid!(59, "color_27"),
+    solid!(60, "color_28"),
+    solid!(61, "color_29"),
+    solid!(62, "color_30"),
+    solid!(63, "color_31"),
+];
+
+/// Looks up a block type by its ID.
+/// # Return value
+/// Returns None for IDs that Craft does not know, including the negative IDs
+/// used for the copies of blocks on chunk borders.
+pub fn block_type(id: i8) -> Option<&'static BlockType> {
+    BLOCK_TYPES.iter().find(|t| t.id == id)
+}
+
+/// Looks up a block type by its name, or by its ID written as a number.
+pub fn block_type_by_name(name: &str) -> Option<&'static BlockType> {
+    match name.parse() {
+        Ok(id) => block_type(id),
+        Err(_) => BLOCK_TYPES.iter().find(|t| t.name == name),
+    }
+}
+
+/// Iterates over all block types.
+pub fn block_types() -> ::std::slice::Iter<'static, BlockType> {
+    BLOCK_TYPES.iter()
+}
@@ -0,0 +1,270 @@
+//! Dense, palette-compressed storage for the blocks and lights of a chunk.
+//!
+//! A chunk is a column of sections, each `CHUNK_SIZE` wide, `SECTION_HEIGHT` high and
+//! `CHUNK_SIZE` deep. A section stores the distinct values it contains in a palette, and
+//! every cell as an index into that palette, packed into as few bits as the palette allows.
+//! Sections that only contain the default value are not stored at all.
+
+use std::collections::BTreeMap;
+use std::mem;
+use std::ops::RangeInclusive;
+use super::CHUNK_SIZE;
+
+/// The height of a section in blocks.
+pub const SECTION_HEIGHT: i32 = 16;
+
+const SIZE: usize = CHUNK_SIZE as usize;
+const VOLUME: usize = SIZE * SIZE * SECTION_HEIGHT as usize;
+
+/// A `CHUNK_SIZE` × `SECTION_HEIGHT` × `CHUNK_SIZE` box of values.
+///
+/// The first palette entry is always the default value, so a packed word of zeros
+/// holds nothing but default values.
+#[derive(Clone, Debug)]
+pub struct Section<T> {
+    palette: Vec<T>,
+    bits: usize,
+    data: Vec<u64>,
+    count: usize,
+}
+
+impl<T: Copy + PartialEq + Default> Section<T> {
+    /// Creates a section filled with the default value.
+    pub fn new() -> Section<T> {
+        Section {
+            palette: vec![T::default()],
+            bits: 0,
+            data: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Returns the value at a local position. The Y coordinate is relative to the section.
+    pub fn get(&self, x: u8, y: u8, z: u8) -> T {
+        self.palette[self.read(Self::index(x, y, z))]
+    }
+
+    /// Sets the value at a local position. The Y coordinate is relative to the section.
+    pub fn set(&mut self, x: u8, y: u8, z: u8, value: T) {
+        let i = Self::index(x, y, z);
+        let old = self.palette[self.read(i)];
+
+        if old == value {
+            return;
+        }
+
+        let entry = match self.palette.iter().position(|v| *v == value) {
+            Some(entry) => entry,
+            None => {
+                self.palette.push(value);
+                self.palette.len() - 1
+            },
+        };
+
+        if entry >= 1 << self.bits {
+            self.grow();
+        }
+
+        self.write(i, entry);
+
+        if old == T::default() {
+            self.count += 1;
+        } else if value == T::default() {
+            self.count -= 1;
+        }
+    }
+
+    /// Returns true if the section holds nothing but the default value.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of bytes this section uses on the heap.
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * mem::size_of::<T>() + self.data.capacity() * mem::size_of::<u64>()
+    }
+
+    /// Calls `f` with the local position and value of every cell that does not hold
+    /// the default value, in order of Y, then Z, then X.
+    pub fn for_each<F: FnMut((u8, u8, u8), T)>(&self, f: F) {
+        self.for_each_in(0..=(SIZE - 1) as u8, 0..=(SIZE - 1) as u8, f);
+    }
+
+    /// Like `for_each`, but only visits cells within the given X and Z ranges.
+    pub fn for_each_in<F>(&self, xs: RangeInclusive<u8>, zs: RangeInclusive<u8>, mut f: F)
+            where F: FnMut((u8, u8, u8), T) {
+        if self.is_empty() {
+            return;
+        }
+
+        let per_word = (64 / self.bits) as u8;
+
+        for y in 0..SECTION_HEIGHT as u8 {
+            for z in zs.clone() {
+                let mut x = *xs.start();
+
+                while x <= *xs.end() {
+                    let i = Self::index(x, y, z);
+                    let bit = i * self.bits;
+
+                    // Skip whole words of default values.
+                    if bit.is_multiple_of(64) && self.data[bit / 64] == 0 {
+                        x = x.saturating_add(per_word);
+                        continue;
+                    }
+
+                    let entry = self.read(i);
+                    if entry != 0 {
+                        f((x, y, z), self.palette[entry]);
+                    }
+
+                    x += 1;
+                }
+            }
+        }
+    }
+
+    fn index(x: u8, y: u8, z: u8) -> usize {
+        (y as usize * SIZE + z as usize) * SIZE + x as usize
+    }
+
+    fn read(&self, i: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let bit = i * self.bits;
+
+        ((self.data[bit / 64] >> (bit % 64)) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write(&mut self, i: usize, entry: usize) {
+        let bit = i * self.bits;
+        let shift = bit % 64;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[bit / 64];
+
+        *word = (*word & !mask) | ((entry as u64) << shift);
+    }
+
+    /// Doubles the bits per cell, keeping them a divisor of 64 so that no cell
+    /// straddles two words.
+    fn grow(&mut self) {
+        let entries: Vec<usize> = (0..VOLUME).map(|i| self.read(i)).collect();
+
+        self.bits = if self.bits == 0 { 1 } else { self.bits * 2 };
+        self.data = vec![0; VOLUME * self.bits / 64];
+
+        for (i, entry) in entries.into_iter().enumerate() {
+            if entry != 0 {
+                self.write(i, entry);
+            }
+        }
+    }
+}
+
+impl<T: Copy + PartialEq + Default> Default for Section<T> {
+    fn default() -> Section<T> {
+        Section::new()
+    }
+}
+
+/// A full-height column of sections. Local X and Z coordinates range from 0 to
+/// `CHUNK_SIZE - 1`, and Y may be any height.
+#[derive(Clone, Debug)]
+pub struct Column<T> {
+    sections: BTreeMap<i32, Section<T>>,
+}
+
+impl<T: Copy + PartialEq + Default> Column<T> {
+    /// Creates a column filled with the default value.
+    pub fn new() -> Column<T> {
+        Column {
+            sections: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the value at a local position.
+    pub fn get(&self, x: u8, y: i32, z: u8) -> T {
+        let (section, y) = Self::split(y);
+
+        match self.sections.get(&section) {
+            Some(s) => s.get(x, y, z),
+            None => T::default(),
+        }
+    }
+
+    /// Sets the value at a local position.
+    pub fn set(&mut self, x: u8, y: i32, z: u8, value: T) {
+        let (section, y) = Self::split(y);
+
+        if value == T::default() {
+            let empty = match self.sections.get_mut(&section) {
+                Some(s) => {
+                    s.set(x, y, z, value);
+                    s.is_empty()
+                },
+                None => false,
+            };
+
+            if empty {
+                self.sections.remove(&section);
+            }
+        } else {
+            self.sections.entry(section).or_default().set(x, y, z, value);
+        }
+    }
+
+    /// Returns true if the column holds nothing but the default value.
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// Returns the number of bytes this column uses on the heap, not counting
+    /// the overhead of the map holding its sections.
+    pub fn heap_size(&self) -> usize {
+        self.sections.values().map(|s| mem::size_of::<Section<T>>() + s.heap_size()).sum()
+    }
+
+    /// Calls `f` with the local position and value of every cell that does not hold
+    /// the default value, from the bottom up.
+    pub fn for_each<F: FnMut((u8, i32, u8), T)>(&self, f: F) {
+        self.for_each_in(0..=(SIZE - 1) as u8, 0..=(SIZE - 1) as u8, f);
+    }
+
+    /// Like `for_each`, but only visits cells within the given X and Z ranges.
+    pub fn for_each_in<F>(&self, xs: RangeInclusive<u8>, zs: RangeInclusive<u8>, mut f: F)
+            where F: FnMut((u8, i32, u8), T) {
+        for (section, s) in &self.sections {
+            let base = section * SECTION_HEIGHT;
+
+            s.for_each_in(xs.clone(), zs.clone(), |(x, y, z), v| f((x, base + y as i32, z), v));
+        }
+    }
+
+    /// Returns the height of the highest cell in a vertical line whose value
+    /// is not the default and matches `f`.
+    pub fn highest<F: Fn(T) -> bool>(&self, x: u8, z: u8, f: F) -> Option<i32> {
+        for (section, s) in self.sections.iter().rev() {
+            for y in (0..SECTION_HEIGHT as u8).rev() {
+                let value = s.get(x, y, z);
+
+                if value != T::default() && f(value) {
+                    return Some(section * SECTION_HEIGHT + y as i32);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn split(y: i32) -> (i32, u8) {
+        (y.div_euclid(SECTION_HEIGHT), y.rem_euclid(SECTION_HEIGHT) as u8)
+    }
+}
+
+impl<T: Copy + PartialEq + Default> Default for Column<T> {
+    fn default() -> Column<T> {
+        Column::new()
+    }
+}